@@ -0,0 +1,9 @@
+CREATE TABLE newsletter_issues (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    -- timestamp with time zone
+    published_at TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE issue_delivery_queue (
+    id INTEGER PRIMARY KEY,
+    newsletter_issue_id TEXT NOT NULL REFERENCES newsletter_issues (uuid),
+    subscriber_email TEXT NOT NULL,
+    UNIQUE (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,12 @@
+CREATE TABLE idempotency (
+    user_id TEXT NOT NULL REFERENCES users (uuid),
+    idempotency_key TEXT NOT NULL,
+    -- the response columns stay NULL while the first request is in flight
+    response_status_code INTEGER NULL,
+    -- JSON array of {"name": .., "value": [..bytes]}
+    response_headers TEXT NULL,
+    response_body BLOB NULL,
+    -- timestamp with time zone
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, idempotency_key)
+);
@@ -0,0 +1,6 @@
+-- `status` is either 'pending' or 'dead_lettered'
+ALTER TABLE issue_delivery_queue ADD COLUMN status TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE issue_delivery_queue ADD COLUMN n_retries INTEGER NOT NULL DEFAULT 0;
+-- timestamp with time zone, the task is not picked up before that
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after TEXT NOT NULL DEFAULT '1970-01-01 00:00:00 UTC';
+ALTER TABLE issue_delivery_queue ADD COLUMN last_error TEXT NULL;
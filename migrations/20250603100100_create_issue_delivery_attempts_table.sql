@@ -0,0 +1,12 @@
+CREATE TABLE issue_delivery_attempts (
+    id INTEGER PRIMARY KEY,
+    newsletter_issue_id TEXT NOT NULL REFERENCES newsletter_issues (uuid),
+    subscriber_email TEXT NOT NULL,
+    -- timestamp with time zone
+    attempted_at TEXT NOT NULL,
+    -- 'delivered', 'transient_failure' or 'permanent_failure'
+    outcome TEXT NOT NULL,
+    error TEXT NULL
+);
+
+CREATE INDEX issue_delivery_attempts_subscriber_email_idx ON issue_delivery_attempts (subscriber_email);
@@ -0,0 +1,4 @@
+-- timestamps with time zone. Tokens handed out before this migration get the
+-- epoch, so they count as expired and their owners have to ask for a new one.
+ALTER TABLE subscription_tokens ADD COLUMN created_at TEXT NOT NULL DEFAULT '1970-01-01 00:00:00 UTC';
+ALTER TABLE subscription_tokens ADD COLUMN expires_at TEXT NOT NULL DEFAULT '1970-01-01 00:00:00 UTC';
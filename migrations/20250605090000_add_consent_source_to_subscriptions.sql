@@ -0,0 +1,3 @@
+-- where the consent of subscribers who never went through our own
+-- confirmation flow comes from, e.g. "Imported from Mailchimp"
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NULL;
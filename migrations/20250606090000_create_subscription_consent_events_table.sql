@@ -0,0 +1,15 @@
+-- append-only audit trail of how each subscriber opted in, for GDPR requests
+CREATE TABLE subscription_consent_events (
+    id INTEGER PRIMARY KEY,
+    subscriber_id TEXT NOT NULL REFERENCES subscriptions (uuid),
+    -- 'signup', 'confirmation' or 'import'
+    event_type TEXT NOT NULL,
+    -- timestamp with time zone
+    occurred_at TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    -- which form the signup came from, or where an import's consent was given
+    source TEXT NULL
+);
+
+CREATE INDEX subscription_consent_events_subscriber_id_idx ON subscription_consent_events (subscriber_id);
@@ -0,0 +1,8 @@
+-- what's left of a subscriber after a GDPR erasure: a keyed hash of their
+-- address, so an old export can't bring them back without anyone noticing
+CREATE TABLE erased_subscribers (
+    id INTEGER PRIMARY KEY,
+    email_hash TEXT NOT NULL UNIQUE,
+    -- timestamp with time zone
+    erased_at TEXT NOT NULL
+);
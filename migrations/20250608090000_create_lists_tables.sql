@@ -0,0 +1,44 @@
+-- one installation can run several newsletters, e.g. a weekly digest and
+-- product announcements, each with its own subscribers
+CREATE TABLE lists (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    -- what signup forms send in their `list` field
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    -- timestamp with time zone
+    created_at TEXT NOT NULL
+);
+
+-- everyone who signed up before lists existed is on this one
+INSERT INTO lists (uuid, slug, name, created_at)
+VALUES ('7c5e1d1e-1f0b-4a55-9a57-4c1f3f0f5e21', 'newsletter', 'Newzletter', '2025-06-08 09:00:00 UTC');
+
+-- `subscriptions` is about the address, this is about who gets which list
+CREATE TABLE list_subscriptions (
+    id INTEGER PRIMARY KEY,
+    list_id TEXT NOT NULL REFERENCES lists (uuid),
+    subscriber_id TEXT NOT NULL REFERENCES subscriptions (uuid),
+    -- 'pending_confirmation', 'confirmed' or 'unsubscribed'
+    status TEXT NOT NULL,
+    -- timestamp with time zone
+    subscribed_at TEXT NOT NULL,
+    UNIQUE (list_id, subscriber_id)
+);
+
+CREATE INDEX list_subscriptions_subscriber_id_idx ON list_subscriptions (subscriber_id);
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT '7c5e1d1e-1f0b-4a55-9a57-4c1f3f0f5e21', uuid, status, subscribed_at
+FROM subscriptions;
+
+-- which lists an issue went out to
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id TEXT NOT NULL REFERENCES newsletter_issues (uuid),
+    list_id TEXT NOT NULL REFERENCES lists (uuid),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT uuid, '7c5e1d1e-1f0b-4a55-9a57-4c1f3f0f5e21'
+FROM newsletter_issues;
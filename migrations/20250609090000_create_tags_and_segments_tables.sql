@@ -0,0 +1,21 @@
+CREATE TABLE subscriber_tags (
+    id INTEGER PRIMARY KEY,
+    subscriber_id TEXT NOT NULL REFERENCES subscriptions (uuid),
+    tag TEXT NOT NULL,
+    UNIQUE (subscriber_id, tag)
+);
+
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- a named filter over tags, status and signup date, see `SegmentExpression`
+CREATE TABLE segments (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL UNIQUE,
+    expression TEXT NOT NULL,
+    -- timestamp with time zone
+    created_at TEXT NOT NULL
+);
+
+-- the segment an issue was narrowed down to, if any
+ALTER TABLE newsletter_issues ADD COLUMN segment_id TEXT NULL REFERENCES segments (uuid);
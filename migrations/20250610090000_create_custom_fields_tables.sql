@@ -0,0 +1,24 @@
+-- extra questions on the signup form, see `CustomField`
+CREATE TABLE custom_fields (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    key TEXT NOT NULL UNIQUE,
+    label TEXT NOT NULL,
+    -- text, select, checkbox or date
+    field_type TEXT NOT NULL,
+    -- what `rule` means depends on `field_type`
+    rule TEXT NOT NULL,
+    required BOOLEAN NOT NULL,
+    -- timestamp with time zone
+    created_at TEXT NOT NULL
+);
+
+-- one row per answered field, the value is validated against the field on the
+-- way in
+CREATE TABLE subscriber_field_values (
+    id INTEGER PRIMARY KEY,
+    subscriber_id TEXT NOT NULL REFERENCES subscriptions (uuid),
+    field_id TEXT NOT NULL REFERENCES custom_fields (uuid),
+    value TEXT NOT NULL,
+    UNIQUE (subscriber_id, field_id)
+);
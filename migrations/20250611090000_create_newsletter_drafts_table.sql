@@ -0,0 +1,26 @@
+-- an issue still being written, it only becomes a `newsletter_issues` row
+-- when it is published
+CREATE TABLE newsletter_drafts (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    -- 'markdown' or 'html_and_text', which of the bodies below is written
+    format TEXT NOT NULL,
+    markdown_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    segment_id TEXT NULL REFERENCES segments (uuid),
+    -- timestamps with time zone
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- which lists a draft is meant for
+CREATE TABLE newsletter_draft_lists (
+    draft_id TEXT NOT NULL REFERENCES newsletter_drafts (uuid),
+    list_id TEXT NOT NULL REFERENCES lists (uuid),
+    PRIMARY KEY (draft_id, list_id)
+);
+
+-- where test sends of a draft go
+ALTER TABLE users ADD COLUMN email TEXT NULL;
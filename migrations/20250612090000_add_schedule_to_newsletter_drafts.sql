@@ -0,0 +1,6 @@
+-- timestamp with time zone, the scheduler publishes the draft once it has passed
+ALTER TABLE newsletter_drafts ADD COLUMN scheduled_at TEXT NULL;
+-- IANA name of the zone the admin picked the time in, to show it back in
+ALTER TABLE newsletter_drafts ADD COLUMN schedule_timezone TEXT NULL;
+-- why the scheduler could not publish the draft, cleared when it is rescheduled
+ALTER TABLE newsletter_drafts ADD COLUMN schedule_error TEXT NULL;
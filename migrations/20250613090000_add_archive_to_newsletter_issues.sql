@@ -0,0 +1,8 @@
+-- where the issue lives in the public archive, /newsletter/archive/{slug}
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NOT NULL DEFAULT '';
+UPDATE newsletter_issues SET slug = 'issue-' || id;
+CREATE UNIQUE INDEX newsletter_issues_slug ON newsletter_issues (slug);
+-- 1 to leave the issue out of the public archive
+ALTER TABLE newsletter_issues ADD COLUMN hidden_from_archive INTEGER NOT NULL DEFAULT 0;
+-- carried over to the issue when the draft is published
+ALTER TABLE newsletter_drafts ADD COLUMN hidden_from_archive INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- timestamp with time zone, when the issue was published or last shown or
+-- hidden in the public archive, the feeds are as new as the newest of these
+ALTER TABLE newsletter_issues ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+UPDATE newsletter_issues SET updated_at = published_at;
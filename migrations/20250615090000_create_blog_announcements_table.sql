@@ -0,0 +1,17 @@
+-- blog posts an issue was drafted for, so that each post is announced once
+CREATE TABLE blog_announcements (
+    id INTEGER PRIMARY KEY,
+    -- as in /blog/{slug}
+    slug TEXT NOT NULL UNIQUE,
+    -- the draft it started as, it may be published or deleted by now
+    draft_id TEXT NULL,
+    -- timestamp with time zone
+    announced_at TEXT NOT NULL
+);
+
+-- when the server first looked for posts, the ones out by then had been
+-- announced by hand, at most one row
+CREATE TABLE blog_announcements_baseline (
+    -- timestamp with time zone
+    taken_at TEXT NOT NULL
+);
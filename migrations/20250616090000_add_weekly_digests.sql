@@ -0,0 +1,21 @@
+-- 'instant' for an issue per blog post, 'weekly_digest' for one email a week
+ALTER TABLE subscriptions ADD COLUMN delivery_preference TEXT NOT NULL DEFAULT 'instant'
+    CHECK (delivery_preference IN ('instant', 'weekly_digest'));
+
+-- the weekly round-ups of blog posts sent to digest subscribers
+CREATE TABLE blog_digests (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    -- the issue it went out as, none for the posts that were out
+    -- before the first digest
+    newsletter_issue_id TEXT NULL,
+    -- timestamp with time zone
+    sent_at TEXT NOT NULL
+);
+
+-- each post is in one digest at most
+CREATE TABLE blog_digest_posts (
+    digest_id TEXT NOT NULL REFERENCES blog_digests (uuid),
+    slug TEXT NOT NULL UNIQUE
+);
+
@@ -0,0 +1,6 @@
+-- what the subscriber picked on the preferences page, issues aren't
+-- translated yet, it tells us who would read them
+ALTER TABLE subscriptions ADD COLUMN language TEXT NOT NULL DEFAULT 'en';
+
+-- timestamp with time zone, nothing is sent to the subscriber before it
+ALTER TABLE subscriptions ADD COLUMN paused_until TEXT NULL;
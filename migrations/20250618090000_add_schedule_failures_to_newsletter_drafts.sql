@@ -0,0 +1,3 @@
+-- how often publishing the draft failed since it was scheduled, the scheduler
+-- backs off and eventually gives up on it
+ALTER TABLE newsletter_drafts ADD COLUMN schedule_failures INTEGER NOT NULL DEFAULT 0;
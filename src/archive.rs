@@ -0,0 +1,314 @@
+//! The public archive of published issues, at `/newsletter/archive`.
+//!
+//! Issues are stored with their merge tags unfilled, the public copy fills
+//! them in with values that say nothing about any one subscriber.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use crate::domain::Language;
+use crate::merge_tags::fill_merge_tags;
+
+// slugs are cut at a word boundary before they grow past this
+const MAX_SLUG_LENGTH: usize = 80;
+
+pub struct ArchivedIssueSummary {
+    pub slug: String,
+    pub title: String,
+    pub published_at: String,
+}
+
+pub struct ArchivedIssue {
+    pub title: String,
+    pub html_content: String,
+    pub published_at: String,
+    // names of the lists it went to
+    pub list_names: Vec<String>,
+}
+
+/// Every published issue the public can see, newest first.
+#[tracing::instrument(name = "Get the archived issues", skip(pool))]
+pub async fn get_archived_issues(
+    pool: &SqlitePool,
+) -> Result<Vec<ArchivedIssueSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssueSummary,
+        r#"
+        SELECT slug, title, published_at
+        FROM newsletter_issues
+        WHERE hidden_from_archive = 0
+        ORDER BY published_at DESC, id DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The issue at `slug`, `None` if there is none or it is hidden.
+#[tracing::instrument(name = "Get an archived issue", skip(pool))]
+pub async fn get_archived_issue(
+    pool: &SqlitePool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT uuid, title, html_content, published_at
+        FROM newsletter_issues
+        WHERE slug = $1 AND hidden_from_archive = 0
+        "#,
+        slug,
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let list_names = get_list_names(pool, &r.uuid).await?;
+    Ok(Some(ArchivedIssue {
+        title: r.title,
+        html_content: r.html_content,
+        published_at: r.published_at,
+        list_names,
+    }))
+}
+
+pub struct FeedIssue {
+    pub uuid: String,
+    pub slug: String,
+    pub title: String,
+    pub html_content: String,
+    pub published_at: String,
+    pub list_names: Vec<String>,
+}
+
+/// The newest `limit` issues the public can see, for the feeds.
+#[tracing::instrument(name = "Get the feed issues", skip(pool))]
+pub async fn get_feed_issues(pool: &SqlitePool, limit: i64) -> Result<Vec<FeedIssue>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT uuid, slug, title, html_content, published_at
+        FROM newsletter_issues
+        WHERE hidden_from_archive = 0
+        ORDER BY published_at DESC, id DESC
+        LIMIT $1
+        "#,
+        limit,
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut issues = Vec::with_capacity(rows.len());
+    for r in rows {
+        let list_names = get_list_names(pool, &r.uuid).await?;
+        issues.push(FeedIssue {
+            uuid: r.uuid,
+            slug: r.slug,
+            title: r.title,
+            html_content: r.html_content,
+            published_at: r.published_at,
+            list_names,
+        });
+    }
+    Ok(issues)
+}
+
+/// When an issue was last published, shown or hidden, `None` before the
+/// first one goes out.
+#[tracing::instrument(name = "Get when the archive was updated", skip(pool))]
+pub async fn archive_updated_at(pool: &SqlitePool) -> Result<Option<String>, sqlx::Error> {
+    Ok(
+        sqlx::query!(r#"SELECT MAX(updated_at) AS "updated_at: String" FROM newsletter_issues"#)
+            .fetch_one(pool)
+            .await?
+            .updated_at,
+    )
+}
+
+async fn get_list_names(pool: &SqlitePool, issue_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT l.name
+        FROM newsletter_issue_lists il
+        JOIN lists l ON l.uuid = il.list_id
+        WHERE il.newsletter_issue_id = $1
+        ORDER BY l.id
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| r.name)
+    .collect())
+}
+
+pub struct PublishedIssue {
+    pub uuid: String,
+    pub slug: String,
+    pub title: String,
+    pub published_at: String,
+    pub hidden_from_archive: bool,
+}
+
+/// Every published issue, hidden ones included, newest first.
+#[tracing::instrument(name = "Get the published issues", skip(pool))]
+pub async fn get_published_issues(pool: &SqlitePool) -> Result<Vec<PublishedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            uuid,
+            slug,
+            title,
+            published_at,
+            hidden_from_archive AS "hidden_from_archive: bool"
+        FROM newsletter_issues
+        ORDER BY published_at DESC, id DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Show or hide an issue in the public archive. Returns `false` if there is
+/// no such issue.
+#[tracing::instrument(name = "Hide an issue from the archive", skip(pool))]
+pub async fn set_hidden_from_archive(
+    pool: &SqlitePool,
+    issue_id: Uuid,
+    hidden: bool,
+) -> Result<bool, sqlx::Error> {
+    let issue_id = issue_id.to_string();
+    let now = Utc::now().to_string();
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            updated_at = CASE WHEN hidden_from_archive = $2 THEN updated_at ELSE $3 END,
+            hidden_from_archive = $2
+        WHERE uuid = $1
+        "#,
+        issue_id,
+        hidden,
+        now,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// A slug for `title` that no other issue has, `-2`, `-3` and so on are
+/// added until it is free.
+pub async fn unique_slug(
+    transaction: &mut Transaction<'_, Sqlite>,
+    title: &str,
+) -> Result<String, sqlx::Error> {
+    let base = slugify(title);
+    let mut slug = base.clone();
+    for n in 2.. {
+        let taken = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM newsletter_issues WHERE slug = $1) AS "taken: bool""#,
+            slug,
+        )
+        .fetch_one(&mut **transaction)
+        .await?
+        .taken;
+        if !taken {
+            break;
+        }
+        slug = format!("{}-{}", base, n);
+    }
+    Ok(slug)
+}
+
+/// Lowercase ASCII letters and digits, with a `-` for any run of anything
+/// else, e.g. `Hello, World!` becomes `hello-world`.
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() && slug.len() + 1 + word.len() > MAX_SLUG_LENGTH {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug.truncate(MAX_SLUG_LENGTH);
+    if slug.is_empty() {
+        // nothing left of a title in another script
+        slug.push_str("issue");
+    }
+    slug
+}
+
+/// The HTML part of an issue as anyone may read it.
+///
+/// Merge tags get generic values: the subscriber's name, address and
+/// language, and custom fields, are left out, and the unsubscribe and preferences links go
+/// to `manage_url`, where subscribers ask for a link to their own settings.
+pub fn public_copy(html_content: &str, list_names: &[String], manage_url: &str) -> String {
+    let values = HashMap::from([
+        ("name".to_owned(), "reader".to_owned()),
+        ("email".to_owned(), "your email address".to_owned()),
+        (
+            "language".to_owned(),
+            Language::default().as_str().to_owned(),
+        ),
+        ("list_name".to_owned(), list_names.join(", ")),
+        ("unsubscribe_url".to_owned(), manage_url.to_owned()),
+        ("preferences_url".to_owned(), manage_url.to_owned()),
+    ]);
+    fill_merge_tags(html_content, &values, |value| {
+        htmlescape::encode_minimal(value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{public_copy, slugify};
+
+    #[test]
+    fn titles_are_slugified() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Issue #12: what's new?  "), "issue-12-what-s-new");
+    }
+
+    #[test]
+    fn a_title_without_ascii_letters_gets_a_generic_slug() {
+        assert_eq!(slugify("نشرة"), "issue");
+        assert_eq!(slugify("!!!"), "issue");
+    }
+
+    #[test]
+    fn long_slugs_are_cut_at_a_word_boundary() {
+        let slug = slugify(&"newsletter ".repeat(20));
+        assert!(slug.len() <= 80);
+        assert!(slug.ends_with("newsletter"));
+        assert_eq!(slugify(&"a".repeat(100)).len(), 80);
+    }
+
+    #[test]
+    fn the_public_copy_says_nothing_about_any_one_subscriber() {
+        let html = "<p>Hi {{ name }} ({{email}}), you are on {{ list_name }} \
+            with {{ plan }}. <a href=\"{{ unsubscribe_url }}\">Leave</a></p>";
+
+        let copy = public_copy(
+            html,
+            &["Rust & Friends".to_owned(), "News".to_owned()],
+            "https://example.com/subscriptions/data",
+        );
+
+        assert_eq!(
+            copy,
+            "<p>Hi reader (your email address), you are on Rust &amp; Friends, News \
+            with . <a href=\"https://example.com/subscriptions/data\">Leave</a></p>"
+        );
+    }
+}
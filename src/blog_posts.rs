@@ -0,0 +1,321 @@
+//! Issues announcing new blog posts.
+//!
+//! A post counts as published once the built site has it under
+//! `frontend/dist/blog/{slug}`. Each one gets a draft built from its
+//! frontmatter, once: `blog_announcements` remembers which slugs had theirs.
+//! The posts already out when the server first looks were announced by hand
+//! and get none.
+
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::Context;
+use chrono::Utc;
+use regex::Regex;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use crate::configuration::BlogSettings;
+use crate::domain::{BodyFormat, IssueBody, SendTime};
+use crate::drafts::{schedule_draft, write_draft};
+use crate::lists::{get_list_by_slug, DEFAULT_LIST_SLUG};
+
+// the hero image as `BlogPost.astro` lays it out
+static HERO_IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<figure class="card-image">\s*<img[^>]*\ssrc="([^"]+)""#).unwrap()
+});
+
+#[derive(Debug)]
+pub struct BlogPost {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    // where the built site serves it from, e.g. `/_astro/hero.webp`
+    pub hero_image: Option<String>,
+}
+
+pub struct Announcement {
+    pub title: String,
+    pub draft_id: Uuid,
+}
+
+/// Every post in `content_directory` that the built site serves, by slug.
+pub fn find_published_posts(settings: &BlogSettings) -> Result<Vec<BlogPost>, anyhow::Error> {
+    let entries = fs::read_dir(&settings.content_directory)
+        .with_context(|| format!("Failed to read {}", settings.content_directory))?;
+    let mut posts = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_markdown = path
+            .extension()
+            .is_some_and(|extension| extension == "md" || extension == "mdx");
+        if !is_markdown {
+            continue;
+        }
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let frontmatter = parse_frontmatter(&source);
+        let Some(title) = frontmatter.get("title") else {
+            // the site would not build with it either
+            continue;
+        };
+        let slug = match frontmatter.get("slug") {
+            Some(slug) => slug.to_owned(),
+            None => slugify(&path.file_stem().unwrap_or_default().to_string_lossy()),
+        };
+        let page = Path::new(&settings.dist_directory)
+            .join(&slug)
+            .join("index.html");
+        let Ok(page) = fs::read_to_string(page) else {
+            // written but not out yet
+            continue;
+        };
+        let hero_image = frontmatter.get("heroImage").and_then(|hero_image| {
+            if hero_image.starts_with("https://") || hero_image.starts_with("http://") {
+                Some(hero_image.to_owned())
+            } else {
+                // a path into the sources, only the built page knows its URL
+                HERO_IMAGE
+                    .captures(&page)
+                    .map(|captures| captures[1].to_owned())
+            }
+        });
+        posts.push(BlogPost {
+            slug,
+            title: title.to_owned(),
+            description: frontmatter.get("description").unwrap_or("").to_owned(),
+            hero_image,
+        });
+    }
+    posts.sort_by(|a, b| a.slug.cmp(&b.slug));
+    Ok(posts)
+}
+
+/// Draft an issue for every published post that has not had one, and with
+/// `auto_send` have the scheduler publish it right away.
+///
+/// Returns what was announced. Publishing goes through the scheduler so the
+/// issue is checked and sent exactly as a scheduled draft would be. The first
+/// sync only takes note of the posts there are.
+#[tracing::instrument(name = "Announce new blog posts", skip(pool, settings))]
+pub async fn announce_new_posts(
+    pool: &SqlitePool,
+    settings: &BlogSettings,
+    base_url: &str,
+) -> Result<Vec<Announcement>, anyhow::Error> {
+    let posts = find_published_posts(settings)?;
+    let mut transaction = pool.begin().await?;
+    let is_first_sync = take_baseline(&mut transaction, &posts).await?;
+    transaction.commit().await?;
+    if is_first_sync {
+        return Ok(Vec::new());
+    }
+    let mut announcements = Vec::new();
+    for post in posts {
+        let mut transaction = pool.begin().await?;
+        let draft_id = Uuid::new_v4();
+        let draft_id_str = draft_id.to_string();
+        let now = Utc::now().to_string();
+        let n_inserted_rows = sqlx::query!(
+            r#"
+            INSERT INTO blog_announcements (slug, draft_id, announced_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (slug) DO NOTHING
+            "#,
+            post.slug,
+            draft_id_str,
+            now,
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if n_inserted_rows == 0 {
+            continue;
+        }
+        let list_ids = get_list_by_slug(&mut *transaction, DEFAULT_LIST_SLUG)
+            .await?
+            .map(|list| vec![list.id])
+            .unwrap_or_default();
+        let body = IssueBody {
+            format: BodyFormat::Markdown,
+            markdown_content: announcement_markdown(&post, base_url),
+            text_content: String::new(),
+            html_content: String::new(),
+        };
+        write_draft(
+            &mut transaction,
+            draft_id,
+            &post.title,
+            &body,
+            &list_ids,
+            None,
+            false,
+        )
+        .await?;
+        transaction.commit().await?;
+        if settings.auto_send {
+            let now = SendTime {
+                at: Utc::now(),
+                timezone: chrono_tz::UTC,
+            };
+            schedule_draft(pool, draft_id, &now).await?;
+        }
+        announcements.push(Announcement {
+            title: post.title,
+            draft_id,
+        });
+    }
+    Ok(announcements)
+}
+
+/// Record `posts` as announced without a draft, unless a sync did so before.
+/// Returns whether this was the first sync.
+async fn take_baseline(
+    transaction: &mut Transaction<'_, Sqlite>,
+    posts: &[BlogPost],
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now().to_string();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO blog_announcements_baseline (taken_at)
+        SELECT $1
+        WHERE NOT EXISTS (SELECT 1 FROM blog_announcements_baseline)
+        "#,
+        now,
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+    if n_inserted_rows == 0 {
+        return Ok(false);
+    }
+    for post in posts {
+        sqlx::query!(
+            r#"
+            INSERT INTO blog_announcements (slug, announced_at)
+            VALUES ($1, $2)
+            ON CONFLICT (slug) DO NOTHING
+            "#,
+            post.slug,
+            now,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+    Ok(true)
+}
+
+fn announcement_markdown(post: &BlogPost, base_url: &str) -> String {
+    let mut markdown = String::new();
+    if let Some(hero_image) = &post.hero_image {
+        let hero_image = if hero_image.starts_with('/') {
+            format!("{}{}", base_url, hero_image)
+        } else {
+            hero_image.to_owned()
+        };
+        markdown.push_str(&format!("![]({})\n\n", hero_image));
+    }
+    if !post.description.is_empty() {
+        markdown.push_str(&format!("{}\n\n", escape_markdown(&post.description)));
+    }
+    markdown.push_str(&format!(
+        "[Read the post on the blog]({}/blog/{})\n",
+        base_url, post.slug
+    ));
+    markdown
+}
+
+/// The `key: value` lines between the leading `---` fences. Enough YAML for
+/// the frontmatter the blog collection takes, quotes are dropped.
+fn parse_frontmatter(source: &str) -> Frontmatter {
+    let mut fields = Vec::new();
+    let mut lines = source.lines();
+    if lines.next().map(str::trim) != Some("---") {
+        return Frontmatter(fields);
+    }
+    for line in lines {
+        if line.trim() == "---" {
+            break;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+            value[1..value.len() - 1].replace("''", "'")
+        } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            value[1..value.len() - 1].replace("\\\"", "\"")
+        } else {
+            value.to_owned()
+        };
+        fields.push((key.trim().to_owned(), value));
+    }
+    Frontmatter(fields)
+}
+
+struct Frontmatter(Vec<(String, String)>);
+
+impl Frontmatter {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The slug Astro gives a post in the blog collection, from its file name:
+/// lowercase, spaces become `-`, other punctuation goes.
+fn slugify(file_stem: &str) -> String {
+    file_stem
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Descriptions are plain text, nothing in them should turn into markup.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]()<>#!|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_frontmatter, slugify};
+
+    #[test]
+    fn file_names_are_slugified_as_astro_does() {
+        assert_eq!(slugify("Astro-Rust"), "astro-rust");
+        assert_eq!(slugify("linkedin queens game"), "linkedin-queens-game");
+        assert_eq!(slugify("What's new?"), "whats-new");
+    }
+
+    #[test]
+    fn frontmatter_values_lose_their_quotes() {
+        let frontmatter = parse_frontmatter(
+            "---\ntitle: 'It''s here'\ndescription: \"A: b\"\npubDate: Jun 8 2025\n---\n# Body\ntitle: no",
+        );
+
+        assert_eq!(frontmatter.get("title"), Some("It's here"));
+        assert_eq!(frontmatter.get("description"), Some("A: b"));
+        assert_eq!(frontmatter.get("pubDate"), Some("Jun 8 2025"));
+        assert_eq!(frontmatter.get("heroImage"), None);
+    }
+
+    #[test]
+    fn a_file_without_frontmatter_has_no_fields() {
+        assert_eq!(parse_frontmatter("# Just a heading").get("title"), None);
+    }
+}
@@ -9,13 +9,20 @@ use sqlx::{
     SqlitePool,
 };
 
-use crate::domain::SubscriberEmail;
+use crate::{
+    consent::ClientIpSource,
+    domain::SubscriberEmail,
+    email_client::{EmailClient, FileOutboxTransport, PostmarkTransport, SmtpTransport},
+    issue_delivery_worker::RetryPolicy,
+};
 
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub delivery: DeliverySettings,
+    pub blog: BlogSettings,
     pub redis_uri: SecretString,
 }
 
@@ -29,6 +36,7 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: SecretString,
+    pub client_ip_source: ClientIpSource,
 }
 
 #[derive(Deserialize, Clone)]
@@ -89,16 +97,51 @@ impl DatabaseSettings {
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
-    pub base_url: String,
+    pub kind: EmailTransportKind,
     pub sender_email: String,
-    pub authorization_token: SecretString,
     // #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_milliseconds: u64,
+    // postmark
+    pub base_url: String,
+    pub authorization_token: SecretString,
+    // smtp
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    // file_outbox
+    pub outbox_directory: String,
+}
+
+/// Which backend `EmailClient` hands its messages to.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTransportKind {
+    Postmark,
+    Smtp,
+    FileOutbox,
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        match self.kind {
+            EmailTransportKind::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout),
+            ),
+            EmailTransportKind::Smtp => EmailClient::new(
+                sender_email,
+                SmtpTransport::new(self.smtp_host, self.smtp_port, timeout),
+            ),
+            EmailTransportKind::FileOutbox => EmailClient::new(
+                sender_email,
+                FileOutboxTransport::new(self.outbox_directory),
+            ),
+        }
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
-        Ok(SubscriberEmail::parse(self.sender_email.clone())?)
+        SubscriberEmail::parse(self.sender_email.clone())
     }
 
     pub fn timeout(&self) -> std::time::Duration {
@@ -106,6 +149,34 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(Deserialize, Clone)]
+pub struct DeliverySettings {
+    pub max_retries: u32,
+    pub backoff_base_milliseconds: u64,
+    pub backoff_cap_milliseconds: u64,
+}
+
+impl DeliverySettings {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: std::time::Duration::from_millis(self.backoff_base_milliseconds),
+            max_delay: std::time::Duration::from_millis(self.backoff_cap_milliseconds),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BlogSettings {
+    // where the posts are written, `*.md` files with frontmatter
+    pub content_directory: String,
+    // where the built site serves them from, a post is out once it is here
+    pub dist_directory: String,
+    pub sync_on_startup: bool,
+    // publish the issue for a new post right away instead of drafting it
+    pub auto_send: bool,
+}
+
 pub fn get_configuration() -> Result<Settings, ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
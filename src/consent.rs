@@ -0,0 +1,211 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use axum::http::{header, request::Parts, HeaderMap};
+use chrono::Utc;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+// user agents can be arbitrarily long, we only need enough to recognise them
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Who is on the other end of a request, as far as we can tell.
+#[derive(Debug, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Where the client's address comes from, set to match what sits in front of
+/// the app. Forwarding headers are whatever the client says unless a proxy we
+/// trust wrote them.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientIpSource {
+    /// Nothing in front, the peer is the client.
+    #[default]
+    Peer,
+    /// A reverse proxy that appends the peer it saw to X-Forwarded-For.
+    XForwardedFor,
+    /// Fly's proxy, which sets Fly-Client-IP.
+    FlyClientIp,
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    ClientIpSource: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        let ip_address = client_ip(ClientIpSource::from_ref(state), &parts.headers, peer)
+            .map(|ip| ip.to_string());
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        Ok(Self {
+            ip_address,
+            user_agent,
+        })
+    }
+}
+
+fn client_ip(source: ClientIpSource, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let forwarded = match source {
+        ClientIpSource::Peer => None,
+        // earlier hops came from the client, the proxy appended the last one
+        ClientIpSource::XForwardedFor => {
+            header("X-Forwarded-For").and_then(|hops| hops.rsplit(',').next())
+        }
+        ClientIpSource::FlyClientIp => header("Fly-Client-IP"),
+    };
+    forwarded.and_then(|ip| ip.trim().parse().ok()).or(peer)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ConsentEventType {
+    /// Someone filled in a subscription form.
+    Signup,
+    /// They clicked the link in the confirmation email.
+    Confirmation,
+    /// An admin imported them, `source` says where they opted in.
+    Import,
+    /// They joined another list from their preferences page.
+    Preferences,
+}
+
+impl ConsentEventType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::Confirmation => "confirmation",
+            Self::Import => "import",
+            Self::Preferences => "preferences",
+        }
+    }
+}
+
+#[tracing::instrument(name = "Record a consent event", skip(transaction, client_info))]
+pub async fn record_consent_event(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+    event_type: ConsentEventType,
+    client_info: &ClientInfo,
+    source: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let event_type = event_type.as_str();
+    let occurred_at = Utc::now().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_consent_events (
+            subscriber_id,
+            event_type,
+            occurred_at,
+            ip_address,
+            user_agent,
+            source
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        subscriber_id,
+        event_type,
+        occurred_at,
+        client_info.ip_address,
+        client_info.user_agent,
+        source,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEvent {
+    pub event_type: String,
+    pub occurred_at: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+}
+
+/// Oldest first.
+#[tracing::instrument(name = "Get consent events", skip(pool))]
+pub async fn get_consent_events(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEvent>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query_as!(
+        ConsentEvent,
+        r#"
+        SELECT event_type, occurred_at, ip_address, user_agent, source
+        FROM subscription_consent_events
+        WHERE subscriber_id = $1
+        ORDER BY id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, ClientIpSource};
+    use axum::http::HeaderMap;
+    use std::net::IpAddr;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Forwarded-For",
+            "203.0.113.7, 198.51.100.2".parse().unwrap(),
+        );
+        headers.insert("Fly-Client-IP", "192.0.2.44".parse().unwrap());
+        headers
+    }
+
+    fn peer() -> Option<IpAddr> {
+        Some("10.0.0.1".parse().unwrap())
+    }
+
+    #[test]
+    fn forwarding_headers_are_ignored_without_a_proxy() {
+        assert_eq!(client_ip(ClientIpSource::Peer, &headers(), peer()), peer());
+    }
+
+    #[test]
+    fn the_hop_the_proxy_appended_is_the_client() {
+        assert_eq!(
+            client_ip(ClientIpSource::XForwardedFor, &headers(), peer()),
+            Some("198.51.100.2".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(ClientIpSource::FlyClientIp, &headers(), peer()),
+            Some("192.0.2.44".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn a_missing_or_garbled_header_falls_back_to_the_peer() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", "not an address".parse().unwrap());
+        assert_eq!(
+            client_ip(ClientIpSource::XForwardedFor, &headers, peer()),
+            peer()
+        );
+        assert_eq!(
+            client_ip(ClientIpSource::FlyClientIp, &headers, peer()),
+            peer()
+        );
+    }
+}
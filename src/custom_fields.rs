@@ -0,0 +1,139 @@
+//! The admin-defined fields of the signup form, and what subscribers answered.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{Sqlite, SqliteExecutor, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{CustomField, CustomFieldValue};
+
+#[tracing::instrument(name = "Get the custom fields", skip(executor))]
+pub async fn get_custom_fields(
+    executor: impl SqliteExecutor<'_>,
+) -> Result<Vec<CustomField>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT key, label, field_type, rule, required AS "required: bool"
+        FROM custom_fields
+        ORDER BY id
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            // they were checked on the way in, failing now means the rules changed
+            CustomField::parse(r.key, r.label, &r.field_type, r.rule, r.required)
+                .map_err(anyhow::Error::msg)
+        })
+        .collect()
+}
+
+/// Returns `false` if another field already goes by the same key.
+#[tracing::instrument(name = "Create a custom field", skip(pool, field), fields(key = %field.key))]
+pub async fn insert_custom_field(
+    pool: &SqlitePool,
+    field: &CustomField,
+) -> Result<bool, sqlx::Error> {
+    let uuid = Uuid::new_v4().to_string();
+    let field_type = field.field_type();
+    let rule = field.rule();
+    let created_at = Utc::now().to_string();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO custom_fields (uuid, key, label, field_type, rule, required, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        uuid,
+        field.key,
+        field.label,
+        field_type,
+        rule,
+        field.required,
+        created_at,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows > 0)
+}
+
+#[tracing::instrument(name = "Store custom field values", skip(transaction, values))]
+pub async fn store_field_values(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+    values: &[CustomFieldValue],
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    for value in values {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_field_values (subscriber_id, field_id, value)
+            SELECT $1, uuid, $3 FROM custom_fields WHERE key = $2
+            ON CONFLICT (subscriber_id, field_id) DO UPDATE SET value = excluded.value
+            "#,
+            subscriber_id,
+            value.key,
+            value.value,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct FieldAnswer {
+    pub key: String,
+    pub label: String,
+    pub value: String,
+}
+
+#[tracing::instrument(name = "Get the custom fields of a subscriber", skip(pool))]
+pub async fn get_field_answers(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Vec<FieldAnswer>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query_as!(
+        FieldAnswer,
+        r#"
+        SELECT f.key, f.label, v.value
+        FROM subscriber_field_values v
+        JOIN custom_fields f ON f.uuid = v.field_id
+        WHERE v.subscriber_id = $1
+        ORDER BY f.id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The subscriber's answers by field key, as merge tags see them. Fields they
+/// left blank are there too, empty.
+#[tracing::instrument(name = "Get the merge fields of a subscriber", skip(executor))]
+pub async fn get_field_values(
+    executor: impl SqliteExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let values = sqlx::query!(
+        r#"
+        SELECT f.key, COALESCE(v.value, '') AS "value!: String"
+        FROM custom_fields f
+        LEFT JOIN subscriber_field_values v
+            ON v.field_id = f.uuid AND v.subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|r| (r.key, r.value))
+    .collect();
+    Ok(values)
+}
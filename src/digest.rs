@@ -0,0 +1,268 @@
+//! The weekly round-up of blog posts, for subscribers who would rather not
+//! get an email per post.
+//!
+//! A digest holds the posts that went out since the last one and goes to the
+//! digest subscribers of the default list. `blog_digest_posts` remembers
+//! which posts were in one, so none is sent twice. The posts already out when
+//! the job first runs are in none: they were announced before digests existed.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::{Sqlite, SqliteExecutor, SqlitePool, Transaction};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::blog_posts::{find_published_posts, BlogPost};
+use crate::configuration::BlogSettings;
+use crate::routes::publish_digest;
+
+pub const DIGEST_TITLE: &str = "This week on the blog";
+
+// at most one digest this often
+const DIGEST_INTERVAL: TimeDelta = TimeDelta::days(7);
+
+// the longest the job sleeps without having another look
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// how long to wait before trying again after a digest failed
+const ERROR_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Send the digest whenever one is due and there is something to put in it,
+/// forever.
+pub async fn run_digest_until_stopped(
+    pool: SqlitePool,
+    settings: BlogSettings,
+    base_url: String,
+    delivery_trigger: Arc<Notify>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match send_due_digest(&pool, &settings, &base_url, Utc::now()).await {
+            Ok(true) => delivery_trigger.notify_one(),
+            Ok(false) => {}
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    retry_in = ?ERROR_BACKOFF,
+                    "Failed to send the weekly digest. Retrying later.",
+                );
+                tokio::time::sleep(ERROR_BACKOFF).await;
+                continue;
+            }
+        }
+        let wait = next_digest_due(&pool)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|due| (due - Utc::now()).to_std().ok())
+            .unwrap_or(IDLE_POLL_INTERVAL)
+            .min(IDLE_POLL_INTERVAL);
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Publish a digest of the posts no digest had yet, if the last one went out
+/// at least a week before `now`. Returns whether one went out. The first run
+/// only takes note of the posts there are.
+///
+/// Checking, publishing and remembering the posts commit together, a second
+/// run racing this one finds the posts taken.
+#[tracing::instrument(skip(pool, settings))]
+pub async fn send_due_digest(
+    pool: &SqlitePool,
+    settings: &BlogSettings,
+    base_url: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    if take_baseline(&mut transaction, settings, now).await? {
+        transaction.commit().await?;
+        return Ok(false);
+    }
+    if next_digest_due(&mut *transaction)
+        .await?
+        .is_some_and(|due| now < due)
+    {
+        return Ok(false);
+    }
+    let sent_slugs = get_sent_slugs(&mut transaction).await?;
+    let posts = find_published_posts(settings)?
+        .into_iter()
+        .filter(|post| !sent_slugs.contains(&post.slug))
+        .collect::<Vec<_>>();
+    if posts.is_empty() {
+        return Ok(false);
+    }
+    let (text_content, html_content) = render_digest(&posts, base_url);
+    let issue_id =
+        publish_digest(&mut transaction, DIGEST_TITLE, &text_content, &html_content).await?;
+    record_digest(&mut transaction, Some(issue_id), &posts, now).await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Take note of the posts out before digests existed, unless a run did so
+/// before. The server does this on startup, ahead of the job.
+pub async fn take_digest_baseline(
+    pool: &SqlitePool,
+    settings: &BlogSettings,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    take_baseline(&mut transaction, settings, Utc::now()).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Record the posts there are as a digest without an issue, if there is no
+/// digest yet. Returns whether this was the first run.
+async fn take_baseline(
+    transaction: &mut Transaction<'_, Sqlite>,
+    settings: &BlogSettings,
+    now: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    let has_digests = sqlx::query!(r#"SELECT id FROM blog_digests LIMIT 1"#)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .is_some();
+    if has_digests {
+        return Ok(false);
+    }
+    let posts = find_published_posts(settings)?;
+    record_digest(transaction, None, &posts, now).await?;
+    Ok(true)
+}
+
+/// When the next digest may go out, `None` if there never was one.
+async fn next_digest_due(
+    executor: impl SqliteExecutor<'_>,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let last_sent_at = sqlx::query!(
+        r#"
+        SELECT MAX(sent_at) AS "sent_at: String"
+        FROM blog_digests
+        WHERE newsletter_issue_id IS NOT NULL
+        "#
+    )
+    .fetch_one(executor)
+    .await?
+    .sent_at;
+    Ok(match last_sent_at {
+        Some(sent_at) => Some(sent_at.parse::<DateTime<Utc>>()? + DIGEST_INTERVAL),
+        None => None,
+    })
+}
+
+async fn get_sent_slugs(
+    transaction: &mut Transaction<'_, Sqlite>,
+) -> Result<HashSet<String>, sqlx::Error> {
+    Ok(sqlx::query!(r#"SELECT slug FROM blog_digest_posts"#)
+        .fetch_all(&mut **transaction)
+        .await?
+        .into_iter()
+        .map(|r| r.slug)
+        .collect())
+}
+
+async fn record_digest(
+    transaction: &mut Transaction<'_, Sqlite>,
+    issue_id: Option<Uuid>,
+    posts: &[BlogPost],
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let digest_id = Uuid::new_v4().to_string();
+    let issue_id = issue_id.map(|issue_id| issue_id.to_string());
+    let sent_at = now.to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO blog_digests (uuid, newsletter_issue_id, sent_at)
+        VALUES ($1, $2, $3)
+        "#,
+        digest_id,
+        issue_id,
+        sent_at,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    for post in posts {
+        sqlx::query!(
+            r#"INSERT INTO blog_digest_posts (digest_id, slug) VALUES ($1, $2)"#,
+            digest_id,
+            post.slug,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The plain text and HTML parts of a digest of `posts`.
+fn render_digest(posts: &[BlogPost], base_url: &str) -> (String, String) {
+    let mut text = DIGEST_TITLE.to_owned();
+    let mut html = format!(
+        "<div style=\"font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Helvetica, Arial, sans-serif; font-size: 16px; line-height: 1.6; color: #1f2937;\">\n\
+        <h1 style=\"margin: 24px 0 12px; font-size: 26px; line-height: 1.3; color: #111827;\">{}</h1>\n",
+        DIGEST_TITLE
+    );
+    for post in posts {
+        let url = format!("{}/blog/{}", base_url, post.slug);
+        text.push_str(&format!("\n\n{}", post.title));
+        if !post.description.is_empty() {
+            text.push_str(&format!("\n{}", post.description));
+        }
+        text.push_str(&format!("\n{}", url));
+
+        let url = htmlescape::encode_minimal(&url);
+        html.push_str(&format!(
+            "<h2 style=\"margin: 24px 0 12px; font-size: 22px; line-height: 1.3; color: #111827;\"><a href=\"{}\" style=\"color: #2563eb; text-decoration: underline;\">{}</a></h2>\n",
+            url,
+            htmlescape::encode_minimal(&post.title)
+        ));
+        if !post.description.is_empty() {
+            html.push_str(&format!(
+                "<p style=\"margin: 0 0 16px;\">{}</p>\n",
+                htmlescape::encode_minimal(&post.description)
+            ));
+        }
+        html.push_str(&format!(
+            "<p style=\"margin: 0 0 16px;\"><a href=\"{}\" style=\"color: #2563eb; text-decoration: underline;\">Read the post</a></p>\n",
+            url
+        ));
+    }
+    html.push_str("</div>");
+    (text, html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_digest;
+    use crate::blog_posts::BlogPost;
+
+    #[test]
+    fn a_digest_links_to_every_post() {
+        let posts = vec![
+            BlogPost {
+                slug: "first".into(),
+                title: "Fish & Chips".into(),
+                description: "A <b>bold</b> take".into(),
+                hero_image: None,
+            },
+            BlogPost {
+                slug: "second".into(),
+                title: "Second".into(),
+                description: String::new(),
+                hero_image: None,
+            },
+        ];
+
+        let (text, html) = render_digest(&posts, "https://example.com");
+
+        assert!(text.contains("Fish & Chips\nA <b>bold</b> take\nhttps://example.com/blog/first"));
+        assert!(text.contains("Second\nhttps://example.com/blog/second"));
+        assert!(html.contains("Fish &amp; Chips"));
+        assert!(html.contains("A &lt;b&gt;bold&lt;/b&gt; take"));
+        assert!(html.contains(r#"href="https://example.com/blog/second""#));
+    }
+}
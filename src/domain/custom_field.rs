@@ -0,0 +1,328 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+// long enough for a company name or a short answer
+const MAX_TEXT_LENGTH: usize = 500;
+
+// Keys double as form field names and merge tags, so they can't shadow the
+// fields the signup form already has or the tags every issue gets.
+const RESERVED_KEYS: [&str; 9] = [
+    "name",
+    "email",
+    "language",
+    "source",
+    "list",
+    "delivery",
+    "list_name",
+    "unsubscribe_url",
+    "preferences_url",
+];
+
+/// An extra question on the signup form, defined by an admin, e.g. a
+/// `company` text field or a `role` select.
+#[derive(Debug)]
+pub struct CustomField {
+    pub key: String,
+    pub label: String,
+    pub required: bool,
+    pub kind: FieldKind,
+    rule: String,
+}
+
+#[derive(Debug)]
+pub enum FieldKind {
+    /// Free text, held to `pattern` if there is one.
+    Text { pattern: Option<Regex> },
+    /// One of `options`.
+    Select { options: Vec<String> },
+    /// Stored as `true` or `false`. A required checkbox has to be ticked.
+    Checkbox,
+    /// A `YYYY-MM-DD` date, between the bounds if there are any.
+    Date {
+        earliest: Option<NaiveDate>,
+        latest: Option<NaiveDate>,
+    },
+}
+
+/// A subscriber's answer to a custom field, already validated.
+#[derive(Debug)]
+pub struct CustomFieldValue {
+    pub key: String,
+    pub value: String,
+}
+
+impl CustomField {
+    /// `rule` depends on `field_type`: a regular expression the whole value
+    /// must match for `text`, the comma-separated options for `select`,
+    /// `YYYY-MM-DD..YYYY-MM-DD` bounds for `date`, either side optional, and
+    /// nothing for `checkbox`.
+    pub fn parse(
+        key: String,
+        label: String,
+        field_type: &str,
+        rule: String,
+        required: bool,
+    ) -> Result<CustomField, String> {
+        let is_valid_length = !key.is_empty() && key.len() <= 50;
+        let has_valid_characters = key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_valid_length || !has_valid_characters {
+            return Err(format!(
+                "{} is not a valid field key. Use lowercase letters, digits and underscores, starting with a letter.",
+                key
+            ));
+        }
+        if RESERVED_KEYS.contains(&key.as_str()) {
+            return Err(format!("{} is already taken by a built-in field.", key));
+        }
+        if label.trim().is_empty() {
+            return Err("Give the field a label.".into());
+        }
+        let rule = rule.trim().to_owned();
+        let kind = match field_type {
+            "text" if rule.is_empty() => FieldKind::Text { pattern: None },
+            "text" => {
+                let pattern = Regex::new(&format!("^(?:{})$", rule))
+                    .map_err(|_| format!("{} is not a valid regular expression.", rule))?;
+                FieldKind::Text {
+                    pattern: Some(pattern),
+                }
+            }
+            "select" => {
+                let options = rule
+                    .split(',')
+                    .map(|option| option.trim().to_owned())
+                    .filter(|option| !option.is_empty())
+                    .collect::<Vec<_>>();
+                if options.is_empty() {
+                    return Err("A select field needs its options, separated by commas.".into());
+                }
+                FieldKind::Select { options }
+            }
+            "checkbox" => FieldKind::Checkbox,
+            "date" => {
+                let (earliest, latest) =
+                    match rule.split_once("..") {
+                        Some((earliest, latest)) => (parse_bound(earliest)?, parse_bound(latest)?),
+                        None if rule.is_empty() => (None, None),
+                        None => return Err(
+                            "Bound a date field with YYYY-MM-DD..YYYY-MM-DD, either side optional."
+                                .into(),
+                        ),
+                    };
+                FieldKind::Date { earliest, latest }
+            }
+            field_type => {
+                return Err(format!(
+                    "{} is not a field type, use text, select, checkbox or date.",
+                    field_type
+                ))
+            }
+        };
+        Ok(Self {
+            key,
+            label: label.trim().to_owned(),
+            required,
+            kind,
+            rule,
+        })
+    }
+
+    pub fn field_type(&self) -> &'static str {
+        match self.kind {
+            FieldKind::Text { .. } => "text",
+            FieldKind::Select { .. } => "select",
+            FieldKind::Checkbox => "checkbox",
+            FieldKind::Date { .. } => "date",
+        }
+    }
+
+    /// The rule as the admin wrote it.
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    pub fn options(&self) -> &[String] {
+        match &self.kind {
+            FieldKind::Select { options } => options,
+            _ => &[],
+        }
+    }
+
+    /// Check what the form sent for this field, `None` if it was left out.
+    ///
+    /// Returns the value to store, if any: a blank optional field is not
+    /// stored, an unticked checkbox is stored as `false`.
+    pub fn validate(&self, value: Option<&str>) -> Result<Option<String>, String> {
+        let value = value.map(str::trim).filter(|value| !value.is_empty());
+        if let FieldKind::Checkbox = self.kind {
+            return match value {
+                Some(_) => Ok(Some("true".into())),
+                None if self.required => Err(format!("{} must be ticked.", self.label)),
+                None => Ok(Some("false".into())),
+            };
+        }
+        let Some(value) = value else {
+            return match self.required {
+                true => Err(format!("{} is required.", self.label)),
+                false => Ok(None),
+            };
+        };
+        let invalid = || format!("{} is not a valid {}.", value, self.label);
+        match &self.kind {
+            FieldKind::Text { pattern } => {
+                if value.chars().count() > MAX_TEXT_LENGTH {
+                    return Err(format!(
+                        "{} can be at most {} characters long.",
+                        self.label, MAX_TEXT_LENGTH
+                    ));
+                }
+                if pattern
+                    .as_ref()
+                    .is_some_and(|pattern| !pattern.is_match(value))
+                {
+                    return Err(invalid());
+                }
+            }
+            FieldKind::Select { options } => {
+                if !options.iter().any(|option| option == value) {
+                    return Err(invalid());
+                }
+            }
+            FieldKind::Date { earliest, latest } => {
+                let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid())?;
+                if earliest.is_some_and(|earliest| date < earliest)
+                    || latest.is_some_and(|latest| date > latest)
+                {
+                    return Err(invalid());
+                }
+            }
+            FieldKind::Checkbox => unreachable!(),
+        }
+        Ok(Some(value.to_owned()))
+    }
+}
+
+fn parse_bound(s: &str) -> Result<Option<NaiveDate>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| format!("{} is not a YYYY-MM-DD date.", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CustomField;
+    use claims::{assert_err, assert_ok, assert_ok_eq};
+
+    fn field(field_type: &str, rule: &str, required: bool) -> CustomField {
+        CustomField::parse(
+            "answer".into(),
+            "Answer".into(),
+            field_type,
+            rule.into(),
+            required,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn keys_must_be_lowercase_identifiers_and_not_built_in() {
+        for key in ["company", "date_of_birth", "q2"] {
+            assert_ok!(CustomField::parse(
+                key.into(),
+                "Label".into(),
+                "text",
+                "".into(),
+                false
+            ));
+        }
+        for key in [
+            "",
+            "Company",
+            "2q",
+            "date-of-birth",
+            "email",
+            "list",
+            "delivery",
+            "preferences_url",
+            "language",
+        ] {
+            assert_err!(CustomField::parse(
+                key.into(),
+                "Label".into(),
+                "text",
+                "".into(),
+                false
+            ));
+        }
+    }
+
+    #[test]
+    fn rules_are_checked_against_the_field_type() {
+        for (field_type, rule) in [
+            ("text", "(unclosed"),
+            ("select", " , "),
+            ("date", "yesterday"),
+            ("date", "2025-01-01..soon"),
+            ("colour", ""),
+        ] {
+            assert_err!(CustomField::parse(
+                "answer".into(),
+                "Answer".into(),
+                field_type,
+                rule.into(),
+                false
+            ));
+        }
+    }
+
+    #[test]
+    fn a_blank_optional_field_is_not_stored_and_a_blank_required_one_is_rejected() {
+        assert_ok_eq!(field("text", "", false).validate(Some("  ")), None);
+        assert_err!(field("text", "", true).validate(None));
+    }
+
+    #[test]
+    fn a_text_field_must_match_its_whole_pattern() {
+        let field = field("text", "[A-Z]{2}[0-9]+", false);
+        assert_ok_eq!(field.validate(Some("DE42")), Some("DE42".to_string()));
+        assert_err!(field.validate(Some("DE42x")));
+    }
+
+    #[test]
+    fn a_select_field_only_takes_its_options() {
+        let field = field("select", "developer, designer", true);
+        assert_ok_eq!(
+            field.validate(Some("designer")),
+            Some("designer".to_string())
+        );
+        assert_err!(field.validate(Some("manager")));
+    }
+
+    #[test]
+    fn a_checkbox_is_true_when_ticked() {
+        assert_ok_eq!(
+            field("checkbox", "", false).validate(Some("on")),
+            Some("true".to_string())
+        );
+        assert_ok_eq!(
+            field("checkbox", "", false).validate(None),
+            Some("false".to_string())
+        );
+        assert_err!(field("checkbox", "", true).validate(None));
+    }
+
+    #[test]
+    fn a_date_field_respects_its_bounds() {
+        let field = field("date", "1900-01-01..", false);
+        assert_ok!(field.validate(Some("1990-05-17")));
+        assert_err!(field.validate(Some("1899-12-31")));
+        assert_err!(field.validate(Some("17/05/1990")));
+    }
+}
@@ -0,0 +1,26 @@
+/// How a subscriber wants blog posts: each one as it comes out, or a weekly
+/// round-up. Issues written by hand go to everyone either way.
+#[derive(serde::Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryPreference {
+    #[default]
+    Instant,
+    WeeklyDigest,
+}
+
+impl DeliveryPreference {
+    pub fn parse(s: &str) -> Result<DeliveryPreference, String> {
+        match s {
+            "instant" => Ok(Self::Instant),
+            "weekly_digest" => Ok(Self::WeeklyDigest),
+            s => Err(format!("{} is not a delivery preference.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Instant => "instant",
+            Self::WeeklyDigest => "weekly_digest",
+        }
+    }
+}
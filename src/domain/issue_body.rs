@@ -0,0 +1,68 @@
+use crate::markdown::render_markdown;
+
+/// How the body of an issue was written.
+#[derive(serde::Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyFormat {
+    /// Once, in markdown.
+    Markdown,
+    /// Both parts by hand, for full control over the HTML.
+    #[default]
+    HtmlAndText,
+}
+
+impl BodyFormat {
+    pub fn parse(s: &str) -> Result<BodyFormat, String> {
+        match s {
+            "markdown" => Ok(Self::Markdown),
+            "html_and_text" => Ok(Self::HtmlAndText),
+            s => Err(format!("{} is not a body format.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::HtmlAndText => "html_and_text",
+        }
+    }
+}
+
+/// The body of an issue as the admin wrote it. Only the fields that go with
+/// `format` count, the others are kept so switching back loses nothing.
+#[derive(Debug)]
+pub struct IssueBody {
+    pub format: BodyFormat,
+    pub markdown_content: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+impl IssueBody {
+    /// The plain text and HTML parts that go out, merge tags still in them.
+    pub fn render(&self) -> Result<(String, String), String> {
+        match self.format {
+            BodyFormat::Markdown if self.markdown_content.trim().is_empty() => {
+                Err("Write the issue in the markdown content.".into())
+            }
+            BodyFormat::Markdown => {
+                let rendered = render_markdown(&self.markdown_content);
+                Ok((rendered.text, rendered.html))
+            }
+            BodyFormat::HtmlAndText
+                if self.text_content.trim().is_empty() || self.html_content.trim().is_empty() =>
+            {
+                Err("Fill in both the plain text and the HTML content.".into())
+            }
+            BodyFormat::HtmlAndText => Ok((self.text_content.clone(), self.html_content.clone())),
+        }
+    }
+
+    /// The name of the field each part came from, for error messages.
+    pub fn part_names(&self) -> [&'static str; 2] {
+        match self.format {
+            BodyFormat::Markdown => ["markdown", "markdown"],
+            BodyFormat::HtmlAndText => ["plain text", "HTML"],
+        }
+    }
+}
@@ -0,0 +1,69 @@
+/// The language a subscriber would rather read the newsletter in, stored as
+/// its ISO 639-1 code.
+#[derive(serde::Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "de")]
+    German,
+    #[serde(rename = "fr")]
+    French,
+    #[serde(rename = "es")]
+    Spanish,
+}
+
+impl Language {
+    /// Every language on offer, in the order the preferences page lists them.
+    pub const ALL: [Language; 4] = [
+        Language::English,
+        Language::German,
+        Language::French,
+        Language::Spanish,
+    ];
+
+    pub fn parse(s: &str) -> Result<Language, String> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.as_str() == s)
+            .ok_or_else(|| format!("{} is not a language we offer.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::German => "de",
+            Self::French => "fr",
+            Self::Spanish => "es",
+        }
+    }
+
+    /// The language's name in itself, as speakers look for it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::English => "English",
+            Self::German => "Deutsch",
+            Self::French => "Français",
+            Self::Spanish => "Español",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Language;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_language_parses_from_its_code() {
+        for language in Language::ALL {
+            assert_ok_eq!(Language::parse(language.as_str()), language);
+        }
+    }
+
+    #[test]
+    fn an_unknown_code_is_rejected() {
+        assert_err!(Language::parse("xx"));
+        assert_err!(Language::parse("EN"));
+    }
+}
@@ -0,0 +1,62 @@
+/// The short name a list goes by in signup forms and unsubscribe links,
+/// e.g. `weekly-digest`.
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_valid_length = !s.is_empty() && s.len() <= 50;
+        let has_valid_characters = s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        let has_dangling_dash = s.starts_with('-') || s.ends_with('-');
+        if is_valid_length && has_valid_characters && !has_dangling_dash {
+            Ok(Self(s))
+        } else {
+            Err(format!(
+                "{} is not a valid list slug. Use lowercase letters, digits and dashes.",
+                s
+            ))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_lowercase_dashed_slug_is_valid() {
+        assert_ok!(ListSlug::parse("weekly-digest-2".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_50_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(51)));
+    }
+
+    #[test]
+    fn uppercase_letters_spaces_and_punctuation_are_rejected() {
+        for slug in ["Weekly", "weekly digest", "weekly_digest", "news/letter"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn leading_or_trailing_dashes_are_rejected() {
+        assert_err!(ListSlug::parse("-weekly".to_string()));
+        assert_err!(ListSlug::parse("weekly-".to_string()));
+    }
+}
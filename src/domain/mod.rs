@@ -1,7 +1,23 @@
+mod custom_field;
+mod delivery_preference;
+mod issue_body;
+mod language;
+mod list_slug;
 mod new_subscriber;
+mod segment_expression;
+mod send_time;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 
+pub use custom_field::{CustomField, CustomFieldValue, FieldKind};
+pub use delivery_preference::DeliveryPreference;
+pub use issue_body::{BodyFormat, IssueBody};
+pub use language::Language;
+pub use list_slug::ListSlug;
 pub use new_subscriber::NewSubscriber;
+pub use segment_expression::{Comparison, SegmentExpression, SegmentSubject};
+pub use send_time::SendTime;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
@@ -1,6 +1,11 @@
-use super::{subscriber_email::SubscriberEmail, subscriber_name::SubscriberName};
+use super::{
+    custom_field::CustomFieldValue, delivery_preference::DeliveryPreference,
+    subscriber_email::SubscriberEmail, subscriber_name::SubscriberName,
+};
 
 pub struct NewSubscriber {
     pub name: SubscriberName,
     pub email: SubscriberEmail,
+    pub custom_fields: Vec<CustomFieldValue>,
+    pub delivery_preference: DeliveryPreference,
 }
@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use super::SubscriberTag;
+
+// plenty for a hand-written filter, and it keeps the recursion shallow
+const MAX_LENGTH: usize = 1000;
+const MAX_DEPTH: usize = 32;
+
+const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+/// The filter of a saved segment, e.g.
+/// `tag:rust AND NOT tag:beta-tester AND subscribed >= 2025-01-01`.
+///
+/// Conditions are `tag:<tag>`, `status:<status>`, `field.<key>:<value>` for
+/// the answer to a custom field, and `subscribed` compared with `<`, `<=`,
+/// `>` or `>=` to a `YYYY-MM-DD` date. Values with spaces go in double quotes,
+/// e.g. `field.city:"New York"`. Conditions combine with `AND`, `OR`, `NOT`
+/// and parentheses, `NOT` binding tightest and `OR` loosest.
+#[derive(Debug, PartialEq)]
+pub enum SegmentExpression {
+    Tag(String),
+    Status(String),
+    Field(String, String),
+    Subscribed(Comparison, NaiveDate),
+    Not(Box<SegmentExpression>),
+    And(Box<SegmentExpression>, Box<SegmentExpression>),
+    Or(Box<SegmentExpression>, Box<SegmentExpression>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Before,
+    OnOrBefore,
+    After,
+    OnOrAfter,
+}
+
+/// What a segment gets to look at when deciding whether a subscriber is in.
+pub struct SegmentSubject<'a> {
+    pub status: &'a str,
+    pub subscribed_on: NaiveDate,
+    pub tags: &'a HashSet<String>,
+    pub fields: &'a HashMap<String, String>,
+}
+
+impl SegmentExpression {
+    pub fn parse(s: &str) -> Result<SegmentExpression, String> {
+        if s.len() > MAX_LENGTH {
+            return Err(format!(
+                "A segment can be at most {} characters long.",
+                MAX_LENGTH
+            ));
+        }
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            depth: 0,
+        };
+        let expression = parser.or()?;
+        match parser.next() {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {} in the segment.", token)),
+        }
+    }
+
+    pub fn matches(&self, subject: &SegmentSubject<'_>) -> bool {
+        match self {
+            Self::Tag(tag) => subject.tags.contains(tag),
+            Self::Status(status) => subject.status == status,
+            Self::Field(key, value) => subject.fields.get(key) == Some(value),
+            Self::Subscribed(comparison, date) => match comparison {
+                Comparison::Before => subject.subscribed_on < *date,
+                Comparison::OnOrBefore => subject.subscribed_on <= *date,
+                Comparison::After => subject.subscribed_on > *date,
+                Comparison::OnOrAfter => subject.subscribed_on >= *date,
+            },
+            Self::Not(expression) => !expression.matches(subject),
+            Self::And(left, right) => left.matches(subject) && right.matches(subject),
+            Self::Or(left, right) => left.matches(subject) || right.matches(subject),
+        }
+    }
+
+    /// The custom fields the segment looks at, so they can be checked to exist.
+    pub fn field_keys(&self) -> Vec<&str> {
+        match self {
+            Self::Field(key, _) => vec![key],
+            Self::Not(expression) => expression.field_keys(),
+            Self::And(left, right) | Self::Or(left, right) => {
+                let mut keys = left.field_keys();
+                keys.extend(right.field_keys());
+                keys
+            }
+            Self::Tag(_) | Self::Status(_) | Self::Subscribed(..) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Colon,
+    Compare(Comparison),
+    OpenParen,
+    CloseParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Quoted(text) => write!(f, "`\"{}\"`", text),
+            Token::Colon => write!(f, "`:`"),
+            Token::Compare(Comparison::Before) => write!(f, "`<`"),
+            Token::Compare(Comparison::OnOrBefore) => write!(f, "`<=`"),
+            Token::Compare(Comparison::After) => write!(f, "`>`"),
+            Token::Compare(Comparison::OnOrAfter) => write!(f, "`>=`"),
+            Token::OpenParen => write!(f, "`(`"),
+            Token::CloseParen => write!(f, "`)`"),
+        }
+    }
+}
+
+fn is_word_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ':' => Token::Colon,
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err("A `\"` in the segment is never closed.".into()),
+                    }
+                }
+                Token::Quoted(text)
+            }
+            '<' | '>' => {
+                let or_equal = chars.next_if_eq(&'=').is_some();
+                Token::Compare(match (c, or_equal) {
+                    ('<', false) => Comparison::Before,
+                    ('<', true) => Comparison::OnOrBefore,
+                    ('>', false) => Comparison::After,
+                    _ => Comparison::OnOrAfter,
+                })
+            }
+            c if is_word_character(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| is_word_character(*c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            c => return Err(format!("Unexpected character `{}` in the segment.", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    /// Move past the next token if it is `keyword`, in any case.
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("The segment is nested too deeply.".into());
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<SegmentExpression, String> {
+        let mut left = self.and()?;
+        while self.next_is_keyword("OR") {
+            let right = self.and()?;
+            left = SegmentExpression::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<SegmentExpression, String> {
+        let mut left = self.not()?;
+        while self.next_is_keyword("AND") {
+            let right = self.not()?;
+            left = SegmentExpression::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<SegmentExpression, String> {
+        if !self.next_is_keyword("NOT") {
+            return self.primary();
+        }
+        self.nest()?;
+        let expression = self.not()?;
+        self.depth -= 1;
+        Ok(SegmentExpression::Not(Box::new(expression)))
+    }
+
+    fn primary(&mut self) -> Result<SegmentExpression, String> {
+        match self.next() {
+            Some(Token::OpenParen) => {
+                self.nest()?;
+                let expression = self.or()?;
+                match self.next() {
+                    Some(Token::CloseParen) => {}
+                    _ => return Err("A `(` in the segment is never closed.".into()),
+                }
+                self.depth -= 1;
+                Ok(expression)
+            }
+            Some(Token::Word(field)) => {
+                let field = field.clone();
+                self.condition(&field)
+            }
+            Some(token) => Err(format!(
+                "Expected a condition in the segment, found {}.",
+                token
+            )),
+            None => Err("The segment ends where a condition was expected.".into()),
+        }
+    }
+
+    fn condition(&mut self, field: &str) -> Result<SegmentExpression, String> {
+        match field {
+            "tag" => {
+                let tag = self.value_after_colon(field)?;
+                let tag = SubscriberTag::parse(tag)?;
+                Ok(SegmentExpression::Tag(tag.as_ref().to_owned()))
+            }
+            "status" => {
+                let status = self.value_after_colon(field)?;
+                if !STATUSES.contains(&status.as_str()) {
+                    return Err(format!(
+                        "`{}` is not a status, use one of {}.",
+                        status,
+                        STATUSES.join(", ")
+                    ));
+                }
+                Ok(SegmentExpression::Status(status))
+            }
+            "subscribed" => {
+                let comparison = match self.next() {
+                    Some(Token::Compare(comparison)) => *comparison,
+                    _ => return Err("Compare `subscribed` with <, <=, > or >=.".into()),
+                };
+                let date = self.value("subscribed")?;
+                let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| format!("`{}` is not a YYYY-MM-DD date.", date))?;
+                Ok(SegmentExpression::Subscribed(comparison, date))
+            }
+            field => match field.strip_prefix("field.") {
+                Some(key) if !key.is_empty() => {
+                    let value = self.value_after_colon(field)?;
+                    Ok(SegmentExpression::Field(key.to_owned(), value))
+                }
+                _ => Err(format!(
+                    "Unknown condition `{}`, use tag, status, field.<key> or subscribed.",
+                    field
+                )),
+            },
+        }
+    }
+
+    fn value_after_colon(&mut self, field: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Colon) => self.value(field),
+            _ => Err(format!("Expected a `:` after `{}`.", field)),
+        }
+    }
+
+    fn value(&mut self, field: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => Ok(value.clone()),
+            _ => Err(format!("Expected a value for `{}`.", field)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use chrono::NaiveDate;
+    use claims::{assert_err, assert_ok};
+
+    use super::{SegmentExpression, SegmentSubject};
+
+    fn matches(expression: &str, status: &str, subscribed_on: &str, tags: &[&str]) -> bool {
+        let tags = tags
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect::<HashSet<_>>();
+        let fields = HashMap::from([
+            ("city".to_string(), "New York".to_string()),
+            ("role".to_string(), "developer".to_string()),
+        ]);
+        let subject = SegmentSubject {
+            status,
+            subscribed_on: subscribed_on.parse::<NaiveDate>().unwrap(),
+            tags: &tags,
+            fields: &fields,
+        };
+        SegmentExpression::parse(expression)
+            .unwrap()
+            .matches(&subject)
+    }
+
+    #[test]
+    fn a_tag_condition_matches_tagged_subscribers() {
+        assert!(matches("tag:rust", "confirmed", "2025-01-01", &["rust"]));
+        assert!(!matches("tag:rust", "confirmed", "2025-01-01", &["go"]));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        let expression = "tag:a OR tag:b AND NOT tag:c";
+        assert!(matches(expression, "confirmed", "2025-01-01", &["a", "c"]));
+        assert!(matches(expression, "confirmed", "2025-01-01", &["b"]));
+        assert!(!matches(expression, "confirmed", "2025-01-01", &["b", "c"]));
+    }
+
+    #[test]
+    fn parentheses_group_conditions() {
+        let expression = "(tag:a OR tag:b) and not tag:c";
+        assert!(!matches(expression, "confirmed", "2025-01-01", &["a", "c"]));
+        assert!(matches(expression, "confirmed", "2025-01-01", &["a"]));
+    }
+
+    #[test]
+    fn subscribed_compares_the_signup_day() {
+        assert!(matches(
+            "subscribed >= 2025-01-01",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+        assert!(!matches(
+            "subscribed > 2025-01-01",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+        assert!(matches(
+            "subscribed<2025-01-02",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn status_matches_the_address_status() {
+        assert!(matches("status:confirmed", "confirmed", "2025-01-01", &[]));
+        assert!(!matches(
+            "status:unsubscribed",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn field_conditions_compare_the_answer() {
+        assert!(matches(
+            "field.role:developer",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+        assert!(matches(
+            r#"field.city:"New York""#,
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+        assert!(!matches(
+            "field.role:designer",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+        assert!(!matches(
+            "field.company:acme",
+            "confirmed",
+            "2025-01-01",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn field_keys_lists_every_field_condition() {
+        let expression =
+            SegmentExpression::parse("field.role:developer OR NOT (tag:a AND field.city:x)")
+                .unwrap();
+        assert_eq!(expression.field_keys(), ["role", "city"]);
+    }
+
+    #[test]
+    fn malformed_segments_are_rejected() {
+        for expression in [
+            "",
+            "tag:",
+            "tag rust",
+            "tag:Rust",
+            "status:gone",
+            "subscribed:2025-01-01",
+            "subscribed > yesterday",
+            "colour:blue",
+            "field.:blue",
+            "field.city",
+            r#"field.city:"New York"#,
+            "(tag:rust",
+            "tag:rust)",
+            "tag:rust tag:go",
+            "tag:rust AND",
+            "tag:rust; DROP TABLE subscriptions",
+        ] {
+            assert_err!(SegmentExpression::parse(expression), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let expression = format!("{}tag:rust{}", "(".repeat(40), ")".repeat(40));
+        assert_err!(SegmentExpression::parse(&expression));
+        let expression = format!("{}tag:rust", "NOT ".repeat(40));
+        assert_err!(SegmentExpression::parse(&expression));
+        let expression = format!("{}tag:rust{}", "(".repeat(10), ")".repeat(10));
+        assert_ok!(SegmentExpression::parse(&expression));
+    }
+}
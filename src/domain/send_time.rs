@@ -0,0 +1,95 @@
+use chrono::{DateTime, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// When a scheduled issue goes out, picked as a wall-clock time in the
+/// audience's time zone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendTime {
+    pub at: DateTime<Utc>,
+    pub timezone: Tz,
+}
+
+impl SendTime {
+    /// `local` is what a `datetime-local` input sends, e.g. `2025-06-16T09:00`,
+    /// and `timezone` an IANA name such as `Europe/Berlin`.
+    pub fn parse(local: &str, timezone: &str) -> Result<SendTime, String> {
+        let timezone = timezone.trim().parse::<Tz>().map_err(|_| {
+            format!(
+                "{} is not a time zone, try one like Europe/Berlin.",
+                timezone
+            )
+        })?;
+        let local = local.trim();
+        let naive = NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M:%S"))
+            .map_err(|_| format!("{} is not a date and time.", local))?;
+        let at = match timezone.from_local_datetime(&naive) {
+            LocalResult::Single(at) => at,
+            // the clocks go back and the time comes round twice, take the first
+            LocalResult::Ambiguous(earliest, _) => earliest,
+            LocalResult::None => {
+                return Err(format!(
+                    "{} never happens in {}, the clocks skip it.",
+                    naive.format("%Y-%m-%d %H:%M"),
+                    timezone
+                ))
+            }
+        };
+        Ok(SendTime {
+            at: at.with_timezone(&Utc),
+            timezone,
+        })
+    }
+
+    /// The wall-clock time in the zone it was picked in.
+    pub fn local(&self) -> NaiveDateTime {
+        self.at.with_timezone(&self.timezone).naive_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SendTime;
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_local_time_is_converted_to_utc() {
+        let send_time = SendTime::parse("2025-06-16T09:00", "Europe/Berlin").unwrap();
+        assert_eq!(
+            send_time.at,
+            Utc.with_ymd_and_hms(2025, 6, 16, 7, 0, 0).unwrap()
+        );
+        assert_eq!(send_time.local().to_string(), "2025-06-16 09:00:00");
+    }
+
+    #[test]
+    fn seconds_are_accepted() {
+        assert_ok!(SendTime::parse("2025-06-16T09:00:30", "UTC"));
+    }
+
+    #[test]
+    fn an_unknown_time_zone_is_rejected() {
+        assert_err!(SendTime::parse("2025-06-16T09:00", "Mars/Olympus_Mons"));
+    }
+
+    #[test]
+    fn a_malformed_time_is_rejected() {
+        assert_err!(SendTime::parse("next monday", "UTC"));
+        assert_err!(SendTime::parse("2025-06-31T09:00", "UTC"));
+    }
+
+    #[test]
+    fn a_time_the_clocks_skip_is_rejected() {
+        assert_err!(SendTime::parse("2025-03-30T02:30", "Europe/Berlin"));
+    }
+
+    #[test]
+    fn a_time_that_happens_twice_goes_out_the_first_time() {
+        let send_time = SendTime::parse("2025-10-26T02:30", "Europe/Berlin").unwrap();
+        assert_eq!(
+            send_time.at,
+            Utc.with_ymd_and_hms(2025, 10, 26, 0, 30, 0).unwrap()
+        );
+    }
+}
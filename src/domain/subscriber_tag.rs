@@ -0,0 +1,49 @@
+/// A label an admin puts on subscribers to single them out later, e.g. `rust`
+/// or `beta-tester`.
+#[derive(Debug)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let is_valid_length = !s.is_empty() && s.len() <= 50;
+        let has_valid_characters = s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_valid_length && has_valid_characters {
+            Ok(Self(s))
+        } else {
+            Err(format!(
+                "{} is not a valid tag. Use lowercase letters, digits and dashes.",
+                s
+            ))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_lowercase_dashed_tag_is_valid() {
+        assert_ok!(SubscriberTag::parse("beta-tester".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SubscriberTag::parse("".to_string()));
+    }
+
+    #[test]
+    fn spaces_and_uppercase_letters_are_rejected() {
+        assert_err!(SubscriberTag::parse("beta tester".to_string()));
+        assert_err!(SubscriberTag::parse("Rust".to_string()));
+    }
+}
@@ -0,0 +1,447 @@
+//! Issues still being written. Publishing one, by hand or on schedule,
+//! turns it into a `newsletter_issues` row and deletes the draft.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{BodyFormat, DeliveryPreference, IssueBody, SendTime};
+
+pub struct DraftSummary {
+    pub uuid: String,
+    pub title: String,
+    pub updated_at: String,
+    // when it goes out, in the zone it was scheduled in, empty if it doesn't
+    pub scheduled_for: String,
+}
+
+pub struct Draft {
+    pub id: Uuid,
+    pub title: String,
+    pub body: IssueBody,
+    // slugs of the lists it is meant for
+    pub lists: Vec<String>,
+    pub segment_id: Option<Uuid>,
+    pub hidden_from_archive: bool,
+    // drafted by `blog_posts::announce_new_posts`
+    pub announces_blog_post: bool,
+    pub schedule: Option<SendTime>,
+    // why the scheduler gave up on it
+    pub schedule_error: Option<String>,
+}
+
+#[tracing::instrument(name = "Get all drafts", skip(pool))]
+pub async fn get_drafts(pool: &SqlitePool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT uuid, title, updated_at, scheduled_at, schedule_timezone
+        FROM newsletter_drafts
+        ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            let schedule = parse_schedule(r.scheduled_at, r.schedule_timezone)?;
+            Ok(DraftSummary {
+                uuid: r.uuid,
+                title: r.title,
+                updated_at: r.updated_at,
+                scheduled_for: schedule
+                    .map(|schedule| {
+                        format!(
+                            "{} {}",
+                            schedule.local().format("%Y-%m-%d %H:%M"),
+                            schedule.timezone
+                        )
+                    })
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Get a draft", skip(connection))]
+pub async fn get_draft(
+    connection: &mut SqliteConnection,
+    draft_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    let draft_id_str = draft_id.to_string();
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT
+            title,
+            format,
+            markdown_content,
+            text_content,
+            html_content,
+            segment_id,
+            hidden_from_archive AS "hidden_from_archive: bool",
+            scheduled_at,
+            schedule_timezone,
+            schedule_error,
+            EXISTS (
+                SELECT 1 FROM blog_announcements WHERE draft_id = $1
+            ) AS "announces_blog_post!: bool"
+        FROM newsletter_drafts
+        WHERE uuid = $1
+        "#,
+        draft_id_str,
+    )
+    .fetch_optional(&mut *connection)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM newsletter_draft_lists dl
+        JOIN lists l ON l.uuid = dl.list_id
+        WHERE dl.draft_id = $1
+        ORDER BY l.id
+        "#,
+        draft_id_str,
+    )
+    .fetch_all(&mut *connection)
+    .await?
+    .into_iter()
+    .map(|r| r.slug)
+    .collect();
+    Ok(Some(Draft {
+        id: draft_id,
+        title: r.title,
+        body: IssueBody {
+            format: BodyFormat::parse(&r.format).map_err(anyhow::Error::msg)?,
+            markdown_content: r.markdown_content,
+            text_content: r.text_content,
+            html_content: r.html_content,
+        },
+        lists,
+        segment_id: r.segment_id.as_deref().map(Uuid::try_parse).transpose()?,
+        hidden_from_archive: r.hidden_from_archive,
+        announces_blog_post: r.announces_blog_post,
+        schedule: parse_schedule(r.scheduled_at, r.schedule_timezone)?,
+        schedule_error: r.schedule_error,
+    }))
+}
+
+impl Draft {
+    /// Who among the audience it is for: a blog post on its own is not for
+    /// digest subscribers, they get it with the rest of the week's posts.
+    pub fn delivery(&self) -> Option<DeliveryPreference> {
+        self.announces_blog_post
+            .then_some(DeliveryPreference::Instant)
+    }
+}
+
+fn parse_schedule(
+    scheduled_at: Option<String>,
+    timezone: Option<String>,
+) -> Result<Option<SendTime>, anyhow::Error> {
+    let Some(scheduled_at) = scheduled_at else {
+        return Ok(None);
+    };
+    let timezone = timezone.as_deref().unwrap_or("UTC");
+    Ok(Some(SendTime {
+        at: scheduled_at.parse()?,
+        timezone: timezone
+            .parse::<Tz>()
+            .map_err(|_| anyhow::anyhow!("Unknown time zone {}", timezone))?,
+    }))
+}
+
+/// Insert the draft if `draft_id` is new, overwrite it otherwise.
+#[tracing::instrument(name = "Save a draft", skip(pool, body))]
+pub async fn save_draft(
+    pool: &SqlitePool,
+    draft_id: Uuid,
+    title: &str,
+    body: &IssueBody,
+    list_ids: &[Uuid],
+    segment_id: Option<Uuid>,
+    hidden_from_archive: bool,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    write_draft(
+        &mut transaction,
+        draft_id,
+        title,
+        body,
+        list_ids,
+        segment_id,
+        hidden_from_archive,
+    )
+    .await?;
+    transaction.commit().await
+}
+
+/// `save_draft` as part of a larger transaction.
+pub async fn write_draft(
+    transaction: &mut Transaction<'_, Sqlite>,
+    draft_id: Uuid,
+    title: &str,
+    body: &IssueBody,
+    list_ids: &[Uuid],
+    segment_id: Option<Uuid>,
+    hidden_from_archive: bool,
+) -> Result<(), sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    let format = body.format.as_str();
+    let segment_id = segment_id.map(|segment_id| segment_id.to_string());
+    let now = Utc::now().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            uuid,
+            title,
+            format,
+            markdown_content,
+            text_content,
+            html_content,
+            segment_id,
+            hidden_from_archive,
+            created_at,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9)
+        ON CONFLICT (uuid) DO UPDATE SET
+            title = excluded.title,
+            format = excluded.format,
+            markdown_content = excluded.markdown_content,
+            text_content = excluded.text_content,
+            html_content = excluded.html_content,
+            segment_id = excluded.segment_id,
+            hidden_from_archive = excluded.hidden_from_archive,
+            updated_at = excluded.updated_at
+        "#,
+        draft_id,
+        title,
+        format,
+        body.markdown_content,
+        body.text_content,
+        body.html_content,
+        segment_id,
+        hidden_from_archive,
+        now,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM newsletter_draft_lists WHERE draft_id = $1"#,
+        draft_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    for list_id in list_ids {
+        let list_id = list_id.to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_draft_lists (draft_id, list_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            draft_id,
+            list_id,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(transaction))]
+pub async fn delete_draft(
+    transaction: &mut Transaction<'_, Sqlite>,
+    draft_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    sqlx::query!(
+        r#"DELETE FROM newsletter_draft_lists WHERE draft_id = $1"#,
+        draft_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(r#"DELETE FROM newsletter_drafts WHERE uuid = $1"#, draft_id)
+        .execute(&mut **transaction)
+        .await?;
+    Ok(())
+}
+
+/// Have the scheduler publish the draft at `send_time`, replacing any earlier
+/// schedule. Returns `false` if there is no such draft.
+#[tracing::instrument(name = "Schedule a draft", skip(pool))]
+pub async fn schedule_draft(
+    pool: &SqlitePool,
+    draft_id: Uuid,
+    send_time: &SendTime,
+) -> Result<bool, sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    let scheduled_at = send_time.at.to_string();
+    let timezone = send_time.timezone.name();
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET
+            scheduled_at = $2,
+            schedule_timezone = $3,
+            schedule_error = NULL,
+            schedule_failures = 0
+        WHERE uuid = $1
+        "#,
+        draft_id,
+        scheduled_at,
+        timezone,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// Take the draft off the schedule. Returns `false` if there is no such
+/// draft, e.g. because it has gone out already.
+#[tracing::instrument(name = "Unschedule a draft", skip(pool))]
+pub async fn unschedule_draft(pool: &SqlitePool, draft_id: Uuid) -> Result<bool, sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET scheduled_at = NULL, schedule_error = NULL, schedule_failures = 0
+        WHERE uuid = $1
+        "#,
+        draft_id,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// The drafts whose time has come by `now`, earliest first.
+#[tracing::instrument(name = "Get due drafts", skip(pool))]
+pub async fn get_due_drafts(
+    pool: &SqlitePool,
+    now: DateTime<Utc>,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let now = now.to_string();
+    let rows = sqlx::query!(
+        r#"
+        SELECT uuid
+        FROM newsletter_drafts
+        WHERE scheduled_at <= $1
+        ORDER BY scheduled_at, id
+        "#,
+        now,
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|r| Ok(Uuid::try_parse(&r.uuid)?))
+        .collect()
+}
+
+/// How long until the next scheduled draft is due, `None` if none is.
+pub async fn time_until_next_draft(pool: &SqlitePool) -> Result<Option<Duration>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT MIN(scheduled_at) AS "scheduled_at: String"
+        FROM newsletter_drafts
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    let Some(scheduled_at) = r.scheduled_at else {
+        return Ok(None);
+    };
+    let scheduled_at: DateTime<Utc> = scheduled_at.parse()?;
+    Ok(Some(
+        (scheduled_at - Utc::now()).to_std().unwrap_or_default(),
+    ))
+}
+
+/// Take a due draft off the schedule as part of publishing it.
+///
+/// Only one transaction can do so: a concurrent or later one finds it no
+/// longer due, and gets `false`.
+pub async fn claim_due_draft(
+    transaction: &mut Transaction<'_, Sqlite>,
+    draft_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    let now = now.to_string();
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET scheduled_at = NULL
+        WHERE uuid = $1 AND scheduled_at <= $2
+        "#,
+        draft_id,
+        now,
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// Leave a note on a draft the scheduler could not publish.
+pub async fn record_schedule_error(
+    transaction: &mut Transaction<'_, Sqlite>,
+    draft_id: Uuid,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    sqlx::query!(
+        r#"UPDATE newsletter_drafts SET schedule_error = $2 WHERE uuid = $1"#,
+        draft_id,
+        error,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// How often publishing the draft failed since it was scheduled.
+pub async fn get_schedule_failures(
+    transaction: &mut Transaction<'_, Sqlite>,
+    draft_id: Uuid,
+) -> Result<u32, anyhow::Error> {
+    let draft_id = draft_id.to_string();
+    let n_failures = sqlx::query!(
+        r#"SELECT schedule_failures FROM newsletter_drafts WHERE uuid = $1"#,
+        draft_id,
+    )
+    .fetch_one(&mut **transaction)
+    .await?
+    .schedule_failures;
+    Ok(n_failures.try_into()?)
+}
+
+/// Count a failed attempt at publishing a due draft and try it again at
+/// `retry_at`.
+pub async fn postpone_failed_draft(
+    transaction: &mut Transaction<'_, Sqlite>,
+    draft_id: Uuid,
+    retry_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let draft_id = draft_id.to_string();
+    let retry_at = retry_at.to_string();
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET schedule_failures = schedule_failures + 1, scheduled_at = $2
+        WHERE uuid = $1
+        "#,
+        draft_id,
+        retry_at,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
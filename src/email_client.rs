@@ -1,221 +0,0 @@
-use reqwest::{Client, Url};
-use secrecy::{ExposeSecret, SecretString};
-use serde::Serialize;
-
-use crate::domain::SubscriberEmail;
-
-pub struct EmailClient {
-    http_client: reqwest::Client,
-    sender: SubscriberEmail,
-    base_url: String,
-    authorization_token: SecretString,
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct SendEmailRequest<'a> {
-    from: &'a str,
-    to: &'a str,
-    subject: &'a str,
-    html_body: &'a str,
-    text_body: &'a str,
-}
-
-impl EmailClient {
-    pub fn new(
-        sender: SubscriberEmail,
-        base_url: String,
-        authorization_token: SecretString,
-        timeout: std::time::Duration,
-    ) -> Self {
-        Self {
-            http_client: Client::builder().timeout(timeout).build().unwrap(),
-            sender,
-            base_url,
-            authorization_token,
-        }
-    }
-
-    pub async fn send_email(
-        &self,
-        recipient: &SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let base = Url::parse(&self.base_url).expect("url from config is wrong");
-        let url = base
-            .join("email")
-            .expect("can't append email to email url from config");
-        let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: text_content,
-            text_body: html_content,
-        };
-        self.http_client
-            .post(url)
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use claims::{assert_err, assert_ok};
-    use fake::{
-        faker::{
-            internet::en::SafeEmail,
-            lorem::en::{Paragraph, Sentence},
-        },
-        Fake, Faker,
-    };
-    use secrecy::SecretString;
-    use wiremock::{
-        matchers::{any, header, header_exists, method, path},
-        Mock, MockServer, ResponseTemplate,
-    };
-
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
-
-    /// Generate a random email subject
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
-
-    /// Generate a random email content
-    fn content() -> String {
-        Paragraph(1..10).fake()
-    }
-
-    /// Generate a random subscriber email
-    fn email() -> SubscriberEmail {
-        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
-    }
-
-    /// Get a test instance of `EmailClient`.
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
-            email(),
-            base_url,
-            SecretString::from(Faker.fake::<String>()),
-            std::time::Duration::from_millis(200),
-        )
-    }
-
-    struct SendEmailBodyMatcher;
-
-    impl wiremock::Match for SendEmailBodyMatcher {
-        fn matches(&self, request: &wiremock::Request) -> bool {
-            match request.body_json::<serde_json::Value>() {
-                Ok(body) => {
-                    if body.get("From").is_some()
-                        && body.get("To").is_some()
-                        && body.get("Subject").is_some()
-                        && body.get("HtmlBody").is_some()
-                        && body.get("TextBody").is_some()
-                    {
-                        return true;
-                    }
-                    return false;
-                }
-                Err(_) => return false,
-            };
-        }
-    }
-
-    #[tokio::test]
-    async fn send_email_fires_a_request_to_base_url() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(header_exists("X-Postmark-Server-Token"))
-            .and(header("Content-Type", "application/json"))
-            .and(path("/email"))
-            .and(method("POST"))
-            .and(SendEmailBodyMatcher)
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-        // Act
-        let _ = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-        // Assert
-    }
-
-    #[tokio::test]
-    async fn send_email_succeeds_if_the_server_returns_200() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(any())
-            .respond_with(ResponseTemplate::new(200))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        // Assert
-        assert_ok!(outcome);
-    }
-
-    #[tokio::test]
-    async fn send_email_fails_if_the_server_returns_500() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        Mock::given(any())
-            // Not a 200 anymore!
-            .respond_with(ResponseTemplate::new(500))
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        // Assert
-        assert_err!(outcome);
-    }
-
-    #[tokio::test]
-    async fn send_email_times_out_if_the_server_takes_too_long() {
-        // Arrange
-        let mock_server = MockServer::start().await;
-        let email_client = email_client(mock_server.uri());
-
-        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
-        Mock::given(any())
-            .respond_with(response)
-            .expect(1)
-            .mount(&mock_server)
-            .await;
-
-        // Act
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
-            .await;
-
-        // Assert
-        assert_err!(outcome);
-    }
-}
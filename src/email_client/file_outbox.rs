@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{message, Email, EmailError, EmailTransport};
+
+/// Writes every message to `directory` as an `.eml` file instead of sending
+/// it, handy during development. Any mail client can open them.
+pub struct FileOutboxTransport {
+    directory: PathBuf,
+}
+
+impl FileOutboxTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileOutboxTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let message = message::render(email)?;
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .context("Failed to create the outbox directory.")
+            .map_err(EmailError::Transient)?;
+        // timestamp first, so a directory listing shows them in sending order
+        let file_name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+            Uuid::new_v4()
+        );
+        tokio::fs::write(self.directory.join(file_name), message)
+            .await
+            .context("Failed to write the message to the outbox.")
+            .map_err(EmailError::Transient)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    use super::FileOutboxTransport;
+    use crate::{domain::SubscriberEmail, email_client::EmailClient};
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_to_the_outbox() {
+        // Arrange
+        let directory = std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()));
+        let email_client = EmailClient::new(
+            SubscriberEmail::parse("newzletter@example.com".to_owned()).unwrap(),
+            FileOutboxTransport::new(&directory),
+        );
+        let recipient = SubscriberEmail::parse("ursula@example.com".to_owned()).unwrap();
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient, "Hello", "<p>Hi</p>", "Hi")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let message = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(message.contains("To: ursula@example.com\r\n"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
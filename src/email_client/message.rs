@@ -0,0 +1,193 @@
+//! Rendering an [`Email`] as an RFC 5322 message, for the transports that
+//! talk raw internet mail instead of a provider's JSON API.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{Email, EmailError};
+
+// RFC 2045 caps quoted-printable lines at 76 characters, soft break included
+const MAX_ENCODED_LINE_LENGTH: usize = 76;
+
+/// The full message, headers and `multipart/alternative` body, with CRLF line endings.
+pub fn render(email: &Email<'_>) -> Result<String, EmailError> {
+    let boundary = format!("newzletter-{}", Uuid::new_v4().simple());
+    let domain = email
+        .from
+        .as_ref()
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or("localhost");
+
+    let mut headers = vec![
+        ("From".to_owned(), email.from.as_ref().to_owned()),
+        ("To".to_owned(), email.to.as_ref().to_owned()),
+        ("Subject".to_owned(), encode_header_value(email.subject)),
+        ("Date".to_owned(), Utc::now().to_rfc2822()),
+        (
+            "Message-ID".to_owned(),
+            format!("<{}@{}>", Uuid::new_v4(), domain),
+        ),
+        ("MIME-Version".to_owned(), "1.0".to_owned()),
+    ];
+    for header in email.headers {
+        headers.push((header.name.clone(), header.value.clone()));
+    }
+    headers.push((
+        "Content-Type".to_owned(),
+        format!("multipart/alternative; boundary=\"{}\"", boundary),
+    ));
+
+    let mut message = String::new();
+    for (name, value) in headers {
+        // a stray newline would let whoever controls the value inject headers
+        if [&name, &value].iter().any(|s| s.contains(['\r', '\n'])) {
+            return Err(EmailError::Permanent(anyhow::anyhow!(
+                "The `{}` header contains a line break.",
+                name
+            )));
+        }
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    message.push_str("\r\n");
+    for (content_type, body) in [
+        ("text/plain", email.text_body),
+        ("text/html", email.html_body),
+    ] {
+        message.push_str(&format!(
+            "--{}\r\nContent-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n{}\r\n",
+            boundary,
+            content_type,
+            quoted_printable(body)
+        ));
+    }
+    message.push_str(&format!("--{}--\r\n", boundary));
+    Ok(message)
+}
+
+/// RFC 2047 encoded-word for anything that isn't plain ASCII.
+fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_owned()
+    } else {
+        format!("=?utf-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+/// RFC 2045 quoted-printable, so the `.eml` files stay readable in an editor.
+fn quoted_printable(body: &str) -> String {
+    let mut encoded = String::new();
+    for (i, line) in body.lines().enumerate() {
+        if i > 0 {
+            encoded.push_str("\r\n");
+        }
+        let bytes = line.as_bytes();
+        let mut line_length = 0;
+        for (j, &byte) in bytes.iter().enumerate() {
+            let is_last = j == bytes.len() - 1;
+            let chunk = match byte {
+                // trailing whitespace gets eaten by mail servers, so escape it
+                b' ' | b'\t' if is_last => format!("={:02X}", byte),
+                b' ' | b'\t' | 33..=60 | 62..=126 => (byte as char).to_string(),
+                _ => format!("={:02X}", byte),
+            };
+            if line_length + chunk.len() > MAX_ENCODED_LINE_LENGTH - 1 {
+                encoded.push_str("=\r\n");
+                line_length = 0;
+            }
+            line_length += chunk.len();
+            encoded.push_str(&chunk);
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use super::{quoted_printable, render};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{Email, EmailHeader},
+    };
+
+    fn email_address(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn a_rendered_message_carries_both_bodies_and_the_extra_headers() {
+        let from = email_address("newzletter@example.com");
+        let to = email_address("ursula@example.com");
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+        let email = Email {
+            from: &from,
+            to: &to,
+            subject: "Hello",
+            html_body: "<p>Hi</p>",
+            text_body: "Hi",
+            headers: &headers,
+        };
+
+        let message = render(&email).unwrap();
+
+        assert!(message.contains("From: newzletter@example.com\r\n"));
+        assert!(message.contains("To: ursula@example.com\r\n"));
+        assert!(message.contains("Subject: Hello\r\n"));
+        assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"));
+        assert!(message.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(message.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(message.contains("<p>Hi</p>"));
+    }
+
+    #[test]
+    fn line_breaks_in_header_values_are_rejected() {
+        let from = email_address("newzletter@example.com");
+        let to = email_address("ursula@example.com");
+        let email = Email {
+            from: &from,
+            to: &to,
+            subject: "Hello\r\nBcc: everyone@example.com",
+            html_body: "",
+            text_body: "",
+            headers: &[],
+        };
+
+        assert_err!(render(&email));
+    }
+
+    #[test]
+    fn non_ascii_subjects_are_encoded() {
+        let from = email_address("newzletter@example.com");
+        let to = email_address("ursula@example.com");
+        let email = Email {
+            from: &from,
+            to: &to,
+            subject: "Grüße",
+            html_body: "",
+            text_body: "",
+            headers: &[],
+        };
+
+        let message = render(&email).unwrap();
+
+        assert!(message.contains("Subject: =?utf-8?B?R3LDvMOfZQ==?=\r\n"));
+    }
+
+    #[test]
+    fn quoted_printable_escapes_equal_signs_and_non_ascii() {
+        assert_eq!(quoted_printable("a=b ü"), "a=3Db =C3=BC");
+    }
+
+    #[test]
+    fn quoted_printable_keeps_lines_within_the_limit() {
+        let encoded = quoted_printable(&"a".repeat(200));
+        assert!(encoded.split("\r\n").all(|line| line.len() <= 76));
+        assert_eq!(encoded.replace("=\r\n", ""), "a".repeat(200));
+    }
+}
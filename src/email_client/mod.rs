@@ -0,0 +1,160 @@
+mod file_outbox;
+mod message;
+mod postmark;
+mod smtp;
+
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::{domain::SubscriberEmail, routes::error_chain_fmt};
+
+pub use file_outbox::FileOutboxTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+/// An extra header to set on a single outgoing message.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// Everything a transport needs to put a single message on the wire.
+pub struct Email<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub headers: &'a [EmailHeader],
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailError {
+    /// The other end may well take it later: overloaded, rate limited, unreachable.
+    #[error(transparent)]
+    Transient(anyhow::Error),
+    /// Rejected for good, e.g. an unknown recipient or a malformed message.
+    #[error(transparent)]
+    Permanent(anyhow::Error),
+}
+
+impl EmailError {
+    /// A copy for each message that went down with the same failed call.
+    fn duplicate(&self) -> Self {
+        match self {
+            Self::Transient(e) => Self::Transient(anyhow::anyhow!("{:#}", e)),
+            Self::Permanent(e) => Self::Permanent(anyhow::anyhow!("{:#}", e)),
+        }
+    }
+}
+
+impl std::fmt::Debug for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// One message of a [`EmailClient::send_batch`] call, the sender is filled in by the client.
+pub struct BatchEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader],
+}
+
+/// The way out of the application for an email.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError>;
+
+    /// One result per email, in the same order as `emails`.
+    ///
+    /// Transports without a bulk API just send them one after the other.
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), EmailError>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send(email).await);
+        }
+        results
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Arc<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Arc::new(transport),
+        }
+    }
+
+    pub fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), EmailError> {
+        let email = Email {
+            from: &self.sender,
+            to: recipient,
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+        self.transport.send(&email).await
+    }
+
+    /// Send many emails in as few calls as the transport allows.
+    ///
+    /// The results line up with `emails`, so the caller can tell exactly which
+    /// recipients failed and retry those on their own.
+    pub async fn send_batch(&self, emails: &[BatchEmail<'_>]) -> Vec<Result<(), EmailError>> {
+        let emails = emails
+            .iter()
+            .map(|email| Email {
+                from: &self.sender,
+                to: email.recipient,
+                subject: email.subject,
+                html_body: email.html_content,
+                text_body: email.text_content,
+                headers: email.headers,
+            })
+            .collect::<Vec<_>>();
+        self.transport.send_batch(&emails).await
+    }
+}
@@ -0,0 +1,607 @@
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+
+use super::{Email, EmailError, EmailHeader, EmailTransport};
+
+// the most messages Postmark accepts in a single `/email/batch` call
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Sends through Postmark's `/email` HTTP API.
+pub struct PostmarkTransport {
+    http_client: reqwest::Client,
+    base_url: String,
+    authorization_token: SecretString,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+impl<'a> From<&'a Email<'a>> for SendEmailRequest<'a> {
+    fn from(email: &'a Email<'a>) -> Self {
+        Self {
+            from: email.from.as_ref(),
+            to: email.to.as_ref(),
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers: email.headers,
+        }
+    }
+}
+
+/// Postmark's verdict on one message of a batch.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResult {
+    error_code: i64,
+    message: String,
+}
+
+impl BatchResult {
+    fn into_result(self) -> Result<(), EmailError> {
+        let e = || anyhow::anyhow!("Postmark error {}: {}", self.error_code, self.message);
+        match self.error_code {
+            0 => Ok(()),
+            // "Service is down for maintenance"
+            100 => Err(EmailError::Transient(e())),
+            // e.g. 300 invalid email request, 406 inactive recipient
+            _ => Err(EmailError::Permanent(e())),
+        }
+    }
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: SecretString,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            base_url,
+            authorization_token,
+        }
+    }
+
+    async fn send_chunk(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<(), EmailError>>, EmailError> {
+        let base = Url::parse(&self.base_url).expect("url from config is wrong");
+        let url = base
+            .join("email/batch")
+            .expect("can't append email/batch to email url from config");
+        let request_body = emails
+            .iter()
+            .map(SendEmailRequest::from)
+            .collect::<Vec<_>>();
+        let response = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        // the batch was accepted, so a body we can't read is no reason to
+        // send it again
+        let batch_results: Vec<BatchResult> = response.json().await.map_err(|e| {
+            EmailError::Permanent(
+                anyhow::Error::new(e).context("Failed to read Postmark's batch results."),
+            )
+        })?;
+        // results come back in the order the messages were sent. If the counts
+        // don't match we can't tell who got what, and retrying could mean
+        // sending some of them twice, so leave it to a human.
+        if batch_results.len() != emails.len() {
+            return Err(EmailError::Permanent(anyhow::anyhow!(
+                "Postmark returned {} results for a batch of {} messages.",
+                batch_results.len(),
+                emails.len()
+            )));
+        }
+        Ok(batch_results
+            .into_iter()
+            .map(BatchResult::into_result)
+            .collect())
+    }
+}
+
+impl From<reqwest::Error> for EmailError {
+    fn from(e: reqwest::Error) -> Self {
+        match e.status() {
+            Some(status) if status.is_server_error() => Self::Transient(e.into()),
+            Some(status) if status == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Self::Transient(e.into())
+            }
+            Some(_) => Self::Permanent(e.into()),
+            // no status means we never got an answer, most likely a timeout
+            None => Self::Transient(e.into()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let base = Url::parse(&self.base_url).expect("url from config is wrong");
+        let url = base
+            .join("email")
+            .expect("can't append email to email url from config");
+        let request_body = SendEmailRequest::from(email);
+        self.http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), EmailError>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                // the whole call failed, so every message in it shares its fate
+                Err(e) => results.extend(chunk.iter().map(|_| Err(e.duplicate()))),
+            }
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::SecretString;
+    use wiremock::{
+        matchers::{any, header, header_exists, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{BatchEmail, EmailClient, EmailError, EmailHeader, PostmarkTransport},
+    };
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    /// Get a test instance of `EmailClient`.
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            PostmarkTransport::new(
+                base_url,
+                SecretString::from(Faker.fake::<String>()),
+                std::time::Duration::from_millis(200),
+            ),
+        )
+    }
+
+    /// Answers a batch call the way Postmark does: one result per message,
+    /// failing the ones sent to `rejected` as inactive recipients.
+    struct PostmarkBatchResponder {
+        rejected: Vec<String>,
+    }
+
+    impl wiremock::Respond for PostmarkBatchResponder {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let messages: Vec<serde_json::Value> = request.body_json().unwrap();
+            let results = messages
+                .iter()
+                .map(|message| {
+                    if self.rejected.iter().any(|r| message["To"] == r.as_str()) {
+                        serde_json::json!({
+                            "ErrorCode": 406,
+                            "Message": "You tried to send to a recipient that has been marked as inactive."
+                        })
+                    } else {
+                        serde_json::json!({ "ErrorCode": 0, "Message": "OK" })
+                    }
+                })
+                .collect::<Vec<_>>();
+            ResponseTemplate::new(200).set_body_json(results)
+        }
+    }
+
+    /// Send the same random content to each of `recipients` in one batch.
+    async fn send_batch(
+        email_client: &EmailClient,
+        recipients: &[SubscriberEmail],
+    ) -> Vec<Result<(), EmailError>> {
+        let (subject, content) = (subject(), content());
+        let emails = recipients
+            .iter()
+            .map(|recipient| BatchEmail {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                headers: &[],
+            })
+            .collect::<Vec<_>>();
+        email_client.send_batch(&emails).await
+    }
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            match request.body_json::<serde_json::Value>() {
+                Ok(body) => {
+                    body.get("From").is_some()
+                        && body.get("To").is_some()
+                        && body.get("Subject").is_some()
+                        && body.get("HtmlBody").is_some()
+                        && body.get("TextBody").is_some()
+                }
+                Err(_) => false,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_send_a_headers_field_when_there_are_none() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        // Assert
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = request.body_json().unwrap();
+        assert!(body.get("Headers").is_none());
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_sends_them_as_a_postmark_headers_array() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let headers = [
+            EmailHeader::new("List-Unsubscribe", "<https://example.com/unsubscribe>"),
+            EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+        ];
+
+        // Act
+        email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await
+            .unwrap();
+
+        // Assert
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = request.body_json().unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([
+                { "Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>" },
+                { "Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            // Not a 200 anymore!
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_4xx_response_is_a_permanent_failure_and_a_5xx_a_transient_one() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let first = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let second = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(first, Err(EmailError::Permanent(_))));
+        assert!(matches!(second, Err(EmailError::Transient(_))));
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_all_the_messages_in_one_call_to_the_batch_endpoint() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email(), email()];
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(PostmarkBatchResponder { rejected: vec![] })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = send_batch(&email_client, &recipients).await;
+
+        // Assert
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(Result::is_ok));
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let messages: Vec<serde_json::Value> = request.body_json().unwrap();
+        for (message, recipient) in messages.iter().zip(&recipients) {
+            assert_eq!(message["To"], recipient.as_ref());
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_which_recipients_failed() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email(), email()];
+
+        Mock::given(path("/email/batch"))
+            .respond_with(PostmarkBatchResponder {
+                rejected: vec![recipients[1].as_ref().to_owned()],
+            })
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = send_batch(&email_client, &recipients).await;
+
+        // Assert
+        assert_ok!(&results[0]);
+        assert!(matches!(results[1], Err(EmailError::Permanent(_))));
+        assert_ok!(&results[2]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_splits_more_than_500_messages_across_several_calls() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = (0..501).map(|_| email()).collect::<Vec<_>>();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(PostmarkBatchResponder { rejected: vec![] })
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = send_batch(&email_client, &recipients).await;
+
+        // Assert
+        assert_eq!(results.len(), 501);
+        assert!(results.iter().all(Result::is_ok));
+        let batch_sizes = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| r.body_json::<Vec<serde_json::Value>>().unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(batch_sizes, [500, 1]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_batch_call_fails_every_message_in_it() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = send_batch(&email_client, &recipients).await;
+
+        // Assert
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(EmailError::Transient(_)))));
+    }
+
+    #[tokio::test]
+    async fn a_batch_response_with_the_wrong_number_of_results_is_a_permanent_failure() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!([{ "ErrorCode": 0, "Message": "OK" }])),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = send_batch(&email_client, &recipients).await;
+
+        // Assert
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(EmailError::Permanent(_)))));
+    }
+
+    #[tokio::test]
+    async fn an_accepted_batch_with_an_unreadable_response_is_a_permanent_failure() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = send_batch(&email_client, &recipients).await;
+
+        // Assert
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(EmailError::Permanent(_)))));
+    }
+}
@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use super::{message, Email, EmailError, EmailTransport};
+
+/// Plain SMTP, no TLS and no auth: meant for a local MailHog-style catch-all
+/// server, not for delivering to the outside world.
+pub struct SmtpTransport {
+    host: String,
+    port: u16,
+    timeout: Duration,
+}
+
+impl SmtpTransport {
+    pub fn new(host: String, port: u16, timeout: Duration) -> Self {
+        Self {
+            host,
+            port,
+            timeout,
+        }
+    }
+
+    async fn converse(&self, email: &Email<'_>, message: &str) -> Result<(), EmailError> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .context("Failed to connect to the SMTP server.")
+            .map_err(EmailError::Transient)?;
+        let mut session = SmtpSession {
+            stream: BufReader::new(stream),
+        };
+        session.expect_reply(220).await?;
+        session.command("EHLO localhost", 250).await?;
+        session
+            .command(&format!("MAIL FROM:<{}>", email.from.as_ref()), 250)
+            .await?;
+        session
+            .command(&format!("RCPT TO:<{}>", email.to.as_ref()), 250)
+            .await?;
+        session.command("DATA", 354).await?;
+        session.send_data(message).await?;
+        // the message is accepted at this point, a failing QUIT changes nothing
+        let _ = session.command("QUIT", 221).await;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let message = message::render(email)?;
+        tokio::time::timeout(self.timeout, self.converse(email, &message))
+            .await
+            .map_err(|_| {
+                EmailError::Transient(anyhow::anyhow!("The SMTP server did not answer in time."))
+            })?
+    }
+}
+
+struct SmtpSession {
+    stream: BufReader<TcpStream>,
+}
+
+impl SmtpSession {
+    async fn command(&mut self, command: &str, expected_code: u16) -> Result<(), EmailError> {
+        self.write(&format!("{}\r\n", command)).await?;
+        self.expect_reply(expected_code).await
+    }
+
+    async fn send_data(&mut self, message: &str) -> Result<(), EmailError> {
+        let mut data = String::with_capacity(message.len());
+        let message = message.strip_suffix("\r\n").unwrap_or(message);
+        for line in message.split("\r\n") {
+            // dot-stuffing, otherwise a line with a lone "." ends the message early
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+        self.write(&data).await?;
+        self.expect_reply(250).await
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), EmailError> {
+        self.stream
+            .get_mut()
+            .write_all(data.as_bytes())
+            .await
+            .context("Failed to write to the SMTP server.")
+            .map_err(EmailError::Transient)
+    }
+
+    /// Read a (possibly multi-line) reply and check its status code.
+    async fn expect_reply(&mut self, expected_code: u16) -> Result<(), EmailError> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            let n_read = self
+                .stream
+                .read_line(&mut line)
+                .await
+                .context("Failed to read from the SMTP server.")
+                .map_err(EmailError::Transient)?;
+            if n_read == 0 {
+                return Err(EmailError::Transient(anyhow::anyhow!(
+                    "The SMTP server closed the connection."
+                )));
+            }
+            reply.push_str(&line);
+            // "250-..." means more lines follow, "250 ..." is the last one
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        let code: u16 = reply
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| {
+                EmailError::Permanent(anyhow::anyhow!(
+                    "Unexpected reply from the SMTP server: {}",
+                    reply.trim_end()
+                ))
+            })?;
+        if code == expected_code {
+            return Ok(());
+        }
+        let e = anyhow::anyhow!("The SMTP server replied with: {}", reply.trim_end());
+        // 4xx are temporary by definition, e.g. a full mailbox or greylisting
+        if (400..500).contains(&code) {
+            Err(EmailError::Transient(e))
+        } else {
+            Err(EmailError::Permanent(e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::assert_ok;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::SmtpTransport;
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, EmailError},
+    };
+
+    /// A one-shot SMTP server that answers `RCPT TO` with `rcpt_reply` and
+    /// hands back the DATA it received.
+    async fn fake_smtp_server(rcpt_reply: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            stream
+                .get_mut()
+                .write_all(b"220 fake ESMTP\r\n")
+                .await
+                .unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let reply = if in_data {
+                    if line != ".\r\n" {
+                        data.push_str(&line);
+                        continue;
+                    }
+                    in_data = false;
+                    "250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    "250-fake\r\n250 8BITMIME\r\n"
+                } else if line.starts_with("RCPT TO") {
+                    rcpt_reply
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    "354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    stream.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 ok\r\n"
+                };
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        EmailClient::new(
+            SubscriberEmail::parse("newzletter@example.com".to_owned()).unwrap(),
+            SmtpTransport::new("127.0.0.1".to_owned(), port, Duration::from_secs(5)),
+        )
+    }
+
+    fn recipient() -> SubscriberEmail {
+        SubscriberEmail::parse("ursula@example.com".to_owned()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_a_dot_stuffed_message() {
+        // Arrange
+        let (port, server) = fake_smtp_server("250 ok\r\n").await;
+
+        // Act
+        let outcome = email_client(port)
+            .send_email(&recipient(), "Hello", "<p>Hi</p>", "Hi\n.\nthere")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let data = server.await.unwrap();
+        assert!(data.contains("To: ursula@example.com\r\n"));
+        assert!(data.contains("Hi\r\n..\r\nthere"));
+    }
+
+    #[tokio::test]
+    async fn a_4xx_reply_is_a_transient_failure() {
+        // Arrange
+        let (port, _server) = fake_smtp_server("451 try again later\r\n").await;
+
+        // Act
+        let outcome = email_client(port)
+            .send_email(&recipient(), "Hello", "<p>Hi</p>", "Hi")
+            .await;
+
+        // Assert
+        assert!(matches!(outcome, Err(EmailError::Transient(_))));
+    }
+
+    #[tokio::test]
+    async fn a_5xx_reply_is_a_permanent_failure() {
+        // Arrange
+        let (port, _server) = fake_smtp_server("550 no such user\r\n").await;
+
+        // Act
+        let outcome = email_client(port)
+            .send_email(&recipient(), "Hello", "<p>Hi</p>", "Hi")
+            .await;
+
+        // Assert
+        assert!(matches!(outcome, Err(EmailError::Permanent(_))));
+    }
+}
@@ -0,0 +1,135 @@
+//! Forgetting a subscriber for good, as GDPR's right to erasure asks.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use sqlx::{Sqlite, Transaction};
+use uuid::Uuid;
+
+use crate::startup::HmacSecret;
+
+/// A keyed hash of the address, a plain one could be reversed by hashing a
+/// list of likely addresses.
+///
+/// Rotating the HMAC secret orphans the existing tombstones.
+pub fn email_tombstone(hmac_secret: &HmacSecret, email: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.0.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("erased:{}", email.trim().to_lowercase()).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[tracing::instrument(name = "Check for an erasure tombstone", skip_all)]
+pub async fn is_erased(
+    transaction: &mut Transaction<'_, Sqlite>,
+    hmac_secret: &HmacSecret,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let email_hash = email_tombstone(hmac_secret, email);
+    let tombstone = sqlx::query!(
+        "SELECT id FROM erased_subscribers WHERE email_hash = $1",
+        email_hash,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(tombstone.is_some())
+}
+
+/// Delete every row tied to the subscriber and leave a tombstone in their place.
+#[tracing::instrument(name = "Erase a subscriber", skip(transaction, hmac_secret, email))]
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Sqlite>,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_consent_events WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM list_subscriptions WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscriber_field_values WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    // the delivery tables only know the address, not the subscriber
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_attempts WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!("DELETE FROM subscriptions WHERE uuid = $1", subscriber_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    let email_hash = email_tombstone(hmac_secret, email);
+    let erased_at = Utc::now().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO erased_subscribers (email_hash, erased_at)
+        VALUES ($1, $2)
+        ON CONFLICT (email_hash) DO UPDATE SET erased_at = excluded.erased_at
+        "#,
+        email_hash,
+        erased_at,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+
+    use super::email_tombstone;
+    use crate::startup::HmacSecret;
+
+    #[test]
+    fn the_tombstone_ignores_case_and_surrounding_whitespace() {
+        let hmac_secret = HmacSecret(SecretString::from("super-secret"));
+        assert_eq!(
+            email_tombstone(&hmac_secret, " Ursula@Example.com "),
+            email_tombstone(&hmac_secret, "ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn the_tombstone_depends_on_the_secret() {
+        let email = "ursula@example.com";
+        assert_ne!(
+            email_tombstone(&HmacSecret(SecretString::from("one")), email),
+            email_tombstone(&HmacSecret(SecretString::from("two")), email)
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Atom and RSS 2.0 feeds of the public archive, for readers who would rather
+//! follow the newsletter than subscribe to it by email.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+const FEED_TITLE: &str = "Newzletter";
+const FEED_DESCRIPTION: &str = "Every issue of the newsletter so far";
+
+pub struct Feed {
+    // where the app is served from, e.g. `https://example.com`
+    pub base_url: String,
+    pub updated: DateTime<Utc>,
+    // newest first
+    pub entries: Vec<FeedEntry>,
+}
+
+pub struct FeedEntry {
+    // the issue's uuid, it stays the same if the app moves to another URL
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub published: DateTime<Utc>,
+    // the public copy, see `archive::public_copy`
+    pub html_content: String,
+}
+
+impl Feed {
+    fn archive_url(&self) -> String {
+        format!("{}/newsletter/archive", self.base_url)
+    }
+
+    fn entry_url(&self, entry: &FeedEntry) -> String {
+        format!("{}/{}", self.archive_url(), entry.slug)
+    }
+
+    /// The feed as an Atom document, served at `/newsletter/feed.xml`.
+    pub fn atom(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+        let _ = write!(
+            xml,
+            r#"<title>{}</title><subtitle>{}</subtitle><link rel="self" type="application/atom+xml" href="{}/newsletter/feed.xml"/><link rel="alternate" type="text/html" href="{}"/><id>{}</id><updated>{}</updated><author><name>{}</name></author>"#,
+            escape(FEED_TITLE),
+            escape(FEED_DESCRIPTION),
+            escape(&self.base_url),
+            escape(&self.archive_url()),
+            escape(&self.archive_url()),
+            rfc3339(self.updated),
+            escape(FEED_TITLE),
+        );
+        for entry in &self.entries {
+            let _ = write!(
+                xml,
+                r#"<entry><title>{}</title><link rel="alternate" type="text/html" href="{}"/><id>urn:uuid:{}</id><published>{}</published><updated>{}</updated><content type="html">{}</content></entry>"#,
+                escape(&entry.title),
+                escape(&self.entry_url(entry)),
+                escape(&entry.id),
+                rfc3339(entry.published),
+                rfc3339(entry.published),
+                escape(&entry.html_content),
+            );
+        }
+        xml.push_str("</feed>");
+        xml
+    }
+
+    /// The feed as an RSS 2.0 document, served at `/newsletter/rss.xml`.
+    pub fn rss(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#);
+        let _ = write!(
+            xml,
+            r#"<title>{}</title><link>{}</link><description>{}</description><atom:link rel="self" type="application/rss+xml" href="{}/newsletter/rss.xml"/><lastBuildDate>{}</lastBuildDate>"#,
+            escape(FEED_TITLE),
+            escape(&self.archive_url()),
+            escape(FEED_DESCRIPTION),
+            escape(&self.base_url),
+            self.updated.to_rfc2822(),
+        );
+        for entry in &self.entries {
+            let _ = write!(
+                xml,
+                r#"<item><title>{}</title><link>{}</link><guid isPermaLink="false">urn:uuid:{}</guid><pubDate>{}</pubDate><description>{}</description></item>"#,
+                escape(&entry.title),
+                escape(&self.entry_url(entry)),
+                escape(&entry.id),
+                entry.published.to_rfc2822(),
+                escape(&entry.html_content),
+            );
+        }
+        xml.push_str("</channel></rss>");
+        xml
+    }
+}
+
+fn rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(text: &str) -> String {
+    htmlescape::encode_minimal(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Feed, FeedEntry};
+    use chrono::{TimeZone, Utc};
+
+    fn feed() -> Feed {
+        Feed {
+            base_url: "https://example.com".to_owned(),
+            updated: Utc.with_ymd_and_hms(2025, 6, 14, 9, 30, 0).unwrap(),
+            entries: vec![FeedEntry {
+                id: "0b6a1d3e-7c1f-4f55-9f57-1c1f3f0f5e21".to_owned(),
+                slug: "tips-tricks".to_owned(),
+                title: "Tips & tricks".to_owned(),
+                published: Utc.with_ymd_and_hms(2025, 6, 13, 9, 0, 0).unwrap(),
+                html_content: "<p>Hi reader</p>".to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn atom_entries_link_to_the_archive_and_escape_their_content() {
+        let xml = feed().atom();
+
+        assert!(xml.contains("<updated>2025-06-14T09:30:00Z</updated>"));
+        assert!(xml.contains("<title>Tips &amp; tricks</title>"));
+        assert!(xml.contains(r#"href="https://example.com/newsletter/archive/tips-tricks""#));
+        assert!(xml.contains("<id>urn:uuid:0b6a1d3e-7c1f-4f55-9f57-1c1f3f0f5e21</id>"));
+        assert!(xml.contains("<published>2025-06-13T09:00:00Z</published>"));
+        assert!(xml.contains(r#"<content type="html">&lt;p&gt;Hi reader&lt;/p&gt;</content>"#));
+    }
+
+    #[test]
+    fn rss_items_use_rfc_2822_dates() {
+        let xml = feed().rss();
+
+        assert!(xml.contains("<lastBuildDate>Sat, 14 Jun 2025 09:30:00 +0000</lastBuildDate>"));
+        assert!(xml.contains("<pubDate>Fri, 13 Jun 2025 09:00:00 +0000</pubDate>"));
+        assert!(xml.contains("<link>https://example.com/newsletter/archive/tips-tricks</link>"));
+        assert!(xml.contains("<description>&lt;p&gt;Hi reader&lt;/p&gt;</description>"));
+    }
+
+    #[test]
+    fn an_empty_feed_is_still_a_feed() {
+        let feed = Feed {
+            entries: vec![],
+            ..feed()
+        };
+
+        assert!(feed.atom().ends_with("</author></feed>"));
+        assert!(feed.rss().ends_with("</lastBuildDate></channel></rss>"));
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Context;
+use axum::{body::Body, http::StatusCode, response::Response};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use super::IdempotencyKey;
+
+#[derive(Serialize, Deserialize)]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+pub enum NextAction {
+    // the transaction holds SQLite's write lock until `save_response` commits it
+    StartProcessing(Transaction<'static, Sqlite>),
+    ReturnSavedResponse(Response),
+}
+
+#[tracing::instrument(skip(transaction, idempotency_key, user_id))]
+async fn get_saved_response(
+    transaction: &mut Transaction<'static, Sqlite>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<Response>, anyhow::Error> {
+    let user_id = user_id.to_string();
+    let idempotency_key = idempotency_key.as_ref();
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            response_status_code,
+            response_headers,
+            response_body
+        FROM idempotency
+        WHERE
+          user_id = $1 AND
+          idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    let Some(r) = saved_response else {
+        return Ok(None);
+    };
+    let (Some(status_code), Some(headers), Some(body)) =
+        (r.response_status_code, r.response_headers, r.response_body)
+    else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(status_code.try_into()?)?;
+    let headers: Vec<HeaderPairRecord> =
+        serde_json::from_str(&headers).context("Failed to parse the saved response headers.")?;
+    let mut response = Response::builder().status(status_code);
+    for HeaderPairRecord { name, value } in headers {
+        response = response.header(name, value);
+    }
+    Ok(Some(response.body(Body::from(body))?))
+}
+
+#[tracing::instrument(skip(pool, idempotency_key, user_id))]
+pub async fn try_processing(
+    pool: &SqlitePool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<NextAction, anyhow::Error> {
+    // SQLite has no row locks. `BEGIN IMMEDIATE` grabs the database write lock
+    // up front, so a concurrent request with the same key waits here (up to
+    // `busy_timeout`) until we have committed the response it should replay.
+    let mut transaction = pool.begin_with("BEGIN IMMEDIATE").await?;
+    let user_id_str = user_id.to_string();
+    let idempotency_key_str = idempotency_key.as_ref();
+    let created_at = Utc::now().to_string();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (
+            user_id,
+            idempotency_key,
+            created_at
+        )
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id_str,
+        idempotency_key_str,
+        created_at,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_inserted_rows > 0 {
+        Ok(NextAction::StartProcessing(transaction))
+    } else {
+        let saved_response = get_saved_response(&mut transaction, idempotency_key, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+        Ok(NextAction::ReturnSavedResponse(saved_response))
+    }
+}
+
+#[tracing::instrument(skip(transaction, idempotency_key, user_id, http_response))]
+pub async fn save_response(
+    mut transaction: Transaction<'static, Sqlite>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: Response,
+) -> Result<Response, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .context("Failed to read the response body.")?;
+    let status_code = response_head.status.as_u16();
+    let headers = response_head
+        .headers
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+    let headers = serde_json::to_string(&headers)?;
+    let user_id = user_id.to_string();
+    let idempotency_key = idempotency_key.as_ref();
+    let response_body = body.as_ref();
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key,
+        status_code,
+        headers,
+        response_body,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+
+    let http_response = Response::from_parts(response_head, Body::from(body));
+    Ok(http_response)
+}
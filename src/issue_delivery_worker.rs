@@ -0,0 +1,724 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use sqlx::{SqliteExecutor, SqlitePool};
+use tokio::sync::Notify;
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::{
+    custom_fields::get_field_values,
+    domain::SubscriberEmail,
+    email_client::{BatchEmail, EmailClient, EmailError, EmailHeader},
+    merge_tags::fill_merge_tags,
+    routes::{preferences_link, unsubscribe_link},
+    startup::HmacSecret,
+};
+
+// how long the worker sleeps on an empty queue if nobody pokes it
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// how many deliveries go out together, Postmark takes up to 500 per call
+const MAX_BATCH_SIZE: i64 = 500;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Capped exponential backoff with jitter.
+    ///
+    /// The delay doubles with every retry until it hits `max_delay`, then a random
+    /// amount of up to half of it is shaved off so that deliveries which failed
+    /// together (e.g. during a provider outage) don't all come back at once.
+    pub fn backoff(&self, n_retries: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(n_retries))
+            .min(self.max_delay);
+        let half = exponential / 2;
+        let jitter = rand::rng().random_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+/// How a failed delivery should be handled.
+#[derive(Debug, PartialEq)]
+enum DeliveryFailure {
+    /// Worth another try later on: 5xx, 429, timeouts, connection errors.
+    Transient,
+    /// Retrying won't help, e.g. the provider rejected the recipient.
+    Permanent,
+}
+
+impl DeliveryFailure {
+    fn classify(e: &EmailError) -> Self {
+        match e {
+            EmailError::Transient(_) => Self::Transient,
+            EmailError::Permanent(_) => Self::Permanent,
+        }
+    }
+
+    fn as_outcome(&self) -> &'static str {
+        match self {
+            Self::Transient => "transient_failure",
+            Self::Permanent => "permanent_failure",
+        }
+    }
+}
+
+/// Drain the delivery queue forever.
+///
+/// `publish_newsletter` pokes `delivery_trigger` after it commits new tasks, so
+/// we don't have to poll the database in a tight loop while the queue is empty.
+pub async fn run_worker_until_stopped(
+    pool: SqlitePool,
+    email_client: EmailClient,
+    retry_policy: RetryPolicy,
+    delivery_trigger: Arc<Notify>,
+    base_url: String,
+    hmac_secret: HmacSecret,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_batch(&pool, &email_client, &retry_policy, &base_url, &hmac_secret).await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                // sleep until the next retry is due, a new issue gets published
+                // or we time out and have another look anyway
+                let wait = time_until_next_task(&pool)
+                    .await
+                    .unwrap_or(IDLE_POLL_INTERVAL)
+                    .min(IDLE_POLL_INTERVAL);
+                let _ = tokio::time::timeout(wait, delivery_trigger.notified()).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Send every due delivery, up to `MAX_BATCH_SIZE` of them, in one go.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_batch(
+    pool: &SqlitePool,
+    email_client: &EmailClient,
+    retry_policy: &RetryPolicy,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let tasks = dequeue_tasks(pool).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", tasks.len());
+    let mut issues = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        if let Some(delivery) =
+            prepare_delivery(pool, email_client, base_url, hmac_secret, &mut issues, task).await?
+        {
+            deliveries.push(delivery);
+        }
+    }
+    let emails = deliveries
+        .iter()
+        .map(|delivery| BatchEmail {
+            recipient: &delivery.recipient,
+            subject: &delivery.subject,
+            html_content: &delivery.html_content,
+            text_content: &delivery.text_content,
+            headers: &delivery.headers,
+        })
+        .collect::<Vec<_>>();
+    let results = email_client.send_batch(&emails).await;
+    // the batch is out, a task we fail to settle goes out again, so don't
+    // leave the rest of them behind with it
+    let mut n_unsettled = 0;
+    for (delivery, result) in deliveries.iter().zip(results) {
+        if let Err(e) = handle_outcome(pool, retry_policy, &delivery.task, result).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                task_id = delivery.task.id,
+                "Failed to record the outcome of a delivery",
+            );
+            n_unsettled += 1;
+        }
+    }
+    // back off rather than send the unsettled ones again straight away
+    if n_unsettled > 0 {
+        anyhow::bail!("Failed to record the outcome of {} deliveries", n_unsettled);
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// A task with its email rendered for this particular recipient.
+struct Delivery {
+    task: DeliveryTask,
+    recipient: SubscriberEmail,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    headers: Vec<EmailHeader>,
+}
+
+/// Render the email for `task`, or settle the task right away if there is
+/// nothing to send.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = %task.newsletter_issue_id,
+        subscriber_email = %task.subscriber_email
+    )
+)]
+async fn prepare_delivery(
+    pool: &SqlitePool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    issues: &mut HashMap<Uuid, NewsletterIssue>,
+    task: DeliveryTask,
+) -> Result<Option<Delivery>, anyhow::Error> {
+    let recipient = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => email,
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Dead-lettering a delivery. The subscriber's stored contact details are invalid",
+            );
+            let mut transaction = pool.begin().await?;
+            record_attempt(
+                &mut *transaction,
+                &task,
+                DeliveryFailure::Permanent.as_outcome(),
+                Some(e.as_str()),
+            )
+            .await?;
+            dead_letter_task(&mut *transaction, task.id, &e).await?;
+            transaction.commit().await?;
+            return Ok(None);
+        }
+    };
+    // they may have unsubscribed or paused since the issue was published
+    let Some(subscription) =
+        get_confirmed_subscription(pool, recipient.as_ref(), task.newsletter_issue_id).await?
+    else {
+        tracing::info!(
+            "Skipping a delivery. The recipient is no longer on any of the issue's lists or has paused delivery"
+        );
+        delete_task(pool, task.id).await?;
+        return Ok(None);
+    };
+    // a batch is usually one issue going out to many people
+    let issue = match issues.entry(task.newsletter_issue_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(get_issue(pool, task.newsletter_issue_id).await?),
+    };
+    let unsubscribe_link = unsubscribe_link(
+        base_url,
+        hmac_secret,
+        subscription.subscriber_id,
+        &subscription.list_slug,
+    );
+    let preferences_link = preferences_link(base_url, hmac_secret, subscription.subscriber_id);
+    let PersonalisedIssue {
+        html_content,
+        text_content,
+    } = personalise_issue(
+        pool,
+        recipient.as_ref(),
+        &subscription,
+        &unsubscribe_link,
+        &preferences_link,
+        &issue.html_content,
+        &issue.text_content,
+    )
+    .await?;
+    // RFC 8058 one-click unsubscribe: mail clients POST `List-Unsubscribe=One-Click`
+    // straight to the link, no login and no confirmation page involved
+    let headers = vec![
+        EmailHeader::new(
+            "List-Unsubscribe",
+            format!(
+                "<mailto:{}?subject=unsubscribe>, <{}>",
+                email_client.sender().as_ref(),
+                unsubscribe_link
+            ),
+        ),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ];
+    Ok(Some(Delivery {
+        subject: issue.title.clone(),
+        task,
+        recipient,
+        html_content,
+        text_content,
+        headers,
+    }))
+}
+
+/// An issue's two parts as one subscriber gets them.
+pub struct PersonalisedIssue {
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// Fill in the merge tags for `recipient` and add the footer with the
+/// unsubscribe and preferences links, previews and test sends go through
+/// here too.
+pub async fn personalise_issue(
+    pool: &SqlitePool,
+    recipient: &str,
+    subscription: &ConfirmedSubscription,
+    unsubscribe_link: &str,
+    preferences_link: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<PersonalisedIssue, anyhow::Error> {
+    let mut merge_fields = get_field_values(pool, subscription.subscriber_id).await?;
+    merge_fields.extend([
+        ("name".to_owned(), subscription.name.clone()),
+        ("email".to_owned(), recipient.to_owned()),
+        ("language".to_owned(), subscription.language.clone()),
+        ("list_name".to_owned(), subscription.list_name.clone()),
+        ("unsubscribe_url".to_owned(), unsubscribe_link.to_owned()),
+        ("preferences_url".to_owned(), preferences_link.to_owned()),
+    ]);
+    let html_content = format!(
+        "{}<hr /><p style=\"font-size: 12px; color: #6b7280;\">Don't want these emails anymore? <a href=\"{}\">Unsubscribe</a>. Or <a href=\"{}\">change what you get and how often</a>.</p>",
+        fill_merge_tags(html_content, &merge_fields, |value| {
+            htmlescape::encode_minimal(value)
+        }),
+        unsubscribe_link,
+        preferences_link
+    );
+    let text_content = format!(
+        "{}\n\n--\nDon't want these emails anymore? Unsubscribe: {}\nChange what you get and how often: {}",
+        fill_merge_tags(text_content, &merge_fields, str::to_owned),
+        unsubscribe_link,
+        preferences_link
+    );
+    Ok(PersonalisedIssue {
+        html_content,
+        text_content,
+    })
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id = %task.newsletter_issue_id,
+        subscriber_email = %task.subscriber_email
+    )
+)]
+async fn handle_outcome(
+    pool: &SqlitePool,
+    retry_policy: &RetryPolicy,
+    task: &DeliveryTask,
+    result: Result<(), EmailError>,
+) -> Result<(), anyhow::Error> {
+    // the attempt and what becomes of the task are recorded together, half
+    // of it would have the message sent again
+    let mut transaction = pool.begin().await?;
+    let e = match result {
+        Ok(()) => {
+            record_attempt(&mut *transaction, task, "delivered", None).await?;
+            delete_task(&mut *transaction, task.id).await?;
+            transaction.commit().await?;
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+    let failure = DeliveryFailure::classify(&e);
+    let error = e.to_string();
+    record_attempt(&mut *transaction, task, failure.as_outcome(), Some(&error)).await?;
+    if failure == DeliveryFailure::Transient && task.n_retries < retry_policy.max_retries {
+        let delay = retry_policy.backoff(task.n_retries);
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            n_retries = task.n_retries,
+            retry_in = ?delay,
+            "Failed to deliver issue to a confirmed subscriber. Retrying later.",
+        );
+        reschedule_task(&mut *transaction, task.id, delay, &error).await?;
+    } else {
+        // one bad recipient must not hold the rest of the queue hostage
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            n_retries = task.n_retries,
+            "Failed to deliver issue to a confirmed subscriber. Dead-lettering it.",
+        );
+        dead_letter_task(&mut *transaction, task.id, &error).await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+struct DeliveryTask {
+    id: i64,
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: u32,
+}
+
+// SQLite has no `FOR UPDATE SKIP LOCKED`, so this is only safe with a single
+// worker per database, which is what `Application::build` spawns.
+#[tracing::instrument(skip_all)]
+async fn dequeue_tasks(pool: &SqlitePool) -> Result<Vec<DeliveryTask>, anyhow::Error> {
+    let now = Utc::now().to_string();
+    let limit = MAX_BATCH_SIZE;
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE status = 'pending' AND execute_after <= $1
+        ORDER BY execute_after, id
+        LIMIT $2
+        "#,
+        now,
+        limit,
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(DeliveryTask {
+                id: r.id,
+                newsletter_issue_id: Uuid::try_parse(&r.newsletter_issue_id)?,
+                subscriber_email: r.subscriber_email,
+                n_retries: r.n_retries.try_into()?,
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(skip_all)]
+async fn time_until_next_task(pool: &SqlitePool) -> Result<Duration, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT MIN(execute_after) AS "execute_after: String"
+        FROM issue_delivery_queue
+        WHERE status = 'pending'
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    let Some(execute_after) = r.execute_after else {
+        return Ok(IDLE_POLL_INTERVAL);
+    };
+    let execute_after: DateTime<Utc> = execute_after.parse()?;
+    Ok((execute_after - Utc::now()).to_std().unwrap_or_default())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(executor: impl SqliteExecutor<'_>, task_id: i64) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE id = $1
+        "#,
+        task_id,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    executor: impl SqliteExecutor<'_>,
+    task_id: i64,
+    delay: Duration,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    let execute_after = (Utc::now() + delay).to_string();
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $2,
+            last_error = $3
+        WHERE id = $1
+        "#,
+        task_id,
+        execute_after,
+        error,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn dead_letter_task(
+    executor: impl SqliteExecutor<'_>,
+    task_id: i64,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            status = 'dead_lettered',
+            last_error = $2
+        WHERE id = $1
+        "#,
+        task_id,
+        error,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_attempt(
+    executor: impl SqliteExecutor<'_>,
+    task: &DeliveryTask,
+    outcome: &str,
+    error: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let newsletter_issue_id = task.newsletter_issue_id.to_string();
+    let attempted_at = Utc::now().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_attempts (
+            newsletter_issue_id,
+            subscriber_email,
+            attempted_at,
+            outcome,
+            error
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        newsletter_issue_id,
+        task.subscriber_email,
+        attempted_at,
+        outcome,
+        error,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The list an issue reaches the recipient through.
+pub struct ConfirmedSubscription {
+    pub subscriber_id: Uuid,
+    pub name: String,
+    // as its ISO 639-1 code
+    pub language: String,
+    pub list_slug: String,
+    pub list_name: String,
+}
+
+/// Someone on several of the issue's lists still gets a single copy, its
+/// unsubscribe link is for the oldest of those lists. Nobody gets it while
+/// their delivery is paused, even if it was queued before they paused.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscription(
+    pool: &SqlitePool,
+    email: &str,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<ConfirmedSubscription>, anyhow::Error> {
+    let newsletter_issue_id = newsletter_issue_id.to_string();
+    let now = Utc::now().to_string();
+    let r = sqlx::query!(
+        r#"
+        SELECT s.uuid, s.name, s.language, l.slug, l.name AS list_name
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.uuid
+        JOIN lists l ON l.uuid = ls.list_id
+        JOIN newsletter_issue_lists il ON il.list_id = ls.list_id
+        WHERE s.email = $1
+            AND s.status = 'confirmed'
+            AND ls.status = 'confirmed'
+            AND il.newsletter_issue_id = $2
+            AND (s.paused_until IS NULL OR s.paused_until <= $3)
+        ORDER BY l.id
+        LIMIT 1
+        "#,
+        email,
+        newsletter_issue_id,
+        now,
+    )
+    .fetch_optional(pool)
+    .await?;
+    match r {
+        Some(r) => Ok(Some(ConfirmedSubscription {
+            subscriber_id: Uuid::try_parse(&r.uuid)?,
+            name: r.name,
+            language: r.language,
+            list_slug: r.slug,
+            list_name: r.list_name,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Like `get_confirmed_subscription`, for an issue that isn't published yet
+/// and only has the lists it is meant for.
+#[tracing::instrument(skip_all)]
+pub async fn get_subscription_on_lists(
+    pool: &SqlitePool,
+    email: &str,
+    list_ids: &[Uuid],
+) -> Result<Option<ConfirmedSubscription>, anyhow::Error> {
+    let list_ids = serde_json::to_string(list_ids)?;
+    let r = sqlx::query!(
+        r#"
+        SELECT s.uuid, s.name, s.language, l.slug, l.name AS list_name
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.uuid
+        JOIN lists l ON l.uuid = ls.list_id
+        WHERE s.email = $1
+            AND s.status = 'confirmed'
+            AND ls.status = 'confirmed'
+            AND ls.list_id IN (SELECT value FROM json_each($2))
+        ORDER BY l.id
+        LIMIT 1
+        "#,
+        email,
+        list_ids,
+    )
+    .fetch_optional(pool)
+    .await?;
+    match r {
+        Some(r) => Ok(Some(ConfirmedSubscription {
+            subscriber_id: Uuid::try_parse(&r.uuid)?,
+            name: r.name,
+            language: r.language,
+            list_slug: r.slug,
+            list_name: r.list_name,
+        })),
+        None => Ok(None),
+    }
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &SqlitePool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue_id = issue_id.to_string();
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE uuid = $1
+        "#,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(issue)
+}
+
+pub struct DeadLetter {
+    pub id: i64,
+    pub title: String,
+    pub subscriber_email: String,
+    pub n_retries: i64,
+    pub last_error: String,
+}
+
+#[tracing::instrument(name = "Get dead-lettered deliveries", skip(pool))]
+pub async fn get_dead_letters(pool: &SqlitePool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT
+            q.id,
+            i.title,
+            q.subscriber_email,
+            q.n_retries,
+            COALESCE(q.last_error, '') AS "last_error!: String"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.uuid = q.newsletter_issue_id
+        WHERE q.status = 'dead_lettered'
+        ORDER BY q.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(dead_letters)
+}
+
+/// Put a dead-lettered delivery back in line, with a fresh retry budget.
+///
+/// Returns `false` if there is no dead-lettered delivery with that id.
+#[tracing::instrument(name = "Requeue a dead-lettered delivery", skip(pool))]
+pub async fn requeue_dead_letter(pool: &SqlitePool, task_id: i64) -> Result<bool, anyhow::Error> {
+    let now = Utc::now().to_string();
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            status = 'pending',
+            n_retries = 0,
+            execute_after = $2
+        WHERE id = $1 AND status = 'dead_lettered'
+        "#,
+        task_id,
+        now,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 8,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_every_retry() {
+        let policy = retry_policy();
+        for n_retries in 0..5 {
+            let expected = Duration::from_secs(2u64.pow(n_retries));
+            let delay = policy.backoff(n_retries);
+            assert!(delay <= expected, "{:?} > {:?}", delay, expected);
+            assert!(delay >= expected / 2, "{:?} < {:?}", delay, expected / 2);
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = retry_policy();
+        for n_retries in [6, 10, 31, 32, 1000] {
+            assert!(policy.backoff(n_retries) <= policy.max_delay);
+        }
+    }
+}
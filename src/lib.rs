@@ -1,9 +1,26 @@
+pub mod archive;
 pub mod authentication;
+pub mod blog_posts;
 pub mod configuration;
+pub mod consent;
+pub mod custom_fields;
+pub mod digest;
 pub mod domain;
+pub mod drafts;
 pub mod email_client;
+pub mod erasure;
+pub mod feed;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod lists;
+pub mod markdown;
+pub mod merge_tags;
+pub mod preferences;
 pub mod routes;
+pub mod scheduler;
+pub mod segments;
 pub mod session_state;
+pub mod signed_link;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
@@ -0,0 +1,233 @@
+//! Mailing lists, and who is subscribed to which.
+//!
+//! `subscriptions.status` is about the address: has it ever been confirmed,
+//! has it left us altogether. Whether it gets a given list is up to
+//! `list_subscriptions`.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{Sqlite, SqliteExecutor, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use crate::domain::ListSlug;
+
+/// The list signup forms without a `list` field subscribe to, seeded by the
+/// migration that introduced lists.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+pub struct MailingList {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+#[tracing::instrument(name = "Get a list by slug", skip(executor))]
+pub async fn get_list_by_slug(
+    executor: impl SqliteExecutor<'_>,
+    slug: &str,
+) -> Result<Option<MailingList>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"SELECT uuid, slug, name FROM lists WHERE slug = $1"#,
+        slug
+    )
+    .fetch_optional(executor)
+    .await?;
+    match r {
+        Some(r) => Ok(Some(MailingList {
+            id: Uuid::try_parse(&r.uuid)?,
+            slug: r.slug,
+            name: r.name,
+        })),
+        None => Ok(None),
+    }
+}
+
+pub struct ListSummary {
+    pub slug: String,
+    pub name: String,
+    pub n_confirmed: i64,
+}
+
+#[tracing::instrument(name = "Get all lists", skip(pool))]
+pub async fn get_lists(pool: &SqlitePool) -> Result<Vec<ListSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COUNT(ls.id) AS "n_confirmed!: i64"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls
+            ON ls.list_id = l.uuid AND ls.status = 'confirmed'
+        GROUP BY l.id
+        ORDER BY l.id
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if another list already goes by `slug`.
+#[tracing::instrument(name = "Create a list", skip(pool))]
+pub async fn insert_list(
+    pool: &SqlitePool,
+    slug: &ListSlug,
+    name: &str,
+) -> Result<bool, sqlx::Error> {
+    let uuid = Uuid::new_v4().to_string();
+    let slug = slug.as_ref();
+    let created_at = Utc::now().to_string();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (uuid, slug, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        uuid,
+        slug,
+        name,
+        created_at,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows > 0)
+}
+
+#[tracing::instrument(name = "Get the status of a list subscription", skip(transaction))]
+pub async fn get_list_subscription_status(
+    transaction: &mut Transaction<'_, Sqlite>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let list_id = list_id.to_string();
+    let subscriber_id = subscriber_id.to_string();
+    let r = sqlx::query!(
+        r#"
+        SELECT status FROM list_subscriptions
+        WHERE list_id = $1 AND subscriber_id = $2
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    Ok(r.map(|r| r.status))
+}
+
+/// Put the subscriber on the list with `status`, whatever they had before.
+#[tracing::instrument(name = "Add a subscriber to a list", skip(transaction))]
+pub async fn add_to_list(
+    transaction: &mut Transaction<'_, Sqlite>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    let list_id = list_id.to_string();
+    let subscriber_id = subscriber_id.to_string();
+    let subscribed_at = Utc::now().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = excluded.status, subscribed_at = excluded.subscribed_at
+        "#,
+        list_id,
+        subscriber_id,
+        status,
+        subscribed_at,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// A confirmed address confirms every list it asked to join.
+#[tracing::instrument(name = "Confirm pending list subscriptions", skip(transaction))]
+pub async fn confirm_list_subscriptions(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Take the subscriber off `list_id`, or off every list if `None`.
+///
+/// Once they are on no list at all, the address itself counts as unsubscribed.
+#[tracing::instrument(name = "Unsubscribe from lists", skip(transaction))]
+pub async fn leave_lists(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let list_id = list_id.map(|list_id| list_id.to_string());
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND ($2 IS NULL OR list_id = $2)
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE uuid = $1 AND NOT EXISTS (
+            SELECT 1 FROM list_subscriptions
+            WHERE subscriber_id = $1 AND status != 'unsubscribed'
+        )
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct ListMembership {
+    pub list_slug: String,
+    pub list_name: String,
+    pub status: String,
+    pub subscribed_at: String,
+}
+
+#[tracing::instrument(name = "Get the lists of a subscriber", skip(pool))]
+pub async fn get_list_memberships(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListMembership>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT
+            l.slug AS list_slug,
+            l.name AS list_name,
+            ls.status,
+            ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN lists l ON l.uuid = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY l.id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
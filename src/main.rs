@@ -1,4 +1,3 @@
-use anyhow;
 use newzletter::{
     configuration::get_configuration,
     startup::Application,
@@ -14,5 +13,5 @@ async fn main() -> anyhow::Result<()> {
 
     let configuration = get_configuration()?;
     let application = Application::build(configuration).await?;
-    Ok(application.run_until_stopped().await?)
+    application.run_until_stopped().await
 }
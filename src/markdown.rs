@@ -0,0 +1,459 @@
+//! Issue bodies written once in markdown, rendered to both parts of the email.
+//!
+//! The HTML is put together here tag by tag rather than by a general purpose
+//! renderer: every element gets the same inline styles, as mail clients drop
+//! `<style>` blocks, and nothing the author typed ends up as markup. Raw HTML
+//! in the source is shown as text and links only keep safe destinations.
+//!
+//! The plain text keeps the shape of the markdown and moves link targets to
+//! numbered footnotes at the end, so sentences stay readable.
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::merge_tags::LINK_TAGS;
+
+const BODY_STYLE: &str = "font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Helvetica, Arial, sans-serif; font-size: 16px; line-height: 1.6; color: #1f2937;";
+const PARAGRAPH_STYLE: &str = "margin: 0 0 16px;";
+const H1_STYLE: &str = "margin: 24px 0 12px; font-size: 26px; line-height: 1.3; color: #111827;";
+const H2_STYLE: &str = "margin: 24px 0 12px; font-size: 22px; line-height: 1.3; color: #111827;";
+const H3_STYLE: &str = "margin: 20px 0 8px; font-size: 18px; line-height: 1.3; color: #111827;";
+const LINK_STYLE: &str = "color: #2563eb; text-decoration: underline;";
+const BLOCKQUOTE_STYLE: &str =
+    "margin: 0 0 16px; padding: 0 16px; border-left: 4px solid #d1d5db; color: #4b5563;";
+const PRE_STYLE: &str = "margin: 0 0 16px; padding: 12px; background-color: #f3f4f6; border-radius: 4px; overflow-x: auto; font-family: Menlo, Consolas, monospace; font-size: 14px; line-height: 1.4;";
+const CODE_STYLE: &str = "padding: 2px 4px; background-color: #f3f4f6; border-radius: 4px; font-family: Menlo, Consolas, monospace; font-size: 14px;";
+const LIST_STYLE: &str = "margin: 0 0 16px; padding-left: 24px;";
+const ITEM_STYLE: &str = "margin: 0 0 4px;";
+const RULE_STYLE: &str = "margin: 24px 0; border: none; border-top: 1px solid #e5e7eb;";
+const IMAGE_STYLE: &str = "max-width: 100%; height: auto; border: 0;";
+
+/// Both parts of an issue, rendered from the same markdown.
+#[derive(Debug)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render_markdown(source: &str) -> RenderedMarkdown {
+    let events = Parser::new_ext(source, Options::ENABLE_STRIKETHROUGH).collect::<Vec<_>>();
+    RenderedMarkdown {
+        html: render_html(&events),
+        text: render_text(&events),
+    }
+}
+
+/// Whether a link may point at `destination`. A lone link tag is let
+/// through, it is how `{{ unsubscribe_url }}` gets into a link, other tags
+/// hold whatever subscribers typed in.
+fn is_safe_link(destination: &str) -> bool {
+    let destination = destination.trim();
+    let lowercase = destination.to_ascii_lowercase();
+    let link_tag = destination
+        .strip_prefix("{{")
+        .and_then(|tag| tag.strip_suffix("}}"))
+        .map(str::trim);
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| lowercase.starts_with(scheme))
+        || link_tag.is_some_and(|tag| LINK_TAGS.contains(&tag))
+}
+
+fn is_safe_image(source: &str) -> bool {
+    let lowercase = source.trim().to_ascii_lowercase();
+    lowercase.starts_with("http://") || lowercase.starts_with("https://")
+}
+
+fn render_html(events: &[Event]) -> String {
+    let mut html = format!(r#"<div style="{}">"#, BODY_STYLE);
+    // whether each open link made it into the output, so its end tag matches
+    let mut open_links = Vec::new();
+    // the alt text of an image is collected from the events inside it
+    let mut image: Option<(String, String)> = None;
+    for event in events {
+        if let Some((_, alt)) = image.as_mut() {
+            match event {
+                Event::End(TagEnd::Image) => {
+                    let (source, alt) = image.take().unwrap();
+                    if is_safe_image(&source) {
+                        html.push_str(&format!(
+                            r#"<img src="{}" alt="{}" style="{}" />"#,
+                            htmlescape::encode_minimal(source.trim()),
+                            htmlescape::encode_minimal(&alt),
+                            IMAGE_STYLE
+                        ));
+                    } else {
+                        html.push_str(&htmlescape::encode_minimal(&alt));
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => alt.push_str(text),
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(tag) => match tag {
+                // raw HTML is shown as a paragraph of text
+                Tag::Paragraph | Tag::HtmlBlock => open(&mut html, "p", PARAGRAPH_STYLE),
+                Tag::Heading { level, .. } => {
+                    let (name, style) = heading(*level);
+                    open(&mut html, name, style);
+                }
+                Tag::BlockQuote(_) => open(&mut html, "blockquote", BLOCKQUOTE_STYLE),
+                Tag::CodeBlock(_) => {
+                    open(&mut html, "pre", PRE_STYLE);
+                    html.push_str("<code>");
+                }
+                Tag::List(Some(1)) => open(&mut html, "ol", LIST_STYLE),
+                Tag::List(Some(start)) => {
+                    html.push_str(&format!(r#"<ol start="{}" style="{}">"#, start, LIST_STYLE))
+                }
+                Tag::List(None) => open(&mut html, "ul", LIST_STYLE),
+                Tag::Item => open(&mut html, "li", ITEM_STYLE),
+                Tag::Emphasis => html.push_str("<em>"),
+                Tag::Strong => html.push_str("<strong>"),
+                Tag::Strikethrough => html.push_str("<del>"),
+                Tag::Link { dest_url, .. } => {
+                    let is_safe = is_safe_link(dest_url);
+                    if is_safe {
+                        html.push_str(&format!(
+                            r#"<a href="{}" style="{}">"#,
+                            htmlescape::encode_minimal(dest_url.trim()),
+                            LINK_STYLE
+                        ));
+                    }
+                    open_links.push(is_safe);
+                }
+                Tag::Image { dest_url, .. } => {
+                    image = Some((dest_url.to_string(), String::new()));
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph | TagEnd::HtmlBlock => html.push_str("</p>"),
+                TagEnd::Heading(level) => {
+                    html.push_str(&format!("</{}>", heading(*level).0));
+                }
+                TagEnd::BlockQuote(_) => html.push_str("</blockquote>"),
+                TagEnd::CodeBlock => html.push_str("</code></pre>"),
+                TagEnd::List(true) => html.push_str("</ol>"),
+                TagEnd::List(false) => html.push_str("</ul>"),
+                TagEnd::Item => html.push_str("</li>"),
+                TagEnd::Emphasis => html.push_str("</em>"),
+                TagEnd::Strong => html.push_str("</strong>"),
+                TagEnd::Strikethrough => html.push_str("</del>"),
+                TagEnd::Link if open_links.pop().unwrap_or_default() => html.push_str("</a>"),
+                _ => {}
+            },
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                html.push_str(&htmlescape::encode_minimal(text));
+            }
+            Event::Code(code) => html.push_str(&format!(
+                r#"<code style="{}">{}</code>"#,
+                CODE_STYLE,
+                htmlescape::encode_minimal(code)
+            )),
+            Event::SoftBreak => html.push('\n'),
+            Event::HardBreak => html.push_str("<br />"),
+            Event::Rule => html.push_str(&format!(r#"<hr style="{}" />"#, RULE_STYLE)),
+            _ => {}
+        }
+    }
+    html.push_str("</div>");
+    html
+}
+
+fn open(html: &mut String, name: &str, style: &str) {
+    html.push_str(&format!(r#"<{} style="{}">"#, name, style));
+}
+
+fn heading(level: HeadingLevel) -> (&'static str, &'static str) {
+    match level {
+        HeadingLevel::H1 => ("h1", H1_STYLE),
+        HeadingLevel::H2 => ("h2", H2_STYLE),
+        HeadingLevel::H3 => ("h3", H3_STYLE),
+        HeadingLevel::H4 => ("h4", H3_STYLE),
+        HeadingLevel::H5 => ("h5", H3_STYLE),
+        HeadingLevel::H6 => ("h6", H3_STYLE),
+    }
+}
+
+/// The plain text part, built up line by line with the indentation of the
+/// blockquotes and lists the current line is in.
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    footnotes: Vec<String>,
+    quote_depth: usize,
+    // the next number of each open list, `None` for bullets
+    lists: Vec<Option<u64>>,
+    // where the open heading's text starts, to underline it
+    heading_start: usize,
+    // the destination and where the text of each open link starts
+    open_links: Vec<(String, usize)>,
+    // no blank line between a list marker and the paragraph it starts
+    at_item_start: bool,
+    in_code_block: bool,
+}
+
+impl TextWriter {
+    fn prefix(&self) -> String {
+        format!(
+            "{}{}",
+            "> ".repeat(self.quote_depth),
+            "   ".repeat(self.lists.len())
+        )
+    }
+
+    /// Start a new line, or reuse the current one if nothing but quote
+    /// markers and indentation went on it yet.
+    fn start_line(&mut self) {
+        let line_start = self.text.rfind('\n').map_or(0, |i| i + 1);
+        if self.text[line_start..]
+            .chars()
+            .all(|c| c == '>' || c == ' ')
+        {
+            self.text.truncate(line_start);
+        } else {
+            self.text.push('\n');
+        }
+        let prefix = self.prefix();
+        self.text.push_str(&prefix);
+    }
+
+    fn start_block(&mut self) {
+        if self.at_item_start {
+            self.at_item_start = false;
+            return;
+        }
+        if !self.text.is_empty() {
+            if !self.text.ends_with('\n') {
+                self.text.push('\n');
+            }
+            let prefix = self.prefix();
+            self.text.push_str(prefix.trim_end());
+            self.text.push('\n');
+        }
+        self.start_line();
+    }
+
+    fn footnote(&mut self, destination: &str) -> usize {
+        self.footnotes.push(destination.trim().to_owned());
+        self.footnotes.len()
+    }
+}
+
+fn render_text(events: &[Event]) -> String {
+    let mut writer = TextWriter::default();
+    for event in events {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph | Tag::Heading { .. } | Tag::HtmlBlock => {
+                    writer.start_block();
+                    writer.heading_start = writer.text.len();
+                }
+                Tag::BlockQuote(_) => {
+                    writer.start_block();
+                    writer.quote_depth += 1;
+                    writer.start_line();
+                    // the paragraph inside goes on the line just opened
+                    writer.at_item_start = true;
+                }
+                Tag::CodeBlock(_) => {
+                    writer.start_block();
+                    writer.in_code_block = true;
+                    writer.text.push_str("    ");
+                }
+                Tag::List(start) => {
+                    writer.start_block();
+                    writer.lists.push(*start);
+                }
+                Tag::Item => {
+                    let marker = match writer.lists.last_mut() {
+                        Some(Some(n)) => {
+                            *n += 1;
+                            format!("{}.", *n - 1)
+                        }
+                        _ => "-".to_owned(),
+                    };
+                    // the marker hangs into the indentation of its own list
+                    writer.start_line();
+                    writer.text.truncate(writer.text.len() - 3);
+                    writer.text.push_str(&marker);
+                    writer.text.push(' ');
+                    writer.at_item_start = true;
+                }
+                Tag::Link { dest_url, .. } => {
+                    let start = writer.text.len();
+                    writer.open_links.push((dest_url.to_string(), start));
+                }
+                Tag::Image { dest_url, .. } => {
+                    writer.text.push_str("[image: ");
+                    let start = writer.text.len();
+                    writer.open_links.push((dest_url.to_string(), start));
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Heading(level) => {
+                    let underline = match level {
+                        HeadingLevel::H1 => Some('='),
+                        HeadingLevel::H2 => Some('-'),
+                        _ => None,
+                    };
+                    if let Some(underline) = underline {
+                        let length = writer.text[writer.heading_start..].chars().count();
+                        writer.start_line();
+                        writer
+                            .text
+                            .push_str(&underline.to_string().repeat(length.max(3)));
+                    }
+                }
+                TagEnd::BlockQuote(_) => {
+                    writer.quote_depth -= 1;
+                    writer.at_item_start = false;
+                }
+                TagEnd::CodeBlock => {
+                    writer.in_code_block = false;
+                    // the last line of code ends in a newline of its own
+                    writer.text.truncate(writer.text.trim_end().len());
+                }
+                TagEnd::HtmlBlock => writer.text.truncate(writer.text.trim_end().len()),
+                TagEnd::List(_) => {
+                    writer.lists.pop();
+                }
+                TagEnd::Item => writer.at_item_start = false,
+                TagEnd::Link => {
+                    let (destination, start) = writer.open_links.pop().unwrap_or_default();
+                    // a bare URL already says where it goes
+                    if writer.text[start..] != *destination.trim() && is_safe_link(&destination) {
+                        let n = writer.footnote(&destination);
+                        writer.text.push_str(&format!(" [{}]", n));
+                    }
+                }
+                TagEnd::Image => {
+                    let (destination, _) = writer.open_links.pop().unwrap_or_default();
+                    writer.text.push(']');
+                    if is_safe_image(&destination) {
+                        let n = writer.footnote(&destination);
+                        writer.text.push_str(&format!(" [{}]", n));
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(text) if writer.in_code_block => {
+                let prefix = format!("{}    ", writer.prefix());
+                let mut lines = text.split('\n').peekable();
+                while let Some(line) = lines.next() {
+                    writer.text.push_str(line);
+                    if lines.peek().is_some() {
+                        writer.text.push('\n');
+                        writer.text.push_str(&prefix);
+                    }
+                }
+            }
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                writer.text.push_str(text);
+            }
+            Event::Code(code) => writer.text.push_str(&format!("`{}`", code)),
+            Event::SoftBreak | Event::HardBreak => writer.start_line(),
+            Event::Rule => {
+                writer.start_block();
+                writer.text.push_str("----------");
+            }
+            _ => {}
+        }
+    }
+    let mut text = writer.text.trim_end().to_owned();
+    if !writer.footnotes.is_empty() {
+        text.push('\n');
+        for (i, destination) in writer.footnotes.iter().enumerate() {
+            text.push_str(&format!("\n[{}] {}", i + 1, destination));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn every_element_gets_its_inline_style() {
+        let html = render_markdown("# Hello\n\nSome *text*.").html;
+        assert!(html.starts_with(r#"<div style="font-family:"#));
+        assert!(html.contains(r#"<h1 style="margin: 24px 0 12px; font-size: 26px;"#));
+        assert!(html.contains(r#"<p style="margin: 0 0 16px;">Some <em>text</em>.</p>"#));
+    }
+
+    #[test]
+    fn raw_html_is_shown_as_text() {
+        let html =
+            render_markdown("Hi <script>alert(1)</script>\n\n<img src=x onerror=alert(1)>").html;
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.ends_with(
+            r#"<p style="margin: 0 0 16px;">&lt;img src=x onerror=alert(1)&gt;</p></div>"#
+        ));
+    }
+
+    #[test]
+    fn only_safe_links_are_kept() {
+        let html = render_markdown(
+            "[ok](https://example.com) [bad](javascript:alert(1)) [mail](mailto:a@b.c)",
+        )
+        .html;
+        assert!(html.contains(r#"<a href="https://example.com""#));
+        assert!(html.contains(r#"<a href="mailto:a@b.c""#));
+        assert!(!html.contains("javascript"));
+        assert!(html.contains(" bad "));
+    }
+
+    #[test]
+    fn merge_tags_survive_in_text_and_links() {
+        let rendered = render_markdown("Hi {{ name }}, [unsubscribe]({{unsubscribe_url}})");
+        assert!(rendered.html.contains("Hi {{ name }}"));
+        assert!(rendered.html.contains(r#"href="{{unsubscribe_url}}""#));
+        assert_eq!(
+            rendered.text,
+            "Hi {{ name }}, unsubscribe [1]\n\n[1] {{unsubscribe_url}}"
+        );
+    }
+
+    #[test]
+    fn only_link_tags_can_be_link_targets() {
+        let html = render_markdown("[site]({{website}}) [me]({{name}})").html;
+        assert!(!html.contains("href"));
+        assert!(html.contains("site me"));
+    }
+
+    #[test]
+    fn links_become_footnotes_in_the_plain_text() {
+        let text = render_markdown(
+            "Read [the post](https://example.com/post) or\n[the docs](https://example.com/docs).\n\nSee https://example.com too.",
+        )
+        .text;
+        assert_eq!(
+            text,
+            "Read the post [1] or\nthe docs [2].\n\nSee https://example.com too.\n\n[1] https://example.com/post\n[2] https://example.com/docs"
+        );
+    }
+
+    #[test]
+    fn the_plain_text_keeps_the_shape_of_the_markdown() {
+        let text = render_markdown(
+            "# Title\n\nIntro.\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted\n\n```\nlet x = 1;\n```",
+        )
+        .text;
+        assert_eq!(
+            text,
+            "Title\n=====\n\nIntro.\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted\n\n    let x = 1;"
+        );
+    }
+
+    #[test]
+    fn nested_lists_and_quotes_are_indented() {
+        let text = render_markdown("- one\n  - inner\n- two\n\n> quote\n> - a\n> - b").text;
+        assert_eq!(text, "- one\n   - inner\n- two\n\n> quote\n>\n> - a\n> - b");
+    }
+}
@@ -0,0 +1,219 @@
+//! Merge tags in issue bodies, filled in for each recipient, e.g.
+//! `Hi {{ name }}`.
+//!
+//! Tags are plain variables: the built-in ones below and the key of any
+//! custom field. There are no expressions or filters, so a template can't
+//! reach anything it isn't handed. `\{{` stands for a literal `{{`.
+
+use std::collections::HashMap;
+
+pub const BUILT_IN_TAGS: [&str; 6] = [
+    "name",
+    "email",
+    "language",
+    "list_name",
+    "unsubscribe_url",
+    "preferences_url",
+];
+
+/// The tags a link may point at. Their values are links we sign ourselves,
+/// everything else can be set by subscribers.
+pub const LINK_TAGS: [&str; 2] = ["unsubscribe_url", "preferences_url"];
+
+#[derive(Debug, PartialEq)]
+pub struct MergeTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Tag(String),
+}
+
+impl MergeTemplate {
+    pub fn parse(s: &str) -> Result<MergeTemplate, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if rest[..start].ends_with('\\') {
+                text.push_str(&rest[..start - 1]);
+                text.push_str("{{");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            text.push_str(&rest[..start]);
+            let Some(length) = rest[start + 2..].find("}}") else {
+                return Err("A `{{` is never closed, write `\\{{` for a literal one.".into());
+            };
+            let tag = rest[start + 2..start + 2 + length].trim();
+            let is_variable = !tag.is_empty()
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !is_variable {
+                return Err(format!(
+                    "`{{{{ {} }}}}` is not a merge tag, tags are variable names like `{{{{ name }}}}`.",
+                    tag
+                ));
+            }
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(Part::Tag(tag.to_owned()));
+            rest = &rest[start + 2 + length + 2..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Reject tags that are neither built in nor in `custom_fields`.
+    pub fn check(&self, custom_fields: &[&str]) -> Result<(), String> {
+        for part in &self.parts {
+            if let Part::Tag(tag) = part {
+                if !BUILT_IN_TAGS.contains(&tag.as_str()) && !custom_fields.contains(&tag.as_str())
+                {
+                    return Err(format!(
+                        "There is no merge tag called `{{{{ {} }}}}`, use {} or the key of a custom field.",
+                        tag,
+                        BUILT_IN_TAGS.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in every tag with its `escape`d value, tags without one are left
+    /// empty. So are link tags whose value is not a web address.
+    pub fn render(
+        &self,
+        values: &HashMap<String, String>,
+        escape: impl Fn(&str) -> String,
+    ) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Tag(tag) => {
+                    let value = values
+                        .get(tag)
+                        .filter(|value| !LINK_TAGS.contains(&tag.as_str()) || is_safe_url(value));
+                    if let Some(value) = value {
+                        rendered.push_str(&escape(value));
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+// a fragment stands in for a link in previews
+fn is_safe_url(value: &str) -> bool {
+    let lowercase = value.trim().to_ascii_lowercase();
+    lowercase.starts_with("http://") || lowercase.starts_with("https://") || value.starts_with('#')
+}
+
+/// Render `template` for one recipient.
+///
+/// Bodies are checked when the issue is published. One that still doesn't
+/// parse was queued before merge tags existed and goes out as written.
+pub fn fill_merge_tags(
+    template: &str,
+    values: &HashMap<String, String>,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    match MergeTemplate::parse(template) {
+        Ok(template) => template.render(values, escape),
+        Err(_) => template.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use claims::{assert_err, assert_ok};
+
+    use super::{fill_merge_tags, MergeTemplate};
+
+    fn values() -> HashMap<String, String> {
+        HashMap::from([
+            ("name".to_string(), "Ursula".to_string()),
+            ("company".to_string(), "Fish & Chips".to_string()),
+            ("role".to_string(), "developer".to_string()),
+        ])
+    }
+
+    #[test]
+    fn tags_are_filled_in_with_or_without_spaces() {
+        let filled = fill_merge_tags("{{company}} hires a {{ role }}.", &values(), str::to_owned);
+        assert_eq!(filled, "Fish & Chips hires a developer.");
+    }
+
+    #[test]
+    fn values_go_through_escape() {
+        let filled = fill_merge_tags("<p>{{ company }}</p>", &values(), |value| {
+            htmlescape::encode_minimal(value)
+        });
+        assert_eq!(filled, "<p>Fish &amp; Chips</p>");
+    }
+
+    #[test]
+    fn tags_without_a_value_are_left_empty() {
+        let filled = fill_merge_tags("Hi {{ name }}{{ city }}!", &values(), str::to_owned);
+        assert_eq!(filled, "Hi Ursula!");
+    }
+
+    #[test]
+    fn link_tags_only_take_web_addresses() {
+        let template = "<a href=\"{{ unsubscribe_url }}\">";
+        for (value, expected) in [
+            (
+                "https://example.com/u",
+                "<a href=\"https://example.com/u\">",
+            ),
+            ("javascript:alert(1)", "<a href=\"\">"),
+        ] {
+            let values = HashMap::from([("unsubscribe_url".to_string(), value.to_string())]);
+            assert_eq!(fill_merge_tags(template, &values, str::to_owned), expected);
+        }
+    }
+
+    #[test]
+    fn an_escaped_brace_pair_is_literal() {
+        let filled = fill_merge_tags(r#"println!("\{{}}", {{ name }})"#, &values(), str::to_owned);
+        assert_eq!(filled, r#"println!("{{}}", Ursula)"#);
+    }
+
+    #[test]
+    fn anything_but_a_variable_is_rejected() {
+        for template in [
+            "Hi {{ name",
+            "Hi {{ }}",
+            "Hi {{ user.name }}",
+            "Hi {{ name | upper }}",
+            "Hi {{ Name }}",
+        ] {
+            assert_err!(MergeTemplate::parse(template), "{}", template);
+        }
+    }
+
+    #[test]
+    fn unknown_tags_fail_the_check() {
+        let template = MergeTemplate::parse("{{ name }} at {{ company }}").unwrap();
+        assert_ok!(template.check(&["company"]));
+        assert_err!(template.check(&["role"]));
+    }
+
+    #[test]
+    fn a_body_that_does_not_parse_goes_out_as_written() {
+        let filled = fill_merge_tags("fn main() { {{ }", &values(), str::to_owned);
+        assert_eq!(filled, "fn main() { {{ }");
+    }
+}
@@ -0,0 +1,100 @@
+//! What subscribers choose for themselves on the preferences page: their
+//! name, how and in which language they get issues, and breaks from them.
+//!
+//! Which lists they are on is up to `lists`.
+
+use chrono::{DateTime, Utc};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{DeliveryPreference, Language, SubscriberName};
+
+pub struct SubscriberPreferences {
+    pub name: String,
+    pub status: String,
+    pub delivery: DeliveryPreference,
+    pub language: Language,
+    // `None` unless delivery is paused as of now
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+/// `None` if there is no such subscriber, e.g. they erased their data.
+#[tracing::instrument(name = "Get the preferences of a subscriber", skip(pool))]
+pub async fn get_preferences(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberPreferences>, anyhow::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let Some(r) = sqlx::query!(
+        r#"
+        SELECT name, status, delivery_preference, language, paused_until
+        FROM subscriptions
+        WHERE uuid = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let paused_until = match r.paused_until {
+        Some(paused_until) => Some(paused_until.parse::<DateTime<Utc>>()?),
+        None => None,
+    }
+    .filter(|paused_until| *paused_until > Utc::now());
+    Ok(Some(SubscriberPreferences {
+        name: r.name,
+        status: r.status,
+        delivery: DeliveryPreference::parse(&r.delivery_preference).map_err(anyhow::Error::msg)?,
+        language: Language::parse(&r.language).map_err(anyhow::Error::msg)?,
+        paused_until,
+    }))
+}
+
+#[tracing::instrument(name = "Update the preferences of a subscriber", skip(transaction))]
+pub async fn save_preferences(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+    delivery: DeliveryPreference,
+    language: Language,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let name = name.as_ref();
+    let delivery = delivery.as_str();
+    let language = language.as_str();
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, delivery_preference = $3, language = $4
+        WHERE uuid = $1
+        "#,
+        subscriber_id,
+        name,
+        delivery,
+        language,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Send the subscriber nothing before `until`, or resume right away if `None`.
+#[tracing::instrument(name = "Pause delivery to a subscriber", skip(pool))]
+pub async fn set_paused_until(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+    until: Option<DateTime<Utc>>,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let until = until.map(|until| until.to_string());
+    sqlx::query!(
+        r#"UPDATE subscriptions SET paused_until = $2 WHERE uuid = $1"#,
+        subscriber_id,
+        until,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
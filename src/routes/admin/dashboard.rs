@@ -1,11 +1,14 @@
 use std::sync::Arc;
 
+use crate::issue_delivery_worker::{get_dead_letters, DeadLetter};
+use crate::routes::admin::email::get_user_email;
 use crate::session_state::TypedSession;
 use crate::startup::AppState;
 use crate::utils::e500;
 use anyhow::Context;
 use axum::extract::State;
 use axum::response::{Html, IntoResponse, Redirect};
+use axum_messages::Messages;
 use rinja_axum::Template;
 use sqlx::SqlitePool;
 use uuid::Uuid;
@@ -14,23 +17,35 @@ use uuid::Uuid;
 #[template(path = "dashboard/index.html")]
 struct DashboardTemplate<'a> {
     username: &'a str,
+    // where test sends go, empty until they set one
+    email: String,
+    errors: Vec<String>,
+    dead_letters: Vec<DeadLetter>,
 }
 
 pub async fn admin_dashboard(
     State(app_state): State<Arc<AppState>>,
     session: TypedSession,
+    messages: Messages,
     // TODO:
     // do proper error handling
 ) -> Result<axum::response::Response, axum::response::Response> {
-    let username = if let Some(user_id) = session.get_user_id().await.map_err(e500)? {
-        get_username(user_id, &app_state.pool).await.map_err(e500)?
-    } else {
+    let Some(user_id) = session.get_user_id().await.map_err(e500)? else {
         return Ok(Redirect::to("/login").into_response());
     };
+    let username = get_username(user_id, &app_state.pool).await.map_err(e500)?;
+    let email = get_user_email(user_id, &app_state.pool)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+    let dead_letters = get_dead_letters(&app_state.pool).await.map_err(e500)?;
 
     Ok(Html(
         DashboardTemplate {
             username: &username,
+            email,
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            dead_letters,
         }
         .render()
         .unwrap(),
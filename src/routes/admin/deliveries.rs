@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+
+use crate::issue_delivery_worker::requeue_dead_letter;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    delivery_id: i64,
+}
+
+#[tracing::instrument(name = "Requeue a delivery", skip(app_state, messages, form))]
+pub async fn requeue_delivery(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    if requeue_dead_letter(&app_state.pool, form.delivery_id)
+        .await
+        .map_err(e500)?
+    {
+        app_state.delivery_trigger.notify_one();
+        messages.info("The delivery has been requeued.");
+    } else {
+        messages.error("There is no failed delivery with that id.");
+    }
+    Ok(Redirect::to("/admin/dashboard").into_response())
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use axum::{Extension, Form};
+use axum_messages::Messages;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Where test sends of a draft go.
+pub async fn change_email(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Extension(user_id): Extension<UserId>,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(e) => {
+            messages.error(e);
+            return Ok(Redirect::to("/admin/dashboard").into_response());
+        }
+    };
+    let user_id = user_id.to_string();
+    let email = email.as_ref();
+    sqlx::query!(
+        r#"UPDATE users SET email = $1 WHERE uuid = $2"#,
+        email,
+        user_id,
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(e500)?;
+    messages.success("Your email address has been changed.");
+    Ok(Redirect::to("/admin/dashboard").into_response())
+}
+
+#[tracing::instrument(name = "Get the email address of a user", skip(pool))]
+pub async fn get_user_email(
+    user_id: Uuid,
+    pool: &SqlitePool,
+) -> Result<Option<String>, anyhow::Error> {
+    let user_id = user_id.to_string();
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE uuid = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve an email address.")?;
+    Ok(row.email)
+}
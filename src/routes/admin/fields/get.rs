@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{Html, IntoResponse};
+use axum_messages::Messages;
+use rinja_axum::Template;
+
+use crate::custom_fields::get_custom_fields;
+use crate::domain::CustomField;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(Template)]
+#[template(path = "fields/index.html")]
+struct CustomFieldsTemplate {
+    errors: Vec<String>,
+    fields: Vec<CustomField>,
+}
+
+#[tracing::instrument(name = "Custom fields page", skip(app_state, messages))]
+pub async fn custom_fields_page(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let fields = get_custom_fields(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(
+        CustomFieldsTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            fields,
+        }
+        .render()
+        .map_err(e500)?,
+    )
+    .into_response())
+}
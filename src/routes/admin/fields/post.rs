@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+
+use crate::custom_fields::insert_custom_field;
+use crate::domain::CustomField;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    key: String,
+    label: String,
+    field_type: String,
+    #[serde(default)]
+    rule: String,
+    // a checkbox, only sent when ticked
+    #[serde(default)]
+    required: Option<String>,
+}
+
+#[tracing::instrument(name = "Create a custom field", skip(app_state, messages, form))]
+pub async fn create_custom_field(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let redirect = Redirect::to("/admin/fields").into_response();
+    let field = match CustomField::parse(
+        form.key.trim().to_owned(),
+        form.label,
+        &form.field_type,
+        form.rule,
+        form.required.is_some(),
+    ) {
+        Ok(field) => field,
+        Err(e) => {
+            messages.error(e);
+            return Ok(redirect);
+        }
+    };
+    let created = insert_custom_field(&app_state.pool, &field)
+        .await
+        .map_err(e500)?;
+    if created {
+        messages.success(format!("The field {} has been created.", field.key));
+    } else {
+        messages.error(format!("There already is a field called {}.", field.key));
+    }
+    Ok(redirect)
+}
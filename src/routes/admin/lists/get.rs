@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{Html, IntoResponse};
+use axum_messages::Messages;
+use rinja_axum::Template;
+
+use crate::lists::{get_lists, ListSummary};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(Template)]
+#[template(path = "lists/index.html")]
+struct ListsTemplate {
+    errors: Vec<String>,
+    lists: Vec<ListSummary>,
+}
+
+#[tracing::instrument(name = "Lists page", skip(app_state, messages))]
+pub async fn lists_page(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let lists = get_lists(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(
+        ListsTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            lists,
+        }
+        .render()
+        .map_err(e500)?,
+    )
+    .into_response())
+}
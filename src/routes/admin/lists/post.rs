@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+
+use crate::domain::ListSlug;
+use crate::lists::insert_list;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a list", skip(app_state, messages, form))]
+pub async fn create_list(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let slug = match ListSlug::parse(form.slug.trim().to_owned()) {
+        Ok(slug) => slug,
+        Err(e) => {
+            messages.error(e);
+            return Ok(Redirect::to("/admin/lists").into_response());
+        }
+    };
+    let name = form.name.trim();
+    if name.is_empty() {
+        messages.error("Give the list a name.");
+        return Ok(Redirect::to("/admin/lists").into_response());
+    }
+    let created = insert_list(&app_state.pool, &slug, name)
+        .await
+        .map_err(e500)?;
+    if created {
+        messages.success(format!("The list {} has been created.", slug.as_ref()));
+    } else {
+        messages.error(format!("There already is a list called {}.", slug.as_ref()));
+    }
+    Ok(Redirect::to("/admin/lists").into_response())
+}
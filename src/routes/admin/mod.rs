@@ -1,9 +1,21 @@
 mod dashboard;
+mod deliveries;
+mod email;
+mod fields;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod segments;
+mod subscribers;
 
 pub use dashboard::admin_dashboard;
+pub use deliveries::requeue_delivery;
+pub use email::change_email;
+pub use fields::*;
+pub use lists::*;
 pub use logout::log_out;
 pub use newsletter::*;
 pub use password::*;
+pub use segments::*;
+pub use subscribers::*;
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use axum_messages::Messages;
+
+use crate::blog_posts::announce_new_posts;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+/// Draft an issue for each blog post that went out since the last look, as
+/// happens when the app starts.
+#[tracing::instrument(name = "Sync blog posts", skip(app_state, messages))]
+pub async fn sync_blog_posts(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let announcements = announce_new_posts(&app_state.pool, &app_state.blog, &app_state.base_url.0)
+        .await
+        .map_err(e500)?;
+    if announcements.is_empty() {
+        messages.info("There are no new blog posts.");
+        return Ok(Redirect::to("/admin/newsletters/drafts").into_response());
+    }
+    if app_state.blog.auto_send {
+        app_state.schedule_trigger.notify_one();
+    }
+    let mut messages = messages;
+    for announcement in announcements {
+        messages = if app_state.blog.auto_send {
+            messages.info(format!("{} is going out now.", announcement.title))
+        } else {
+            messages.info(format!("{} is drafted.", announcement.title))
+        };
+    }
+    Ok(Redirect::to("/admin/newsletters/drafts").into_response())
+}
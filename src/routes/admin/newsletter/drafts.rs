@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect};
+use axum_extra::extract::Form;
+use axum_messages::Messages;
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::domain::{BodyFormat, IssueBody};
+use crate::drafts::{get_draft, get_drafts, save_draft, DraftSummary};
+use crate::lists::{get_lists, ListSummary};
+use crate::segments::{get_segments, SegmentRow};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+use super::recipients::resolve_audience;
+
+#[derive(Template)]
+#[template(path = "newsletter_drafts/index.html")]
+struct DraftsTemplate {
+    errors: Vec<String>,
+    drafts: Vec<DraftSummary>,
+}
+
+#[tracing::instrument(name = "Drafts page", skip(app_state, messages))]
+pub async fn drafts_page(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let drafts = get_drafts(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(
+        DraftsTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            drafts,
+        }
+        .render()
+        .unwrap(),
+    )
+    .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_draft/index.html")]
+struct DraftTemplate {
+    errors: Vec<String>,
+    draft_id: Uuid,
+    title: String,
+    format: &'static str,
+    markdown_content: String,
+    text_content: String,
+    html_content: String,
+    lists: Vec<DraftList>,
+    segment_id: String,
+    segments: Vec<SegmentRow>,
+    hidden_from_archive: bool,
+    idempotency_key: String,
+    scheduled: bool,
+    // the send time as the schedule form takes it, and as the page shows it
+    scheduled_for: String,
+    send_on: String,
+    timezone: String,
+    schedule_error: String,
+}
+
+struct DraftList {
+    list: ListSummary,
+    // whether the draft is meant for it
+    checked: bool,
+}
+
+#[tracing::instrument(name = "Draft form", skip(app_state, messages))]
+pub async fn draft_form(
+    State(app_state): State<Arc<AppState>>,
+    Path(draft_id): Path<Uuid>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    let Some(draft) = get_draft(&mut connection, draft_id).await.map_err(e500)? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let lists = get_lists(&app_state.pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| DraftList {
+            checked: draft.lists.contains(&list.slug),
+            list,
+        })
+        .collect();
+    let segments = get_segments(&app_state.pool).await.map_err(e500)?;
+    let schedule = draft.schedule.as_ref();
+    Ok(Html(
+        DraftTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            draft_id,
+            title: draft.title,
+            format: draft.body.format.as_str(),
+            markdown_content: draft.body.markdown_content,
+            text_content: draft.body.text_content,
+            html_content: draft.body.html_content,
+            lists,
+            segment_id: draft
+                .segment_id
+                .map(|segment_id| segment_id.to_string())
+                .unwrap_or_default(),
+            segments,
+            hidden_from_archive: draft.hidden_from_archive,
+            idempotency_key: Uuid::new_v4().to_string(),
+            scheduled: schedule.is_some(),
+            scheduled_for: schedule
+                .map(|schedule| schedule.local().format("%Y-%m-%dT%H:%M").to_string())
+                .unwrap_or_default(),
+            send_on: schedule
+                .map(|schedule| schedule.local().format("%A %Y-%m-%d at %H:%M").to_string())
+                .unwrap_or_default(),
+            timezone: schedule
+                .map(|schedule| schedule.timezone.name())
+                .unwrap_or("UTC")
+                .to_owned(),
+            schedule_error: draft.schedule_error.unwrap_or_default(),
+        }
+        .render()
+        .unwrap(),
+    )
+    .into_response())
+}
+
+/// The publish form, minus the idempotency key: a draft can be saved any
+/// number of times.
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    #[serde(default)]
+    format: BodyFormat,
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    lists: Vec<String>,
+    #[serde(default)]
+    segment: String,
+    #[serde(default)]
+    hidden_from_archive: bool,
+}
+
+/// Save the publish form as a new draft. Unlike publishing, an unfinished
+/// body is fine.
+#[tracing::instrument(name = "Create a draft", skip(app_state, messages, form))]
+pub async fn create_draft(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Form(form): Form<DraftFormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    store_draft(&app_state, messages, Uuid::new_v4(), form).await
+}
+
+#[tracing::instrument(name = "Update a draft", skip(app_state, messages, form))]
+pub async fn update_draft(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Path(draft_id): Path<Uuid>,
+    Form(form): Form<DraftFormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    if get_draft(&mut connection, draft_id)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    store_draft(&app_state, messages, draft_id, form).await
+}
+
+async fn store_draft(
+    app_state: &AppState,
+    messages: Messages,
+    draft_id: Uuid,
+    form: DraftFormData,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let audience = resolve_audience(app_state, form.lists, &form.segment).await?;
+    let body = IssueBody {
+        format: form.format,
+        markdown_content: form.markdown_content,
+        text_content: form.text_content,
+        html_content: form.html_content,
+    };
+    save_draft(
+        &app_state.pool,
+        draft_id,
+        &form.title,
+        &body,
+        &audience.list_ids,
+        audience.segment.map(|(segment_id, _)| segment_id),
+        form.hidden_from_archive,
+    )
+    .await
+    .map_err(e500)?;
+    messages.info("The draft has been saved.");
+    Ok(Redirect::to(&format!("/admin/newsletters/drafts/{}", draft_id)).into_response())
+}
@@ -1,20 +1,40 @@
+use std::sync::Arc;
+
+use axum::extract::State;
 use axum::response::{Html, IntoResponse};
 use axum_messages::Messages;
 use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::lists::{get_lists, ListSummary, DEFAULT_LIST_SLUG};
+use crate::segments::{get_segments, SegmentRow};
+use crate::startup::AppState;
+use crate::utils::e500;
 
 #[derive(Template)]
 #[template(path = "publish_newsletter/index.html")]
 struct PublishNewsletterTemplate {
     errors: Vec<String>,
+    idempotency_key: String,
+    lists: Vec<ListSummary>,
+    default_list: &'static str,
+    segments: Vec<SegmentRow>,
 }
 
-#[tracing::instrument(name = "Publish newsletter form", skip(messages))]
+#[tracing::instrument(name = "Publish newsletter form", skip(app_state, messages))]
 pub async fn publish_newsletter_form(
+    State(app_state): State<Arc<AppState>>,
     messages: Messages,
 ) -> Result<axum::response::Response, axum::response::Response> {
+    let lists = get_lists(&app_state.pool).await.map_err(e500)?;
+    let segments = get_segments(&app_state.pool).await.map_err(e500)?;
     Ok(Html(
         PublishNewsletterTemplate {
             errors: messages.into_iter().map(|m| m.message).collect(),
+            idempotency_key: Uuid::new_v4().to_string(),
+            lists,
+            default_list: DEFAULT_LIST_SLUG,
+            segments,
         }
         .render()
         .unwrap(),
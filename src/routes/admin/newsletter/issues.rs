@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::archive::{get_published_issues, set_hidden_from_archive, PublishedIssue};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(Template)]
+#[template(path = "newsletter_issues/index.html")]
+struct IssuesTemplate {
+    errors: Vec<String>,
+    issues: Vec<PublishedIssue>,
+}
+
+#[tracing::instrument(name = "Published issues page", skip(app_state, messages))]
+pub async fn issues_page(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let issues = get_published_issues(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(
+        IssuesTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            issues,
+        }
+        .render()
+        .unwrap(),
+    )
+    .into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveFormData {
+    hidden: bool,
+}
+
+/// Take an issue out of the public archive, or put it back.
+#[tracing::instrument(
+    name = "Show or hide an issue in the archive",
+    skip(app_state, messages, form)
+)]
+pub async fn set_issue_archived(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Path(issue_id): Path<Uuid>,
+    Form(form): Form<ArchiveFormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    if !set_hidden_from_archive(&app_state.pool, issue_id, form.hidden)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    messages.info(if form.hidden {
+        "The issue is hidden from the public archive."
+    } else {
+        "The issue is in the public archive."
+    });
+    Ok(Redirect::to("/admin/newsletters/issues").into_response())
+}
@@ -1,5 +1,17 @@
+mod blog;
+mod drafts;
 mod get;
+mod issues;
 mod post;
+mod preview;
+mod recipients;
+mod schedule;
 
+pub use blog::sync_blog_posts;
+pub use drafts::{create_draft, draft_form, drafts_page, update_draft};
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use issues::{issues_page, set_issue_archived};
+pub use post::{publish_digest, publish_draft, publish_newsletter, publish_scheduled_draft};
+pub use preview::{preview_draft, send_test_issue};
+pub use recipients::count_recipients;
+pub use schedule::{schedule_draft, unschedule_draft};
@@ -1,21 +1,54 @@
 use std::sync::Arc;
 
+use crate::archive::unique_slug;
 use crate::authentication::UserId;
-use crate::domain::SubscriberEmail;
+use crate::custom_fields::get_custom_fields;
+use crate::domain::{
+    BodyFormat, DeliveryPreference, IssueBody, SegmentExpression, SubscriberEmail,
+};
+use crate::drafts::{claim_due_draft, delete_draft, get_draft, record_schedule_error};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::lists::{get_list_by_slug, DEFAULT_LIST_SLUG};
+use crate::merge_tags::MergeTemplate;
+use crate::routes::error_chain_fmt;
+use crate::segments::get_recipients;
 use crate::startup::AppState;
-use crate::utils::e500;
+use crate::utils::{e400, e500};
 use anyhow::Context;
-use axum::extract::State;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Redirect};
-use axum::{Extension, Form};
+use axum::Extension;
+// the plain one can't take a field more than once, as checkboxes send it
+use axum_extra::extract::Form;
 use axum_messages::Messages;
-use sqlx::SqlitePool;
+use chrono::Utc;
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use uuid::Uuid;
+
+use super::recipients::{find_audience, resolve_audience, Audience};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
+    #[serde(default)]
+    format: BodyFormat,
+    // the body in markdown mode, both parts are rendered from it
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
     text_content: String,
+    #[serde(default)]
     html_content: String,
+    idempotency_key: String,
+    // slugs of the lists to send the issue to, the default one if left out
+    #[serde(default)]
+    lists: Vec<String>,
+    // a saved segment's id, empty to send to everyone on the lists
+    #[serde(default)]
+    segment: String,
+    #[serde(default)]
+    hidden_from_archive: bool,
 }
 
 #[tracing::instrument(
@@ -29,25 +62,397 @@ pub async fn publish_newsletter(
     Extension(user_id): Extension<UserId>,
     Form(form): Form<FormData>,
 ) -> Result<axum::response::Response, axum::response::Response> {
-    let subscribers = get_confirmed_subscribers(&app_state.pool)
+    let FormData {
+        title,
+        format,
+        markdown_content,
+        text_content,
+        html_content,
+        idempotency_key,
+        lists,
+        segment,
+        hidden_from_archive,
+    } = form;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let body = IssueBody {
+        format,
+        markdown_content,
+        text_content,
+        html_content,
+    };
+    let Some((text_content, html_content)) = prepare_parts(&app_state, &messages, &body).await?
+    else {
+        return Ok(Redirect::to("/admin/newsletters").into_response());
+    };
+    let audience = resolve_audience(&app_state, lists, &segment).await?;
+    let mut transaction = match try_processing(&app_state.pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(messages);
+            return Ok(saved_response);
+        }
+    };
+    publish_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        &audience,
+        hidden_from_archive,
+    )
+    .await
+    .map_err(e500)?;
+    let response = Redirect::to("/admin/newsletters").into_response();
+    // committing the transaction happens as part of saving the response
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    // the worker may be sleeping on an empty queue
+    app_state.delivery_trigger.notify_one();
+    success_message(messages);
+    Ok(response)
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishDraftFormData {
+    idempotency_key: String,
+}
+
+/// Publish a draft as it was last saved.
+#[tracing::instrument(
+    name = "Publish a draft",
+    skip(form, app_state, messages, user_id),
+    fields(user_id=%user_id),
+)]
+pub async fn publish_draft(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Extension(user_id): Extension<UserId>,
+    Path(draft_id): Path<Uuid>,
+    Form(form): Form<PublishDraftFormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let idempotency_key: IdempotencyKey = form.idempotency_key.try_into().map_err(e400)?;
+    // the draft is gone once published, a retry has to find the saved response
+    let mut transaction = match try_processing(&app_state.pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(messages);
+            return Ok(saved_response);
+        }
+    };
+    let Some(draft) = get_draft(&mut transaction, draft_id).await.map_err(e500)? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    // leaving early drops the transaction, and the idempotency key with it
+    let Some((text_content, html_content)) =
+        prepare_parts(&app_state, &messages, &draft.body).await?
+    else {
+        return Ok(
+            Redirect::to(&format!("/admin/newsletters/drafts/{}", draft_id)).into_response(),
+        );
+    };
+    let segment = draft
+        .segment_id
+        .map(|segment_id| segment_id.to_string())
+        .unwrap_or_default();
+    let delivery = draft.delivery();
+    let audience = Audience {
+        delivery,
+        ..resolve_audience(&app_state, draft.lists, &segment).await?
+    };
+    publish_issue(
+        &mut transaction,
+        &draft.title,
+        &text_content,
+        &html_content,
+        &audience,
+        draft.hidden_from_archive,
+    )
+    .await
+    .map_err(e500)?;
+    delete_draft(&mut transaction, draft_id)
         .await
         .map_err(e500)?;
+    let response = Redirect::to("/admin/newsletters").into_response();
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    app_state.delivery_trigger.notify_one();
+    success_message(messages);
+    Ok(response)
+}
+
+/// The plain text and HTML parts of `body`, with its merge tags checked.
+///
+/// Returns `None` after flashing what is wrong with it.
+pub(super) async fn prepare_parts(
+    app_state: &AppState,
+    messages: &Messages,
+    body: &IssueBody,
+) -> Result<Option<(String, String)>, axum::response::Response> {
+    match render_parts(&app_state.pool, body).await {
+        Ok(parts) => Ok(Some(parts)),
+        Err(IssueError::Invalid(e)) => {
+            messages.clone().error(e);
+            Ok(None)
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
+
+/// Why an issue can't go out.
+#[derive(thiserror::Error)]
+pub enum IssueError {
+    /// Something the admin has to fix, e.g. an unknown merge tag.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+async fn render_parts(pool: &SqlitePool, body: &IssueBody) -> Result<(String, String), IssueError> {
+    let (text_content, html_content) = body.render().map_err(IssueError::Invalid)?;
+    let custom_fields = get_custom_fields(pool).await?;
+    let field_keys = custom_fields
+        .iter()
+        .map(|field| field.key.as_str())
+        .collect::<Vec<_>>();
+    // better now than halfway through the deliveries
+    let [text_name, html_name] = body.part_names();
+    check_merge_tags(
+        &[(text_name, &text_content), (html_name, &html_content)],
+        &field_keys,
+    )
+    .map_err(IssueError::Invalid)?;
+    Ok((text_content, html_content))
+}
+
+/// Publish a draft whose time has come, the scheduler's `publish_draft`.
+/// Returns whether it went out.
+///
+/// Taking the draft off the schedule, queueing its deliveries and deleting
+/// it commit together. If the process dies halfway the draft is still due
+/// when it comes back, and once committed no other run finds it due again.
+/// A draft that can't go out as it stands stays a draft, with a note saying
+/// why for the admin.
+#[tracing::instrument(name = "Publish a scheduled draft", skip(pool))]
+pub async fn publish_scheduled_draft(
+    pool: &SqlitePool,
+    draft_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    if !claim_due_draft(&mut transaction, draft_id, Utc::now()).await? {
+        return Ok(false);
+    }
+    let draft = get_draft(&mut transaction, draft_id)
+        .await?
+        .context("A due draft vanished")?;
+    let delivery = draft.delivery();
+    let prepared = match render_parts(pool, &draft.body).await {
+        Ok(parts) => find_audience(pool, draft.lists, draft.segment_id)
+            .await
+            .map(|audience| {
+                (
+                    parts,
+                    Audience {
+                        delivery,
+                        ..audience
+                    },
+                )
+            }),
+        Err(e) => Err(e),
+    };
+    let ((text_content, html_content), audience) = match prepared {
+        Ok(prepared) => prepared,
+        Err(IssueError::Invalid(e)) => {
+            tracing::warn!(error = %e, "A scheduled draft could not be published");
+            record_schedule_error(&mut transaction, draft_id, &e).await?;
+            transaction.commit().await?;
+            return Ok(false);
+        }
+        Err(IssueError::UnexpectedError(e)) => return Err(e),
+    };
+    publish_issue(
+        &mut transaction,
+        &draft.title,
+        &text_content,
+        &html_content,
+        &audience,
+        draft.hidden_from_archive,
+    )
+    .await?;
+    delete_draft(&mut transaction, draft_id).await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Store the issue, in the archive unless `hidden_from_archive`, and queue a
+/// delivery to each of its recipients.
+async fn publish_issue(
+    transaction: &mut Transaction<'_, Sqlite>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    audience: &Audience,
+    hidden_from_archive: bool,
+) -> Result<Uuid, anyhow::Error> {
+    let segment_id = audience.segment.as_ref().map(|(segment_id, _)| *segment_id);
+    let issue_id = insert_newsletter_issue(
+        transaction,
+        title,
+        text_content,
+        html_content,
+        segment_id,
+        hidden_from_archive,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(transaction, issue_id, audience)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    Ok(issue_id)
+}
+
+/// Publish a weekly digest of blog posts to the digest subscribers on the
+/// default list, as announcements of single posts go to the default list too.
+/// Digests stay out of the public archive, the blog has the posts already.
+pub async fn publish_digest(
+    transaction: &mut Transaction<'_, Sqlite>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<Uuid, anyhow::Error> {
+    let list = get_list_by_slug(&mut **transaction, DEFAULT_LIST_SLUG)
+        .await?
+        .context("The default list is missing")?;
+    let audience = Audience {
+        list_ids: vec![list.id],
+        segment: None,
+        delivery: Some(DeliveryPreference::WeeklyDigest),
+    };
+    publish_issue(
+        transaction,
+        title,
+        text_content,
+        html_content,
+        &audience,
+        true,
+    )
+    .await
+}
+
+fn success_message(messages: Messages) {
+    messages.info("The newsletter issue has been published!");
+}
+
+/// `bodies` are the parts of the issue with the name of the field they came
+/// from.
+fn check_merge_tags(bodies: &[(&str, &String)], field_keys: &[&str]) -> Result<(), String> {
+    for (body, content) in bodies {
+        MergeTemplate::parse(content)
+            .and_then(|template| template.check(field_keys))
+            .map_err(|e| format!("In the {} content: {}", body, e))?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Sqlite>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    segment_id: Option<Uuid>,
+    hidden_from_archive: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let uuid = newsletter_issue_id.to_string();
+    let slug = unique_slug(transaction, title).await?;
+    let published_at = Utc::now().to_string();
+    let segment_id = segment_id.map(|segment_id| segment_id.to_string());
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            uuid,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            segment_id,
+            slug,
+            hidden_from_archive,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $5)
+        "#,
+        uuid,
+        title,
+        text_content,
+        html_content,
+        published_at,
+        segment_id,
+        slug,
+        hidden_from_archive,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Sqlite>,
+    newsletter_issue_id: Uuid,
+    audience: &Audience,
+) -> Result<(), anyhow::Error> {
+    let newsletter_issue_id = newsletter_issue_id.to_string();
+    for list_id in &audience.list_ids {
+        let list_id = list_id.to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            newsletter_issue_id,
+            list_id,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+    let segment = audience.segment.as_ref().map(|(_, expression)| expression);
+    let subscribers =
+        get_confirmed_subscribers(transaction, &audience.list_ids, segment, audience.delivery)
+            .await?;
     for subscriber in subscribers {
         match subscriber {
             Ok(subscriber) => {
-                app_state
-                    .email_client
-                    .send_email(
-                        &subscriber.email,
-                        &form.title,
-                        &form.html_content,
-                        &form.text_content,
+                let subscriber_email = subscriber.email.as_ref();
+                sqlx::query!(
+                    r#"
+                    INSERT INTO issue_delivery_queue (
+                        newsletter_issue_id,
+                        subscriber_email
                     )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })
-                    .map_err(e500)?;
+                    VALUES ($1, $2)
+                    "#,
+                    newsletter_issue_id,
+                    subscriber_email,
+                )
+                .execute(&mut **transaction)
+                .await?;
             }
             Err(error) => {
                 tracing::warn!(
@@ -58,32 +463,27 @@ pub async fn publish_newsletter(
             }
         }
     }
-    messages.info("The newsletter issue has been published!");
-    Ok(Redirect::to("/admin/newsletters").into_response())
+    Ok(())
 }
 
 struct ConfirmedSubscriber {
     email: SubscriberEmail,
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+#[tracing::instrument(name = "Get confirmed subscribers", skip(transaction, segment))]
 async fn get_confirmed_subscribers(
-    pool: &SqlitePool,
+    transaction: &mut Transaction<'_, Sqlite>,
+    list_ids: &[Uuid],
+    segment: Option<&SegmentExpression>,
+    delivery: Option<DeliveryPreference>,
 ) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
+    let confirmed_subscribers = get_recipients(transaction, list_ids, segment, delivery)
+        .await?
+        .into_iter()
+        .map(|email| match SubscriberEmail::parse(email) {
+            Ok(email) => Ok(ConfirmedSubscriber { email }),
+            Err(error) => Err(anyhow::anyhow!(error)),
+        })
+        .collect();
     Ok(confirmed_subscribers)
 }
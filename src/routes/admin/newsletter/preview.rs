@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Redirect};
+use axum::{Extension, Form};
+use axum_messages::Messages;
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::drafts::{get_draft, Draft};
+use crate::issue_delivery_worker::{
+    get_subscription_on_lists, personalise_issue, PersonalisedIssue,
+};
+use crate::merge_tags::fill_merge_tags;
+use crate::routes::admin::email::get_user_email;
+use crate::segments::get_recipients;
+use crate::startup::AppState;
+use crate::utils::{e400, e500};
+
+use super::post::prepare_parts;
+use super::recipients::resolve_audience;
+
+// enough to find someone with the answers worth checking
+const MAX_SAMPLES: usize = 100;
+// samples reach the admin, not the subscriber, so their footer links must not
+// carry a signed token that acts for the subscriber
+const SAMPLE_UNSUBSCRIBE_LINK: &str = "#unsubscribe";
+const SAMPLE_PREFERENCES_LINK: &str = "#preferences";
+
+#[derive(serde::Deserialize)]
+pub struct SampleParameters {
+    // the email address of the recipient to render the draft for, the first
+    // one if left out
+    #[serde(default)]
+    subscriber: String,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_preview/index.html")]
+struct PreviewTemplate {
+    errors: Vec<String>,
+    draft_id: Uuid,
+    title: String,
+    recipients: Vec<String>,
+    subscriber: String,
+    html_content: String,
+    text_content: String,
+}
+
+/// The draft as one of its recipients would get it, merge tags and footer
+/// included.
+#[tracing::instrument(name = "Preview a draft", skip(app_state, messages, parameters))]
+pub async fn preview_draft(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Path(draft_id): Path<Uuid>,
+    Query(parameters): Query<SampleParameters>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let mut errors = messages.into_iter().map(|m| m.message).collect::<Vec<_>>();
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    let Some(draft) = get_draft(&mut connection, draft_id).await.map_err(e500)? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    // an unfinished draft previews as far as it goes
+    let (text_content, html_content) = draft.body.render().unwrap_or_else(|e| {
+        errors.push(e);
+        Default::default()
+    });
+    let sample = render_sample(
+        &app_state,
+        &draft,
+        &parameters.subscriber,
+        &text_content,
+        &html_content,
+    )
+    .await?;
+    if sample.subscriber.is_none() {
+        errors.push("Nobody would get this draft yet, so its merge tags are left empty.".into());
+    }
+    Ok(Html(
+        PreviewTemplate {
+            errors,
+            draft_id,
+            title: draft.title,
+            recipients: sample.recipients,
+            subscriber: sample.subscriber.unwrap_or_default(),
+            html_content: sample.issue.html_content,
+            text_content: sample.issue.text_content,
+        }
+        .render()
+        .unwrap(),
+    )
+    .into_response())
+}
+
+/// Send the draft, as rendered for a sample recipient, to the admin who asks
+/// and to nobody else.
+#[tracing::instrument(
+    name = "Send a test of a draft",
+    skip(app_state, messages, user_id, form),
+    fields(user_id=%user_id),
+)]
+pub async fn send_test_issue(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Extension(user_id): Extension<UserId>,
+    Path(draft_id): Path<Uuid>,
+    Form(form): Form<SampleParameters>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let preview = Redirect::to(&format!(
+        "/admin/newsletters/drafts/{}/preview?subscriber={}",
+        draft_id,
+        urlencoding::encode(&form.subscriber)
+    ))
+    .into_response();
+    let Some(email) = get_user_email(*user_id, &app_state.pool)
+        .await
+        .map_err(e500)?
+    else {
+        messages.error("Add your email address on the dashboard first, test sends go there.");
+        return Ok(preview);
+    };
+    let email = SubscriberEmail::parse(email).map_err(e500)?;
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    let Some(draft) = get_draft(&mut connection, draft_id).await.map_err(e500)? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some((text_content, html_content)) =
+        prepare_parts(&app_state, &messages, &draft.body).await?
+    else {
+        return Ok(preview);
+    };
+    let sample = render_sample(
+        &app_state,
+        &draft,
+        &form.subscriber,
+        &text_content,
+        &html_content,
+    )
+    .await?;
+    // no unsubscribe headers, the sample's footer links are inert too
+    match app_state
+        .email_client
+        .send_email(
+            &email,
+            &format!("[Test] {}", draft.title),
+            &sample.issue.html_content,
+            &sample.issue.text_content,
+        )
+        .await
+    {
+        Ok(()) => messages.info(format!(
+            "A test of the draft has been sent to {}.",
+            email.as_ref()
+        )),
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to send a test of a draft");
+            messages.error(format!("The test send failed: {}", e))
+        }
+    };
+    Ok(preview)
+}
+
+struct Sample {
+    // who the draft was rendered for, `None` if nobody would get it
+    subscriber: Option<String>,
+    // the first few recipients, to pick another sample from
+    recipients: Vec<String>,
+    issue: PersonalisedIssue,
+}
+
+async fn render_sample(
+    app_state: &AppState,
+    draft: &Draft,
+    subscriber: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<Sample, axum::response::Response> {
+    let segment = draft
+        .segment_id
+        .map(|segment_id| segment_id.to_string())
+        .unwrap_or_default();
+    let audience = resolve_audience(app_state, draft.lists.clone(), &segment).await?;
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    let mut recipients = get_recipients(
+        &mut connection,
+        &audience.list_ids,
+        audience.segment.as_ref().map(|(_, expression)| expression),
+        draft.delivery(),
+    )
+    .await
+    .map_err(e500)?;
+    recipients.sort();
+    let subscriber = if subscriber.is_empty() {
+        recipients.first().cloned()
+    } else if recipients.iter().any(|recipient| recipient == subscriber) {
+        Some(subscriber.to_owned())
+    } else {
+        return Err(e400(format!("{} would not get this draft.", subscriber)));
+    };
+    recipients.truncate(MAX_SAMPLES);
+    let Some(subscriber) = subscriber else {
+        let no_values = HashMap::new();
+        return Ok(Sample {
+            subscriber: None,
+            recipients,
+            issue: PersonalisedIssue {
+                html_content: fill_merge_tags(html_content, &no_values, str::to_owned),
+                text_content: fill_merge_tags(text_content, &no_values, str::to_owned),
+            },
+        });
+    };
+    let subscription = get_subscription_on_lists(&app_state.pool, &subscriber, &audience.list_ids)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e500("A recipient of the draft is on none of its lists"))?;
+    let issue = personalise_issue(
+        &app_state.pool,
+        &subscriber,
+        &subscription,
+        SAMPLE_UNSUBSCRIBE_LINK,
+        SAMPLE_PREFERENCES_LINK,
+        html_content,
+        text_content,
+    )
+    .await
+    .map_err(e500)?;
+    Ok(Sample {
+        subscriber: Some(subscriber),
+        recipients,
+        issue,
+    })
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Json};
+use axum_extra::extract::Query;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::domain::{DeliveryPreference, SegmentExpression};
+use crate::lists::{get_list_by_slug, DEFAULT_LIST_SLUG};
+use crate::segments::{get_recipients, get_segment};
+use crate::startup::AppState;
+use crate::utils::{e400, e500};
+
+use super::post::IssueError;
+
+/// Who an issue goes to: everyone confirmed on one of the lists, narrowed
+/// down to a segment if one was picked.
+pub(super) struct Audience {
+    pub list_ids: Vec<Uuid>,
+    pub segment: Option<(Uuid, SegmentExpression)>,
+    // only the subscribers who want issues this way, e.g. blog posts one by
+    // one are not for digest subscribers
+    pub delivery: Option<DeliveryPreference>,
+}
+
+/// `lists` holds slugs and falls back to the default list, `segment` is a
+/// segment id or empty.
+pub(super) async fn resolve_audience(
+    app_state: &AppState,
+    lists: Vec<String>,
+    segment: &str,
+) -> Result<Audience, axum::response::Response> {
+    let segment_id = if segment.is_empty() {
+        None
+    } else {
+        Some(Uuid::try_parse(segment).map_err(e400)?)
+    };
+    find_audience(&app_state.pool, lists, segment_id)
+        .await
+        .map_err(|e| match e {
+            IssueError::Invalid(_) => e400(e),
+            IssueError::UnexpectedError(_) => e500(e),
+        })
+}
+
+/// `resolve_audience` for callers without a request to answer, such as the
+/// scheduler.
+pub(super) async fn find_audience(
+    pool: &SqlitePool,
+    mut lists: Vec<String>,
+    segment_id: Option<Uuid>,
+) -> Result<Audience, IssueError> {
+    if lists.is_empty() {
+        lists.push(DEFAULT_LIST_SLUG.to_owned());
+    }
+    let mut list_ids = Vec::with_capacity(lists.len());
+    for slug in &lists {
+        let list = get_list_by_slug(pool, slug)
+            .await?
+            .ok_or_else(|| IssueError::Invalid(format!("There is no list called {}.", slug)))?;
+        list_ids.push(list.id);
+    }
+    let segment = match segment_id {
+        None => None,
+        Some(segment_id) => {
+            let expression = get_segment(pool, segment_id).await?.ok_or_else(|| {
+                IssueError::Invalid(format!("There is no segment with id {}.", segment_id))
+            })?;
+            Some((segment_id, expression))
+        }
+    };
+    Ok(Audience {
+        list_ids,
+        segment,
+        delivery: None,
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct RecipientsParameters {
+    #[serde(default)]
+    lists: Vec<String>,
+    #[serde(default)]
+    segment: String,
+}
+
+#[derive(serde::Serialize)]
+struct RecipientCount {
+    recipients: usize,
+}
+
+/// How many people an issue would reach, the publish form asks as the admin
+/// ticks lists and picks a segment.
+#[tracing::instrument(name = "Count the recipients of an issue", skip(app_state, parameters))]
+pub async fn count_recipients(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<RecipientsParameters>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let audience = resolve_audience(&app_state, parameters.lists, &parameters.segment).await?;
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    let recipients = get_recipients(
+        &mut connection,
+        &audience.list_ids,
+        audience.segment.as_ref().map(|(_, expression)| expression),
+        audience.delivery,
+    )
+    .await
+    .map_err(e500)?;
+    Ok(Json(RecipientCount {
+        recipients: recipients.len(),
+    })
+    .into_response())
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::domain::SendTime;
+use crate::drafts::{self, get_draft};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+use super::post::prepare_parts;
+use super::recipients::resolve_audience;
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleFormData {
+    // as a datetime-local input sends it
+    scheduled_for: String,
+    // an IANA time zone name
+    timezone: String,
+}
+
+/// Have the scheduler publish the draft later, or move it to another time.
+///
+/// The draft is checked now as publishing it would be, but it is published as
+/// it stands when the time comes.
+#[tracing::instrument(name = "Schedule a draft", skip(app_state, messages, form))]
+pub async fn schedule_draft(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Path(draft_id): Path<Uuid>,
+    Form(form): Form<ScheduleFormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let draft_page =
+        Redirect::to(&format!("/admin/newsletters/drafts/{}", draft_id)).into_response();
+    let mut connection = app_state.pool.acquire().await.map_err(e500)?;
+    let Some(draft) = get_draft(&mut connection, draft_id).await.map_err(e500)? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let send_time = match SendTime::parse(&form.scheduled_for, &form.timezone) {
+        Ok(send_time) => send_time,
+        Err(e) => {
+            messages.error(e);
+            return Ok(draft_page);
+        }
+    };
+    if send_time.at <= Utc::now() {
+        messages.error("Pick a time in the future.");
+        return Ok(draft_page);
+    }
+    if prepare_parts(&app_state, &messages, &draft.body)
+        .await?
+        .is_none()
+    {
+        return Ok(draft_page);
+    }
+    let segment = draft
+        .segment_id
+        .map(|segment_id| segment_id.to_string())
+        .unwrap_or_default();
+    resolve_audience(&app_state, draft.lists, &segment).await?;
+    if !drafts::schedule_draft(&app_state.pool, draft_id, &send_time)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    // the scheduler may be sleeping until a later draft is due
+    app_state.schedule_trigger.notify_one();
+    messages.info(format!(
+        "The draft goes out on {} in {}.",
+        send_time.local().format("%A %Y-%m-%d at %H:%M"),
+        send_time.timezone
+    ));
+    Ok(draft_page)
+}
+
+/// Keep the draft as a draft after all.
+#[tracing::instrument(name = "Unschedule a draft", skip(app_state, messages))]
+pub async fn unschedule_draft(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Path(draft_id): Path<Uuid>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    if drafts::unschedule_draft(&app_state.pool, draft_id)
+        .await
+        .map_err(e500)?
+    {
+        messages.info("The draft is no longer scheduled.");
+        Ok(Redirect::to(&format!("/admin/newsletters/drafts/{}", draft_id)).into_response())
+    } else {
+        // the scheduler got to it first, or there never was such a draft
+        messages.error("The draft is gone, it may have been published already.");
+        Ok(Redirect::to("/admin/newsletters/drafts").into_response())
+    }
+}
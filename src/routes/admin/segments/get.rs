@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{Html, IntoResponse};
+use axum_messages::Messages;
+use rinja_axum::Template;
+
+use crate::segments::{get_segments, SegmentRow};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(Template)]
+#[template(path = "segments/index.html")]
+struct SegmentsTemplate {
+    errors: Vec<String>,
+    segments: Vec<SegmentRow>,
+}
+
+#[tracing::instrument(name = "Segments page", skip(app_state, messages))]
+pub async fn segments_page(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let segments = get_segments(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(
+        SegmentsTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            segments,
+        }
+        .render()
+        .map_err(e500)?,
+    )
+    .into_response())
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+
+use crate::custom_fields::get_custom_fields;
+use crate::domain::SegmentExpression;
+use crate::segments::insert_segment;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    expression: String,
+}
+
+#[tracing::instrument(name = "Create a segment", skip(app_state, messages, form))]
+pub async fn create_segment(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let redirect = Redirect::to("/admin/segments").into_response();
+    let name = form.name.trim();
+    if name.is_empty() {
+        messages.error("Give the segment a name.");
+        return Ok(redirect);
+    }
+    let expression = form.expression.trim();
+    let parsed = match SegmentExpression::parse(expression) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            messages.error(e);
+            return Ok(redirect);
+        }
+    };
+    let fields = get_custom_fields(&app_state.pool).await.map_err(e500)?;
+    if let Some(key) = parsed
+        .field_keys()
+        .into_iter()
+        .find(|key| !fields.iter().any(|field| field.key == *key))
+    {
+        messages.error(format!("There is no field called {}.", key));
+        return Ok(redirect);
+    }
+    let created = insert_segment(&app_state.pool, name, expression)
+        .await
+        .map_err(e500)?;
+    if created {
+        messages.success(format!("The segment {} has been created.", name));
+    } else {
+        messages.error(format!("There already is a segment called {}.", name));
+    }
+    Ok(redirect)
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use uuid::Uuid;
+
+use crate::consent::{get_consent_events, ConsentEvent};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+use super::detail::get_subscriber;
+
+#[derive(serde::Serialize)]
+struct ConsentRecord {
+    subscriber_id: Uuid,
+    name: String,
+    email: String,
+    status: String,
+    subscribed_at: String,
+    consent_events: Vec<ConsentEvent>,
+}
+
+/// Everything we know about how a subscriber opted in, as a file to hand to
+/// an auditor.
+#[tracing::instrument(name = "Export subscriber consent", skip(app_state))]
+pub async fn export_subscriber_consent(
+    State(app_state): State<Arc<AppState>>,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let Some(subscriber) = get_subscriber(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let consent_events = get_consent_events(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"consent-{}.json\"", subscriber_id),
+        )],
+        Json(ConsentRecord {
+            subscriber_id,
+            name: subscriber.name,
+            email: subscriber.email,
+            status: subscriber.status,
+            subscribed_at: subscriber.subscribed_at,
+            consent_events,
+        }),
+    )
+        .into_response())
+}
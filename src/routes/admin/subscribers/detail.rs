@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse};
+use axum_messages::Messages;
+use chrono::{DateTime, Utc};
+use rinja_axum::Template;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::consent::{get_consent_events, ConsentEvent};
+use crate::custom_fields::{get_field_answers, FieldAnswer};
+use crate::lists::{get_list_memberships, ListMembership};
+use crate::segments::get_tags;
+use crate::startup::AppState;
+use crate::utils::e500;
+
+use super::list::SubscriberRow;
+
+pub struct TokenRow {
+    pub subscription_token: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub expired: bool,
+}
+
+pub struct DeliveryAttemptRow {
+    pub title: String,
+    pub attempted_at: String,
+    pub outcome: String,
+    pub error: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscriber/index.html")]
+struct SubscriberTemplate {
+    errors: Vec<String>,
+    subscriber: SubscriberRow,
+    custom_fields: Vec<FieldAnswer>,
+    lists: Vec<ListMembership>,
+    tags: Vec<String>,
+    tokens: Vec<TokenRow>,
+    delivery_attempts: Vec<DeliveryAttemptRow>,
+    consent_events: Vec<ConsentEvent>,
+}
+
+#[tracing::instrument(name = "Subscriber detail", skip(app_state, messages))]
+pub async fn subscriber_detail(
+    State(app_state): State<Arc<AppState>>,
+    Path(subscriber_id): Path<Uuid>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let Some(subscriber) = get_subscriber(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let custom_fields = get_field_answers(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let lists = get_list_memberships(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let tags = get_tags(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let tokens = get_tokens(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let delivery_attempts = get_delivery_attempts(&app_state.pool, &subscriber.email)
+        .await
+        .map_err(e500)?;
+    let consent_events = get_consent_events(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    Ok(Html(
+        SubscriberTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            subscriber,
+            custom_fields,
+            lists,
+            tags,
+            tokens,
+            delivery_attempts,
+            consent_events,
+        }
+        .render()
+        .map_err(e500)?,
+    )
+    .into_response())
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+pub(super) async fn get_subscriber(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRow>, anyhow::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let subscriber = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id AS "id!", uuid, name, email, status, subscribed_at
+        FROM subscriptions
+        WHERE uuid = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(subscriber)
+}
+
+#[tracing::instrument(name = "Get subscription tokens", skip(pool))]
+async fn get_tokens(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Vec<TokenRow>, anyhow::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let rows = sqlx::query!(
+        r#"
+        SELECT subscription_token, created_at, expires_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY id DESC
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let now = Utc::now();
+    rows.into_iter()
+        .map(|r| {
+            let expires_at: DateTime<Utc> = r.expires_at.parse()?;
+            Ok(TokenRow {
+                subscription_token: r.subscription_token,
+                created_at: r.created_at,
+                expires_at: r.expires_at,
+                expired: expires_at <= now,
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Get delivery attempts", skip(pool))]
+async fn get_delivery_attempts(
+    pool: &SqlitePool,
+    email: &str,
+) -> Result<Vec<DeliveryAttemptRow>, anyhow::Error> {
+    let attempts = sqlx::query_as!(
+        DeliveryAttemptRow,
+        r#"
+        SELECT
+            i.title,
+            a.attempted_at,
+            a.outcome,
+            COALESCE(a.error, '') AS "error!: String"
+        FROM issue_delivery_attempts a
+        JOIN newsletter_issues i ON i.uuid = a.newsletter_issue_id
+        WHERE a.subscriber_email = $1
+        ORDER BY a.id DESC
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(attempts)
+}
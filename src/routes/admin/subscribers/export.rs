@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use crate::startup::AppState;
+use crate::utils::{e400, e500};
+
+const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+#[derive(serde::Deserialize)]
+pub struct ExportFilters {
+    // every subscriber when left empty
+    #[serde(default)]
+    status: String,
+}
+
+/// The same `name` and `email` columns the import expects, so an export can
+/// be fed straight back in.
+#[tracing::instrument(name = "Export subscribers", skip(app_state, filters))]
+pub async fn export_subscribers(
+    State(app_state): State<Arc<AppState>>,
+    Query(filters): Query<ExportFilters>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let status = if filters.status.is_empty() {
+        None
+    } else if STATUSES.contains(&filters.status.as_str()) {
+        Some(filters.status.as_str())
+    } else {
+        return Err(e400(format!("`{}` is not a status.", filters.status)));
+    };
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT name, email, status, subscribed_at, consent_source
+        FROM subscriptions
+        WHERE $1 IS NULL OR status = $1
+        ORDER BY id
+        "#,
+        status,
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(e500)?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["name", "email", "status", "subscribed_at", "consent_source"])
+        .map_err(e500)?;
+    for subscriber in subscribers {
+        writer
+            .write_record([
+                subscriber.name.as_str(),
+                subscriber.email.as_str(),
+                subscriber.status.as_str(),
+                subscriber.subscribed_at.as_str(),
+                subscriber.consent_source.as_deref().unwrap_or_default(),
+            ])
+            .map_err(e500)?;
+    }
+    let body = writer.into_inner().map_err(e500)?;
+
+    let file_name = match status {
+        Some(status) => format!("subscribers-{}.csv", status),
+        None => "subscribers.csv".to_owned(),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,634 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::{Multipart, State};
+use axum::response::{Html, IntoResponse, Redirect};
+use axum_messages::Messages;
+use chrono::Utc;
+use csv_core::{ReadRecordResult, Reader};
+use rinja_axum::Template;
+use sqlx::{Sqlite, Transaction};
+use uuid::Uuid;
+
+use crate::consent::{record_consent_event, ClientInfo, ConsentEventType};
+use crate::domain::{DeliveryPreference, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::BatchEmail;
+use crate::erasure::is_erased;
+use crate::lists::{
+    add_to_list, get_list_by_slug, get_list_subscription_status, get_lists, ListSummary,
+    DEFAULT_LIST_SLUG,
+};
+use crate::routes::{
+    confirmation_email_bodies, error_chain_fmt, generate_subscription_token,
+    get_existing_subscriber, insert_subscriber, store_token, CONFIRMATION_EMAIL_SUBJECT,
+};
+use crate::startup::AppState;
+use crate::utils::{e400, e500};
+
+/// Uploads bigger than this are turned away before we read them.
+pub const MAX_IMPORT_SIZE: usize = 50 * 1024 * 1024;
+
+// past this the flash messages stop being readable anyway
+const MAX_REPORTED_INVALID_ROWS: usize = 50;
+
+#[derive(Template)]
+#[template(path = "subscribers_import/index.html")]
+struct ImportSubscribersTemplate {
+    errors: Vec<String>,
+    lists: Vec<ListSummary>,
+}
+
+#[tracing::instrument(name = "Import subscribers form", skip(app_state, messages))]
+pub async fn import_subscribers_form(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let lists = get_lists(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(
+        ImportSubscribersTemplate {
+            errors: messages.into_iter().map(|m| m.message).collect(),
+            lists,
+        }
+        .render()
+        .map_err(e500)?,
+    )
+    .into_response())
+}
+
+/// The status imported subscribers start with.
+enum ImportStatus {
+    /// They get a confirmation email, like anyone signing up through the form.
+    PendingConfirmation,
+    /// They already opted in somewhere else, `consent_source` says where.
+    Confirmed { consent_source: String },
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error("Failed to read the upload.")]
+    UploadError(#[from] MultipartError),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[tracing::instrument(name = "Import subscribers", skip(app_state, messages, multipart))]
+pub async fn import_subscribers(
+    State(app_state): State<Arc<AppState>>,
+    messages: Messages,
+    mut multipart: Multipart,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let mut status = String::new();
+    let mut consent_source = String::new();
+    let mut list_slug = DEFAULT_LIST_SLUG.to_owned();
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(e400)? {
+        match field.name() {
+            Some("status") => status = field.text().await.map_err(e400)?,
+            Some("list") => list_slug = field.text().await.map_err(e400)?,
+            Some("consent_source") => consent_source = field.text().await.map_err(e400)?,
+            Some("file") => upload = Some(parse_upload(field).await),
+            _ => {}
+        }
+    }
+    let redirect = Redirect::to("/admin/subscribers/import").into_response();
+
+    let status = match status.as_str() {
+        "pending_confirmation" => ImportStatus::PendingConfirmation,
+        "confirmed" => {
+            let consent_source = consent_source.trim();
+            if consent_source.is_empty() {
+                messages.error("Say where pre-confirmed subscribers gave their consent.");
+                return Ok(redirect);
+            }
+            ImportStatus::Confirmed {
+                consent_source: consent_source.to_owned(),
+            }
+        }
+        _ => return Err(e400(format!("`{}` is not an import status.", status))),
+    };
+    let list = get_list_by_slug(&app_state.pool, &list_slug)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e400(format!("There is no list called {}.", list_slug)))?;
+    let upload = match upload {
+        Some(Ok(upload)) => upload,
+        Some(Err(ImportError::InvalidFile(e))) => {
+            messages.error(e);
+            return Ok(redirect);
+        }
+        Some(Err(e)) => return Err(e400(e)),
+        None => {
+            messages.error("Choose a CSV file to import.");
+            return Ok(redirect);
+        }
+    };
+
+    let mut transaction = app_state
+        .pool
+        .begin()
+        .await
+        .context("Failed to acquire a connection from the pool")
+        .map_err(e500)?;
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut erased = 0;
+    let mut pending_confirmations = Vec::new();
+    for new_subscriber in upload.subscribers {
+        let existing_subscriber = get_existing_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .map_err(e500)?;
+        let (subscriber_id, address_confirmed) = match existing_subscriber {
+            // whatever they did with us before, e.g. unsubscribing, wins over an old export
+            Some(subscriber) if subscriber.status == "unsubscribed" => {
+                skipped += 1;
+                continue;
+            }
+            Some(subscriber) => {
+                if get_list_subscription_status(&mut transaction, list.id, subscriber.id)
+                    .await
+                    .context("Failed to look up the subscriber's status on the list.")
+                    .map_err(e500)?
+                    .is_some()
+                {
+                    skipped += 1;
+                    continue;
+                }
+                (subscriber.id, subscriber.status == "confirmed")
+            }
+            None => {
+                // they asked us to forget them, an old export must not undo that
+                if is_erased(
+                    &mut transaction,
+                    &app_state.hmac_secret,
+                    new_subscriber.email.as_ref(),
+                )
+                .await
+                .context("Failed to look for an erasure tombstone.")
+                .map_err(e500)?
+                {
+                    erased += 1;
+                    continue;
+                }
+                let subscriber_id = match &status {
+                    ImportStatus::PendingConfirmation => {
+                        insert_subscriber(&mut transaction, &new_subscriber).await
+                    }
+                    ImportStatus::Confirmed { consent_source } => {
+                        insert_confirmed_subscriber(
+                            &mut transaction,
+                            &new_subscriber,
+                            consent_source,
+                        )
+                        .await
+                    }
+                }
+                .context("Failed to insert an imported subscriber in the database.")
+                .map_err(e500)?;
+                (
+                    subscriber_id,
+                    matches!(status, ImportStatus::Confirmed { .. }),
+                )
+            }
+        };
+        let (list_status, consent_source) = match &status {
+            ImportStatus::Confirmed { consent_source } if address_confirmed => {
+                ("confirmed", Some(consent_source.as_str()))
+            }
+            // an address still waiting for its confirmation can't skip it for one list
+            ImportStatus::Confirmed { consent_source } => {
+                ("pending_confirmation", Some(consent_source.as_str()))
+            }
+            ImportStatus::PendingConfirmation => ("pending_confirmation", None),
+        };
+        add_to_list(&mut transaction, list.id, subscriber_id, list_status)
+            .await
+            .context("Failed to add an imported subscriber to the list.")
+            .map_err(e500)?;
+        if list_status == "pending_confirmation" {
+            let subscription_token = generate_subscription_token();
+            store_token(&mut transaction, subscriber_id, &subscription_token)
+                .await
+                .map_err(e500)?;
+            pending_confirmations.push((new_subscriber.email, subscription_token));
+        }
+        // the request comes from an admin, their address says nothing about the subscriber
+        record_consent_event(
+            &mut transaction,
+            subscriber_id,
+            ConsentEventType::Import,
+            &ClientInfo::default(),
+            consent_source,
+        )
+        .await
+        .context("Failed to record the import of a subscriber.")
+        .map_err(e500)?;
+        imported += 1;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers.")
+        .map_err(e500)?;
+
+    let unsent_confirmations = send_confirmation_emails(&app_state, &pending_confirmations).await;
+
+    let mut messages = messages.info(format!("Imported {} subscribers.", imported));
+    if skipped > 0 {
+        messages = messages.info(format!(
+            "Skipped {} rows whose email is already on the list.",
+            skipped
+        ));
+    }
+    if erased > 0 {
+        messages = messages.info(format!(
+            "Skipped {} rows whose owner had their data erased.",
+            erased
+        ));
+    }
+    if unsent_confirmations > 0 {
+        messages = messages.error(format!(
+            "{} confirmation emails could not be sent, those subscribers will have to sign up again.",
+            unsent_confirmations
+        ));
+    }
+    let n_invalid_rows = upload.invalid_rows.len();
+    for invalid_row in upload
+        .invalid_rows
+        .into_iter()
+        .take(MAX_REPORTED_INVALID_ROWS)
+    {
+        messages = messages.error(invalid_row);
+    }
+    if n_invalid_rows > MAX_REPORTED_INVALID_ROWS {
+        messages.error(format!(
+            "... and {} more invalid rows.",
+            n_invalid_rows - MAX_REPORTED_INVALID_ROWS
+        ));
+    }
+    Ok(redirect)
+}
+
+/// Returns how many of the emails could not be sent.
+async fn send_confirmation_emails(
+    app_state: &AppState,
+    pending_confirmations: &[(SubscriberEmail, String)],
+) -> usize {
+    let bodies = pending_confirmations
+        .iter()
+        .map(|(_, subscription_token)| {
+            confirmation_email_bodies(&app_state.base_url.0, subscription_token)
+        })
+        .collect::<Vec<_>>();
+    let emails = pending_confirmations
+        .iter()
+        .zip(&bodies)
+        .map(|((email, _), (html_body, plain_body))| BatchEmail {
+            recipient: email,
+            subject: CONFIRMATION_EMAIL_SUBJECT,
+            html_content: html_body,
+            text_content: plain_body,
+            headers: &[],
+        })
+        .collect::<Vec<_>>();
+    let outcomes = app_state.email_client.send_batch(&emails).await;
+    let mut n_failed = 0;
+    for (email, outcome) in emails.iter().zip(outcomes) {
+        if let Err(e) = outcome {
+            tracing::error!(
+                error.cause_chain = ?e,
+                subscriber_email = %email.recipient.as_ref(),
+                "Failed to send a confirmation email to an imported subscriber.",
+            );
+            n_failed += 1;
+        }
+    }
+    n_failed
+}
+
+#[tracing::instrument(
+    name = "Saving an imported subscriber in the database",
+    skip(new_subscriber, transaction)
+)]
+async fn insert_confirmed_subscriber(
+    transaction: &mut Transaction<'_, Sqlite>,
+    new_subscriber: &NewSubscriber,
+    consent_source: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let uuid = Uuid::new_v4();
+    let subscriber_id = uuid.to_string();
+    let subscribed_at = Utc::now().to_string();
+    let name = new_subscriber.name.as_ref();
+    let email = new_subscriber.email.as_ref();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (uuid, name, email, subscribed_at, status, consent_source)
+        VALUES ($1, $2, $3, $4, 'confirmed', $5)
+        "#,
+        subscriber_id,
+        name,
+        email,
+        subscribed_at,
+        consent_source,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(uuid)
+}
+
+/// The valid rows of an upload, plus what was wrong with the others.
+#[derive(Default)]
+struct ParsedUpload {
+    columns: Option<Columns>,
+    subscribers: Vec<NewSubscriber>,
+    invalid_rows: Vec<String>,
+    seen_emails: HashSet<String>,
+}
+
+struct Columns {
+    name: usize,
+    email: usize,
+}
+
+/// Parse the CSV as it comes in instead of buffering the whole upload.
+async fn parse_upload(mut field: Field<'_>) -> Result<ParsedUpload, ImportError> {
+    let mut upload = ParsedUpload::default();
+    let mut reader = CsvRecords::new();
+    let mut records = Vec::new();
+    while let Some(chunk) = field.chunk().await? {
+        reader.push(&chunk, &mut records);
+        for record in records.drain(..) {
+            upload.add(record)?;
+        }
+    }
+    reader.finish(&mut records);
+    for record in records.drain(..) {
+        upload.add(record)?;
+    }
+    if upload.columns.is_none() {
+        return Err(ImportError::InvalidFile("The file is empty.".into()));
+    }
+    Ok(upload)
+}
+
+impl ParsedUpload {
+    fn add(&mut self, record: CsvRecord) -> Result<(), ImportError> {
+        let Some(columns) = &self.columns else {
+            self.columns = Some(Columns::from_header(record)?);
+            return Ok(());
+        };
+        let fields = match record.fields {
+            // a stray blank line, e.g. after the last row
+            Ok(fields) if fields.iter().all(|field| field.trim().is_empty()) => return Ok(()),
+            Ok(fields) => fields,
+            Err(e) => {
+                self.invalid_rows
+                    .push(format!("Line {}: {}", record.line, e));
+                return Ok(());
+            }
+        };
+        let (Some(name), Some(email)) = (fields.get(columns.name), fields.get(columns.email))
+        else {
+            self.invalid_rows.push(format!(
+                "Line {}: expected at least {} columns.",
+                record.line,
+                columns.name.max(columns.email) + 1
+            ));
+            return Ok(());
+        };
+        let new_subscriber = SubscriberName::parse(name.trim().to_owned()).and_then(|name| {
+            let email = SubscriberEmail::parse(email.trim().to_owned())?;
+            // custom fields are only asked for by the signup form
+            Ok(NewSubscriber {
+                name,
+                email,
+                custom_fields: Vec::new(),
+                delivery_preference: DeliveryPreference::default(),
+            })
+        });
+        match new_subscriber {
+            Ok(new_subscriber) => {
+                if self
+                    .seen_emails
+                    .insert(new_subscriber.email.as_ref().to_lowercase())
+                {
+                    self.subscribers.push(new_subscriber);
+                } else {
+                    self.invalid_rows.push(format!(
+                        "Line {}: {} already appears earlier in the file.",
+                        record.line,
+                        new_subscriber.email.as_ref()
+                    ));
+                }
+            }
+            Err(e) => self
+                .invalid_rows
+                .push(format!("Line {}: {}", record.line, e)),
+        }
+        Ok(())
+    }
+}
+
+impl Columns {
+    fn from_header(record: CsvRecord) -> Result<Self, ImportError> {
+        let header = record.fields.map_err(|e| {
+            ImportError::InvalidFile(format!("The header on line {}: {}", record.line, e))
+        })?;
+        let position = |column: &str| {
+            header.iter().position(|field| {
+                // spreadsheet apps like to start UTF-8 files with a byte order mark
+                field
+                    .trim_start_matches('\u{feff}')
+                    .trim()
+                    .eq_ignore_ascii_case(column)
+            })
+        };
+        match (position("name"), position("email")) {
+            (Some(name), Some(email)) => Ok(Self { name, email }),
+            _ => Err(ImportError::InvalidFile(
+                "The first line must name the columns, with at least a `name` and an `email` one."
+                    .into(),
+            )),
+        }
+    }
+}
+
+struct CsvRecord {
+    /// Where the record starts in the file, counting from 1.
+    line: u64,
+    fields: Result<Vec<String>, String>,
+}
+
+/// An incremental CSV reader: feed it the upload chunk by chunk, it hands
+/// back every record completed so far.
+struct CsvRecords {
+    reader: Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+    line: u64,
+    record_line: Option<u64>,
+}
+
+impl CsvRecords {
+    fn new() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+            line: 1,
+            record_line: None,
+        }
+    }
+
+    fn push(&mut self, mut input: &[u8], records: &mut Vec<CsvRecord>) {
+        // `csv_core` takes an empty input to mean the end of the file
+        while !input.is_empty() {
+            let (_, n_read) = self.read(input, records);
+            input = &input[n_read..];
+        }
+    }
+
+    /// Flush the last record, in case the file doesn't end with a newline.
+    fn finish(&mut self, records: &mut Vec<CsvRecord>) {
+        // the buffers are grown by `read`, so just go again
+        while matches!(
+            self.read(&[], records),
+            (
+                ReadRecordResult::OutputFull | ReadRecordResult::OutputEndsFull,
+                _
+            )
+        ) {}
+    }
+
+    /// Also returns how many bytes of `input` were consumed.
+    fn read(&mut self, input: &[u8], records: &mut Vec<CsvRecord>) -> (ReadRecordResult, usize) {
+        let (result, n_read, n_written, n_ends) = self.reader.read_record(
+            input,
+            &mut self.output[self.output_len..],
+            &mut self.ends[self.ends_len..],
+        );
+        self.track_lines(&input[..n_read]);
+        self.output_len += n_written;
+        self.ends_len += n_ends;
+        match result {
+            ReadRecordResult::InputEmpty | ReadRecordResult::End => {}
+            ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+            ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+            ReadRecordResult::Record => {
+                records.push(CsvRecord {
+                    line: self.record_line.take().unwrap_or(self.line),
+                    fields: self.decode_fields(),
+                });
+                self.output_len = 0;
+                self.ends_len = 0;
+            }
+        }
+        (result, n_read)
+    }
+
+    /// `csv_core` counts lines too, but only tells us where a record ends and
+    /// skips blank lines silently, so keep track of where each one starts.
+    fn track_lines(&mut self, consumed: &[u8]) {
+        for &byte in consumed {
+            if self.record_line.is_none() && byte != b'\r' && byte != b'\n' {
+                self.record_line = Some(self.line);
+            }
+            if byte == b'\n' {
+                self.line += 1;
+            }
+        }
+    }
+
+    fn decode_fields(&self) -> Result<Vec<String>, String> {
+        let mut start = 0;
+        self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = std::str::from_utf8(&self.output[start..end])
+                    .map(str::to_owned)
+                    .map_err(|_| "the row is not valid UTF-8.".to_owned());
+                start = end;
+                field
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvRecord, CsvRecords};
+
+    fn read_all(data: &[u8], chunk_size: usize) -> Vec<CsvRecord> {
+        let mut reader = CsvRecords::new();
+        let mut records = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            reader.push(chunk, &mut records);
+        }
+        reader.finish(&mut records);
+        records
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_put_back_together() {
+        let data =
+            b"name,email\nUrsula,ursula@example.com\n\"Le Guin, Ursula\",le_guin@example.com";
+
+        for chunk_size in [1, 3, 7, 1000] {
+            let records = read_all(data, chunk_size);
+
+            assert_eq!(records.len(), 3);
+            assert_eq!(
+                records[2].fields.as_ref().unwrap(),
+                &["Le Guin, Ursula", "le_guin@example.com"]
+            );
+        }
+    }
+
+    #[test]
+    fn records_know_the_line_they_start_on() {
+        let data =
+            b"name,email\r\n\r\n\"Ursula\nLe Guin\",ursula@example.com\r\nJo,jo@example.com\r\n";
+
+        let lines = read_all(data, 2)
+            .iter()
+            .map(|record| record.line)
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines, [1, 3, 5]);
+    }
+
+    #[test]
+    fn long_fields_grow_the_buffers() {
+        let name = "a".repeat(5000);
+        let data = format!("{},{}", name, ",".repeat(40));
+
+        let records = read_all(data.as_bytes(), 64);
+
+        assert_eq!(records.len(), 1);
+        let fields = records[0].fields.as_ref().unwrap();
+        assert_eq!(fields.len(), 42);
+        assert_eq!(fields[0], name);
+    }
+
+    #[test]
+    fn invalid_utf8_is_reported_for_its_row_only() {
+        let records = read_all(
+            b"name,email\n\xff,ursula@example.com\nJo,jo@example.com\n",
+            4,
+        );
+
+        assert!(records[1].fields.is_err());
+        assert!(records[2].fields.is_ok());
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::extract::{Query, State};
+use axum::response::{Html, IntoResponse};
+use chrono::{NaiveDate, TimeDelta};
+use rinja_axum::Template;
+use sqlx::SqlitePool;
+
+use crate::startup::AppState;
+use crate::utils::{e400, e500};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberFilters {
+    // matched against both the email and the name
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    status: String,
+    // inclusive dates, formatted as `YYYY-MM-DD`
+    #[serde(default)]
+    subscribed_from: String,
+    #[serde(default)]
+    subscribed_to: String,
+    // keyset pagination: only show subscribers with a smaller `subscriptions.id`
+    after: Option<i64>,
+}
+
+pub struct SubscriberRow {
+    pub id: i64,
+    pub uuid: String,
+    pub name: String,
+    pub email: String,
+    pub status: String,
+    pub subscribed_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscribers/index.html")]
+struct SubscribersTemplate {
+    subscribers: Vec<SubscriberRow>,
+    q: String,
+    status: String,
+    subscribed_from: String,
+    subscribed_to: String,
+    is_first_page: bool,
+    next_page_url: Option<String>,
+    // the export only knows about the status filter
+    export_url: String,
+}
+
+#[tracing::instrument(name = "List subscribers", skip(app_state, filters))]
+pub async fn list_subscribers(
+    State(app_state): State<Arc<AppState>>,
+    Query(filters): Query<SubscriberFilters>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let subscribed_from = parse_date(&filters.subscribed_from).map_err(e400)?;
+    // the stored timestamps carry a time of day, so "up to the 3rd" means
+    // "before the 4th"
+    let subscribed_until = parse_date(&filters.subscribed_to)
+        .map_err(e400)?
+        .map(|date| date + TimeDelta::days(1));
+    let mut subscribers =
+        get_subscribers(&app_state.pool, &filters, subscribed_from, subscribed_until)
+            .await
+            .map_err(e500)?;
+
+    // we asked for one extra row to know whether there's a next page at all
+    let next_page_url = if subscribers.len() as i64 > PAGE_SIZE {
+        subscribers.truncate(PAGE_SIZE as usize);
+        subscribers
+            .last()
+            .map(|last| next_page_url(&filters, last.id))
+    } else {
+        None
+    };
+
+    Ok(Html(
+        SubscribersTemplate {
+            subscribers,
+            is_first_page: filters.after.is_none(),
+            next_page_url,
+            export_url: format!(
+                "/admin/subscribers/export?status={}",
+                urlencoding::encode(&filters.status)
+            ),
+            q: filters.q,
+            status: filters.status,
+            subscribed_from: filters.subscribed_from,
+            subscribed_to: filters.subscribed_to,
+        }
+        .render()
+        .map_err(e500)?,
+    )
+    .into_response())
+}
+
+fn parse_date(date: &str) -> Result<Option<NaiveDate>, anyhow::Error> {
+    if date.is_empty() {
+        return Ok(None);
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("`{}` is not a valid date.", date))?;
+    Ok(Some(date))
+}
+
+fn next_page_url(filters: &SubscriberFilters, after: i64) -> String {
+    format!(
+        "/admin/subscribers?q={}&status={}&subscribed_from={}&subscribed_to={}&after={}",
+        urlencoding::encode(&filters.q),
+        urlencoding::encode(&filters.status),
+        urlencoding::encode(&filters.subscribed_from),
+        urlencoding::encode(&filters.subscribed_to),
+        after
+    )
+}
+
+#[tracing::instrument(name = "Get a page of subscribers", skip(pool, filters))]
+async fn get_subscribers(
+    pool: &SqlitePool,
+    filters: &SubscriberFilters,
+    subscribed_from: Option<NaiveDate>,
+    subscribed_until: Option<NaiveDate>,
+) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let pattern = (!filters.q.is_empty()).then(|| {
+        let escaped = filters
+            .q
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{}%", escaped)
+    });
+    let status = (!filters.status.is_empty()).then_some(filters.status.as_str());
+    // `subscribed_at` is stored as text starting with `YYYY-MM-DD`, so plain
+    // string comparison against a date orders correctly
+    let subscribed_from = subscribed_from.map(|date| date.to_string());
+    let subscribed_until = subscribed_until.map(|date| date.to_string());
+    let limit = PAGE_SIZE + 1;
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, uuid, name, email, status, subscribed_at
+        FROM subscriptions
+        WHERE
+            ($1 IS NULL OR email LIKE $1 ESCAPE '\' OR name LIKE $1 ESCAPE '\') AND
+            ($2 IS NULL OR status = $2) AND
+            ($3 IS NULL OR subscribed_at >= $3) AND
+            ($4 IS NULL OR subscribed_at < $4) AND
+            ($5 IS NULL OR id < $5)
+        ORDER BY id DESC
+        LIMIT $6
+        "#,
+        pattern,
+        status,
+        subscribed_from,
+        subscribed_until,
+        filters.after,
+        limit,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(subscribers)
+}
@@ -0,0 +1,13 @@
+mod consent;
+mod detail;
+mod export;
+mod import;
+mod list;
+mod tags;
+
+pub use consent::export_subscriber_consent;
+pub use detail::subscriber_detail;
+pub use export::export_subscribers;
+pub use import::{import_subscribers, import_subscribers_form, MAX_IMPORT_SIZE};
+pub use list::list_subscribers;
+pub use tags::{add_subscriber_tag, remove_subscriber_tag};
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect};
+use axum::Form;
+use axum_messages::Messages;
+use uuid::Uuid;
+
+use crate::domain::SubscriberTag;
+use crate::segments::{add_tag, remove_tag};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+use super::detail::get_subscriber;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    tag: String,
+}
+
+#[tracing::instrument(name = "Add a subscriber tag", skip(app_state, messages, form))]
+pub async fn add_subscriber_tag(
+    State(app_state): State<Arc<AppState>>,
+    Path(subscriber_id): Path<Uuid>,
+    messages: Messages,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    if get_subscriber(&app_state.pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let redirect = Redirect::to(&format!("/admin/subscribers/{}", subscriber_id)).into_response();
+    let tag = match SubscriberTag::parse(form.tag.trim().to_owned()) {
+        Ok(tag) => tag,
+        Err(e) => {
+            messages.error(e);
+            return Ok(redirect);
+        }
+    };
+    add_tag(&app_state.pool, subscriber_id, &tag)
+        .await
+        .map_err(e500)?;
+    Ok(redirect)
+}
+
+#[tracing::instrument(name = "Remove a subscriber tag", skip(app_state, form))]
+pub async fn remove_subscriber_tag(
+    State(app_state): State<Arc<AppState>>,
+    Path(subscriber_id): Path<Uuid>,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    remove_tag(&app_state.pool, subscriber_id, &form.tag)
+        .await
+        .map_err(e500)?;
+    Ok(Redirect::to(&format!("/admin/subscribers/{}", subscriber_id)).into_response())
+}
@@ -3,13 +3,23 @@ mod blog;
 mod health_check;
 mod home;
 mod login;
+mod newsletter_archive;
+mod newsletter_feed;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
 pub use blog::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use newsletter_archive::*;
+pub use newsletter_feed::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
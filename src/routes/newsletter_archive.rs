@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse};
+use chrono::{DateTime, Utc};
+use rinja_axum::Template;
+
+use crate::archive::{get_archived_issue, get_archived_issues, public_copy};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+struct IssueLink {
+    slug: String,
+    title: String,
+    published_on: String,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_archive/index.html")]
+struct ArchiveTemplate {
+    issues: Vec<IssueLink>,
+}
+
+#[tracing::instrument(name = "Newsletter archive", skip(app_state))]
+pub async fn newsletter_archive(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let issues = get_archived_issues(&app_state.pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|issue| IssueLink {
+            published_on: published_on(&issue.published_at),
+            slug: issue.slug,
+            title: issue.title,
+        })
+        .collect();
+    Ok(Html(ArchiveTemplate { issues }.render().unwrap()).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_archive_issue/index.html")]
+struct ArchivedIssueTemplate {
+    title: String,
+    published_on: String,
+    html_content: String,
+}
+
+/// A published issue as anyone may read it, see `public_copy`.
+#[tracing::instrument(name = "Archived newsletter issue", skip(app_state))]
+pub async fn archived_issue(
+    State(app_state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let Some(issue) = get_archived_issue(&app_state.pool, &slug)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let manage_url = format!("{}/subscriptions/data", app_state.base_url.0);
+    let html_content = public_copy(&issue.html_content, &issue.list_names, &manage_url);
+    Ok(Html(
+        ArchivedIssueTemplate {
+            title: issue.title,
+            published_on: published_on(&issue.published_at),
+            // links in the issue open next to the archive, not inside it
+            html_content: format!("<base target=\"_blank\" />{}", html_content),
+        }
+        .render()
+        .unwrap(),
+    )
+    .into_response())
+}
+
+fn published_on(published_at: &str) -> String {
+    published_at
+        .parse::<DateTime<Utc>>()
+        .map(|published_at| published_at.format("%B %-d, %Y").to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use chrono::{DateTime, SubsecRound, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::archive::{archive_updated_at, get_feed_issues, public_copy};
+use crate::feed::{Feed, FeedEntry};
+use crate::startup::AppState;
+use crate::utils::e500;
+
+// how many of the newest issues the feeds carry
+const FEED_LENGTH: i64 = 20;
+
+#[tracing::instrument(name = "Atom feed", skip_all)]
+pub async fn atom_feed(
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<axum::response::Response, axum::response::Response> {
+    serve_feed(
+        &app_state,
+        &headers,
+        "application/atom+xml; charset=utf-8",
+        Feed::atom,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "RSS feed", skip_all)]
+pub async fn rss_feed(
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<axum::response::Response, axum::response::Response> {
+    serve_feed(
+        &app_state,
+        &headers,
+        "application/rss+xml; charset=utf-8",
+        Feed::rss,
+    )
+    .await
+}
+
+/// Render the feed, or answer 304 if the reader's copy is still current.
+async fn serve_feed(
+    app_state: &AppState,
+    headers: &HeaderMap,
+    content_type: &'static str,
+    render: fn(&Feed) -> String,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let updated = archive_updated_at(&app_state.pool)
+        .await
+        .map_err(e500)?
+        .map(|updated_at| parse_timestamp(&updated_at))
+        // HTTP dates have no fractions of a second
+        .map(|updated| updated.trunc_subsecs(0));
+    let manage_url = format!("{}/subscriptions/data", app_state.base_url.0);
+    let entries = get_feed_issues(&app_state.pool, FEED_LENGTH)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|issue| FeedEntry {
+            published: parse_timestamp(&issue.published_at),
+            html_content: public_copy(&issue.html_content, &issue.list_names, &manage_url),
+            id: issue.uuid,
+            slug: issue.slug,
+            title: issue.title,
+        })
+        .collect();
+    let body = render(&Feed {
+        base_url: app_state.base_url.0.clone(),
+        updated: updated.unwrap_or(DateTime::UNIX_EPOCH),
+        entries,
+    });
+
+    let etag = format!(
+        "\"{}\"",
+        hex::encode(&Sha256::digest(body.as_bytes())[..16])
+    );
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ETAG, HeaderValue::from_str(&etag).map_err(e500)?);
+    if let Some(updated) = updated {
+        response_headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&http_date(updated)).map_err(e500)?,
+        );
+    }
+    if is_fresh(headers, &etag, updated) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    Ok((response_headers, body).into_response())
+}
+
+/// Whether the copy the reader has, going by `If-None-Match` or else
+/// `If-Modified-Since`, is the one we would send.
+fn is_fresh(headers: &HeaderMap, etag: &str, updated: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        // the date is not looked at when there is a tag to compare
+        return if_none_match.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    }
+    let Some(updated) = updated else {
+        return false;
+    };
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+        .is_some_and(|since| updated <= since)
+}
+
+fn parse_timestamp(timestamp: &str) -> DateTime<Utc> {
+    timestamp.parse().unwrap_or(DateTime::UNIX_EPOCH)
+}
+
+fn http_date(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
@@ -1,10 +1,23 @@
+use std::sync::Arc;
+
+use axum::extract::State;
 use axum::response::{Html, IntoResponse};
 use rinja_axum::Template;
 
+use crate::custom_fields::get_custom_fields;
+use crate::domain::CustomField;
+use crate::startup::AppState;
+use crate::utils::e500;
+
 #[derive(Template)]
 #[template(path = "subscriptions/index.html")]
-struct SubscriptionsTemplate;
+struct SubscriptionsTemplate {
+    fields: Vec<CustomField>,
+}
 
-pub async fn subscribe_form() -> impl IntoResponse {
-    Html(SubscriptionsTemplate.render().unwrap())
+pub async fn subscribe_form(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let fields = get_custom_fields(&app_state.pool).await.map_err(e500)?;
+    Ok(Html(SubscriptionsTemplate { fields }.render().map_err(e500)?).into_response())
 }
@@ -1,8 +1,9 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Context;
 use axum::{extract::State, response::IntoResponse, Form};
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 use rand::{distr::Alphanumeric, rng, Rng};
 use reqwest::StatusCode;
 use serde::Deserialize;
@@ -10,15 +11,46 @@ use sqlx::{Sqlite, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
-    startup::AppState,
+    consent::{record_consent_event, ClientInfo, ConsentEventType},
+    custom_fields::{get_custom_fields, store_field_values},
+    domain::{
+        CustomField, CustomFieldValue, DeliveryPreference, NewSubscriber, SubscriberEmail,
+        SubscriberName,
+    },
+    email_client::{EmailClient, EmailError},
+    lists::{
+        add_to_list, get_list_by_slug, get_list_subscription_status, MailingList, DEFAULT_LIST_SLUG,
+    },
+    routes::unsubscribe_link,
+    startup::{AppState, HmacSecret},
 };
 
+// how long a confirmation link stays valid
+const SUBSCRIPTION_TOKEN_TTL: TimeDelta = TimeDelta::hours(48);
+
+pub const CONFIRMATION_EMAIL_SUBJECT: &str = "Willkommen!";
+
+// enough for any identifier we'd put in a form
+const MAX_SOURCE_LENGTH: usize = 100;
+
 #[derive(Deserialize)]
 pub struct FormData {
     name: String,
     email: String,
+    // which form the signup came from, e.g. `homepage`
+    #[serde(default)]
+    source: Option<String>,
+    // the slug of the list to join, the default one if left out
+    #[serde(default)]
+    list: Option<String>,
+    #[serde(default)]
+    delivery: DeliveryPreference,
+    // the answers to the custom fields, by field key
+    #[serde(flatten)]
+    custom_fields: HashMap<String, String>,
+    // what those answers are checked against, filled in before validation
+    #[serde(skip)]
+    field_definitions: Vec<CustomField>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -27,7 +59,22 @@ impl TryFrom<FormData> for NewSubscriber {
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(value.name)?;
         let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { name, email })
+        let mut custom_fields = Vec::new();
+        for field in &value.field_definitions {
+            let answer = value.custom_fields.get(&field.key).map(String::as_str);
+            if let Some(answer) = field.validate(answer)? {
+                custom_fields.push(CustomFieldValue {
+                    key: field.key.clone(),
+                    value: answer,
+                });
+            }
+        }
+        Ok(Self {
+            name,
+            email,
+            custom_fields,
+            delivery_preference: value.delivery,
+        })
     }
 }
 
@@ -63,7 +110,7 @@ impl IntoResponse for SubscribeError {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, app_state),
+    skip(form, app_state, client_info),
     fields(
         subscriber_name = %form.name,
         subscriber_email = %form.email
@@ -71,17 +118,112 @@ impl IntoResponse for SubscribeError {
 )]
 pub async fn subscribe(
     State(app_state): State<Arc<AppState>>,
-    Form(form): Form<FormData>,
+    client_info: ClientInfo,
+    Form(mut form): Form<FormData>,
 ) -> Result<impl IntoResponse, SubscribeError> {
-    let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let source = form
+        .source
+        .take()
+        .map(|source| {
+            source
+                .trim()
+                .chars()
+                .take(MAX_SOURCE_LENGTH)
+                .collect::<String>()
+        })
+        .filter(|source| !source.is_empty());
+    let list_slug = form
+        .list
+        .take()
+        .unwrap_or_else(|| DEFAULT_LIST_SLUG.to_owned());
+    form.field_definitions = get_custom_fields(&app_state.pool)
+        .await
+        .context("Failed to fetch the custom fields of the signup form.")?;
+    let new_subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
     let mut transaction = app_state
         .pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list = get_list_by_slug(&mut *transaction, &list_slug)
+        .await
+        .context("Failed to look up the list to subscribe to.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!("There is no list called {}.", list_slug))
+        })?;
+    // Every branch ends in the same response, so the form can't be used to
+    // find out whether someone is on the list. Only the inbox owner learns that.
+    let existing_subscriber = get_existing_subscriber(&mut transaction, &new_subscriber)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to look up the subscriber's current status.")?;
+    let subscriber_id = match existing_subscriber {
+        None => {
+            let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+                .await
+                .context("Failed to insert new subscriber in the database.")?;
+            // only for newcomers, anyone can type in the address of an
+            // existing subscriber and we don't want them rewriting their answers
+            store_field_values(
+                &mut transaction,
+                subscriber_id,
+                &new_subscriber.custom_fields,
+            )
+            .await
+            .context("Failed to store the custom fields of a new subscriber.")?;
+            subscriber_id
+        }
+        // they left at some point, they have to opt in again like anyone else
+        Some(subscriber) if subscriber.status == "unsubscribed" => {
+            mark_subscriber_as_pending(&mut transaction, subscriber.id)
+                .await
+                .context("Failed to put an unsubscribed subscriber back to pending.")?;
+            subscriber.id
+        }
+        // Either they lost the confirmation email or let the link expire, or
+        // they want another list on top of the ones they have. They may also
+        // be on this list already.
+        Some(subscriber) => {
+            let list_status =
+                get_list_subscription_status(&mut transaction, list.id, subscriber.id)
+                    .await
+                    .context("Failed to look up the subscriber's status on the list.")?;
+            if list_status.as_deref() == Some("confirmed") {
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to commit SQL transaction to look up a subscriber.")?;
+                send_already_subscribed_email(
+                    &app_state.email_client,
+                    new_subscriber,
+                    &app_state.base_url.0,
+                    &app_state.hmac_secret,
+                    subscriber.id,
+                    &list,
+                )
+                .await
+                .context("Failed to send an already subscribed email.")?;
+                return Ok(StatusCode::OK);
+            }
+            subscriber.id
+        }
+    };
+    add_to_list(
+        &mut transaction,
+        list.id,
+        subscriber_id,
+        "pending_confirmation",
+    )
+    .await
+    .context("Failed to add the subscriber to the list.")?;
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentEventType::Signup,
+        &client_info,
+        source.as_deref(),
+    )
+    .await
+    .context("Failed to record the signup of a subscriber.")?;
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
@@ -102,7 +244,7 @@ pub async fn subscribe(
     Ok(StatusCode::OK)
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -119,7 +261,20 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), EmailError> {
+    let (html_body, plain_body) = confirmation_email_bodies(base_url, subscription_token);
+    email_client
+        .send_email(
+            &new_subscriber.email,
+            CONFIRMATION_EMAIL_SUBJECT,
+            &html_body,
+            &plain_body,
+        )
+        .await
+}
+
+/// The html and plain text bodies of the email asking to confirm a subscription.
+pub fn confirmation_email_bodies(base_url: &str, subscription_token: &str) -> (String, String) {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
@@ -132,10 +287,35 @@ pub async fn send_confirmation_email(
         "Willkommen zu unserem newzletter!<br />Click <a href=\"{}\">here</a> to confirm your subscription.",
         confirmation_link
     );
+    (html_body, plain_body)
+}
+
+#[tracing::instrument(
+    name = "Send an already subscribed email",
+    skip(email_client, new_subscriber, base_url, hmac_secret, list)
+)]
+pub async fn send_already_subscribed_email(
+    email_client: &EmailClient,
+    new_subscriber: NewSubscriber,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+    list: &MailingList,
+) -> Result<(), EmailError> {
+    let unsubscribe_link = unsubscribe_link(base_url, hmac_secret, subscriber_id, &list.slug);
+    let plain_body = format!(
+        "Someone, hopefully you, tried to subscribe this address to {}, but you're already subscribed!\nThere's nothing you need to do. If you'd rather stop receiving it, visit {} to unsubscribe.",
+        list.name, unsubscribe_link
+    );
+    let html_body = format!(
+        "Someone, hopefully you, tried to subscribe this address to {}, but you're already subscribed!<br />There's nothing you need to do. If you'd rather stop receiving it, click <a href=\"{}\">here</a> to unsubscribe.",
+        htmlescape::encode_minimal(&list.name),
+        unsubscribe_link
+    );
     email_client
         .send_email(
             &new_subscriber.email,
-            "Willkommen!",
+            "You're already subscribed",
             &html_body,
             &plain_body,
         )
@@ -155,18 +335,67 @@ pub async fn insert_subscriber(
     let timestamptz = Utc::now().to_string();
     let name = new_subscriber.name.as_ref();
     let email = new_subscriber.email.as_ref();
+    let delivery_preference = new_subscriber.delivery_preference.as_str();
     sqlx::query!(
         r#"
-            INSERT INTO subscriptions(uuid, name, email, subscribed_at, status) VALUES($1, $2, $3, $4, 'pending_confirmation')
+            INSERT INTO subscriptions(uuid, name, email, subscribed_at, status, delivery_preference) VALUES($1, $2, $3, $4, 'pending_confirmation', $5)
             "#,
         subscriber_id,
         name,
         email,
         timestamptz,
+        delivery_preference,
     ).execute(&mut **transaction).await?;
     Ok(uuid)
 }
 
+pub struct ExistingSubscriber {
+    pub id: Uuid,
+    pub status: String,
+}
+
+#[tracing::instrument(
+    name = "Get an existing subscriber by email",
+    skip(new_subscriber, transaction)
+)]
+pub async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Sqlite>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<ExistingSubscriber>, anyhow::Error> {
+    let email = new_subscriber.email.as_ref();
+    let r = sqlx::query!(
+        r#"
+        SELECT uuid, status FROM subscriptions
+        WHERE email = $1
+        "#,
+        email,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    match r {
+        Some(r) => Ok(Some(ExistingSubscriber {
+            id: Uuid::try_parse(&r.uuid)?,
+            status: r.status,
+        })),
+        None => Ok(None),
+    }
+}
+
+#[tracing::instrument(name = "Mark subscriber as pending confirmation", skip(transaction))]
+pub async fn mark_subscriber_as_pending(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'pending_confirmation' WHERE uuid = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Store subscription token in the database",
     skip(subscription_token, transaction)
@@ -177,17 +406,32 @@ pub async fn store_token(
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     let subscriber_id = subscriber_id.to_string();
+    let now = Utc::now();
+    let created_at = now.to_string();
+    let expires_at = (now + SUBSCRIPTION_TOKEN_TTL).to_string();
+    // only the latest link should work, older ones may be sitting in a stale inbox
     sqlx::query!(
         r#"
-    INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-    VALUES ($1, $2)
+    DELETE FROM subscription_tokens WHERE subscriber_id = $1
         "#,
-        subscription_token,
         subscriber_id
     )
     .execute(&mut **transaction)
     .await
     .map_err(StoreTokenError)?;
+    sqlx::query!(
+        r#"
+    INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at, expires_at)
+    VALUES ($1, $2, $3, $4)
+        "#,
+        subscription_token,
+        subscriber_id,
+        created_at,
+        expires_at
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(StoreTokenError)?;
     Ok(())
 }
 
@@ -5,10 +5,13 @@ use axum::{
     extract::{Query, State},
     response::{Html, IntoResponse},
 };
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
-use sqlx::SqlitePool;
+use sqlx::{Sqlite, SqlitePool, Transaction};
 use uuid::Uuid;
 
+use crate::consent::{record_consent_event, ClientInfo, ConsentEventType};
+use crate::lists::confirm_list_subscriptions;
 use crate::startup::AppState;
 
 use super::error_chain_fmt;
@@ -24,6 +27,8 @@ pub enum ConfirmationError {
     UnexpectedError(#[from] anyhow::Error),
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error("The subscription token has expired.")]
+    ExpiredToken,
 }
 
 impl std::fmt::Debug for ConfirmationError {
@@ -39,6 +44,18 @@ impl IntoResponse for ConfirmationError {
                 tracing::error!(cause_chain = ?self);
                 StatusCode::UNAUTHORIZED
             }
+            // a human followed an old link, show them how to get a new one
+            Self::ExpiredToken => {
+                tracing::warn!(cause_chain = ?self);
+                let expired_page_path =
+                    PathBuf::from("frontend/dist/confirmation-expired/index.html");
+                return match fs::read_to_string(expired_page_path) {
+                    Ok(content) => (StatusCode::GONE, Html(content)).into_response(),
+                    Err(_) => {
+                        (StatusCode::GONE, "This confirmation link has expired.").into_response()
+                    }
+                };
+            }
             Self::UnexpectedError(e) => {
                 tracing::error!(cause_chain = ?e);
                 StatusCode::INTERNAL_SERVER_ERROR
@@ -50,20 +67,48 @@ impl IntoResponse for ConfirmationError {
 
 // could later take only the pool from the state, if you want to do it check the
 // axum's State docs
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, app_state))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, app_state, client_info)
+)]
 pub async fn confirm(
     State(app_state): State<Arc<AppState>>,
+    client_info: ClientInfo,
     Query(parameters): Query<Parameters>,
 ) -> Result<impl IntoResponse, ConfirmationError> {
-    let subscriber_id =
-        get_subscriber_id_from_token(&app_state.pool, &parameters.subscription_token)
-            .await
-            .context("Failed to retrieve the subscriber id associated with the provided token.")?
-            .ok_or(ConfirmationError::UnknownToken)?;
+    let token = get_subscription_token(&app_state.pool, &parameters.subscription_token)
+        .await
+        .context("Failed to retrieve the subscriber id associated with the provided token.")?
+        .ok_or(ConfirmationError::UnknownToken)?;
+    if token.expires_at <= Utc::now() {
+        return Err(ConfirmationError::ExpiredToken);
+    }
+    let subscriber_id = token.subscriber_id;
 
-    confirm_subscriber(&app_state.pool, subscriber_id)
+    let mut transaction = app_state
+        .pool
+        .begin()
+        .await
+        .context("Failed to acquire a connection from the pool")?;
+    confirm_subscriber(&mut transaction, subscriber_id)
         .await
         .context("Failed to update the subscriber status to `confirmed`.")?;
+    confirm_list_subscriptions(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to confirm the subscriber's pending lists.")?;
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentEventType::Confirmation,
+        &client_info,
+        None,
+    )
+    .await
+    .context("Failed to record the confirmation of a subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
 
     let confirm_email_page_path = PathBuf::from("frontend/dist/email-confirmed/index.html");
     match fs::read_to_string(confirm_email_page_path) {
@@ -76,28 +121,45 @@ pub async fn confirm(
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &SqlitePool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Sqlite>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
     let subscriber_id = subscriber_id.to_string();
     sqlx::query!(
         r#"UPDATE subscriptions SET status = 'confirmed' WHERE uuid = $1"#,
         subscriber_id,
     )
-    .execute(pool)
+    .execute(&mut **transaction)
     .await?;
     Ok(())
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
+pub struct SubscriptionToken {
+    pub subscriber_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get subscription token", skip(subscription_token, pool))]
+pub async fn get_subscription_token(
     pool: &SqlitePool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<SubscriptionToken>, anyhow::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"SELECT subscriber_id, expires_at FROM subscription_tokens WHERE subscription_token = $1"#,
         subscription_token,
     )
     .fetch_optional(pool)
     .await?;
-    Ok(result.map(|r| Uuid::try_parse(&r.subscriber_id).unwrap()))
+    match result {
+        Some(r) => Ok(Some(SubscriptionToken {
+            subscriber_id: Uuid::try_parse(&r.subscriber_id)?,
+            expires_at: r.expires_at.parse()?,
+        })),
+        None => Ok(None),
+    }
 }
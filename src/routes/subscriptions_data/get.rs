@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse},
+    Json,
+};
+use rinja_axum::Template;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::consent::{get_consent_events, ConsentEvent};
+use crate::custom_fields::{get_field_answers, FieldAnswer};
+use crate::lists::{get_list_memberships, ListMembership};
+use crate::segments::get_tags;
+use crate::startup::AppState;
+
+use super::{DataLinkParameters, DataRequestError, DataRequestTemplate, ManageDataTemplate};
+
+pub async fn data_request_form() -> Result<impl IntoResponse, DataRequestError> {
+    let html = DataRequestTemplate { link_sent: false }
+        .render()
+        .context("Failed to render the data request page.")?;
+    Ok(Html(html))
+}
+
+// Like unsubscribing, following the link only shows the choices, erasing is a POST.
+#[tracing::instrument(name = "Manage data form", skip(parameters, app_state))]
+pub async fn manage_data(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<DataLinkParameters>,
+) -> Result<impl IntoResponse, DataRequestError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let subscriber = get_subscriber(&app_state.pool, parameters.subscriber_id)
+        .await
+        .context("Failed to look up the subscriber.")?;
+    let html = ManageDataTemplate {
+        query: parameters.query(),
+        // the link outlives the data it points to
+        erased: subscriber.is_none(),
+    }
+    .render()
+    .context("Failed to render the manage data page.")?;
+    Ok(Html(html))
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberData {
+    subscriber: Subscriber,
+    lists: Vec<ListMembership>,
+    tags: Vec<String>,
+    custom_fields: Vec<FieldAnswer>,
+    confirmation_tokens: Vec<ConfirmationToken>,
+    consent_events: Vec<ConsentEvent>,
+    deliveries: Vec<Delivery>,
+}
+
+#[derive(serde::Serialize)]
+struct Subscriber {
+    name: String,
+    email: String,
+    status: String,
+    subscribed_at: String,
+    consent_source: Option<String>,
+    delivery_preference: String,
+    language: String,
+    paused_until: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ConfirmationToken {
+    created_at: String,
+    expires_at: String,
+}
+
+#[derive(serde::Serialize)]
+struct Delivery {
+    newsletter_issue: String,
+    attempted_at: String,
+    outcome: String,
+}
+
+/// Everything tied to the subscriber's address, as a JSON download.
+#[tracing::instrument(name = "Export subscriber data", skip(parameters, app_state))]
+pub async fn export_data(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<DataLinkParameters>,
+) -> Result<axum::response::Response, DataRequestError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let subscriber_id = parameters.subscriber_id;
+    let Some(subscriber) = get_subscriber(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let lists = get_list_memberships(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's lists.")?;
+    let tags = get_tags(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's tags.")?;
+    let custom_fields = get_field_answers(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's custom fields.")?;
+    let confirmation_tokens = get_confirmation_tokens(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's confirmation tokens.")?;
+    let consent_events = get_consent_events(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's consent events.")?;
+    let deliveries = get_deliveries(&app_state.pool, &subscriber.email)
+        .await
+        .context("Failed to fetch the subscriber's deliveries.")?;
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"newzletter-data.json\"",
+        )],
+        Json(SubscriberData {
+            subscriber,
+            lists,
+            tags,
+            custom_fields,
+            confirmation_tokens,
+            consent_events,
+            deliveries,
+        }),
+    )
+        .into_response())
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+async fn get_subscriber(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            name,
+            email,
+            status,
+            subscribed_at,
+            consent_source,
+            delivery_preference,
+            language,
+            paused_until
+        FROM subscriptions
+        WHERE uuid = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get confirmation tokens", skip(pool))]
+async fn get_confirmation_tokens(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConfirmationToken>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+        SELECT created_at, expires_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get deliveries", skip(pool, email))]
+async fn get_deliveries(pool: &SqlitePool, email: &str) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT
+            newsletter_issues.title AS newsletter_issue,
+            issue_delivery_attempts.attempted_at,
+            issue_delivery_attempts.outcome
+        FROM issue_delivery_attempts
+        JOIN newsletter_issues
+            ON newsletter_issues.uuid = issue_delivery_attempts.newsletter_issue_id
+        WHERE issue_delivery_attempts.subscriber_email = $1
+        ORDER BY issue_delivery_attempts.id
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+}
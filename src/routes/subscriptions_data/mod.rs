@@ -0,0 +1,100 @@
+mod get;
+mod post;
+
+pub use get::{data_request_form, export_data, manage_data};
+pub use post::{erase_data, request_data_link};
+
+use axum::response::IntoResponse;
+use chrono::{TimeDelta, Utc};
+use reqwest::StatusCode;
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::routes::error_chain_fmt;
+use crate::signed_link::{self, LinkPurpose};
+use crate::startup::HmacSecret;
+
+// the link hands out everything we know about someone, it shouldn't live forever
+const DATA_LINK_TTL: TimeDelta = TimeDelta::hours(24);
+
+#[derive(serde::Deserialize)]
+pub struct DataLinkParameters {
+    subscriber_id: Uuid,
+    // unix timestamp
+    expires: i64,
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "my_data/index.html")]
+struct DataRequestTemplate {
+    link_sent: bool,
+}
+
+#[derive(Template)]
+#[template(path = "manage_data/index.html")]
+struct ManageDataTemplate {
+    query: String,
+    erased: bool,
+}
+
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("The link is invalid or has expired.")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for DataRequestError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::InvalidLink(_) => {
+                tracing::warn!(cause_chain = ?self);
+                StatusCode::UNAUTHORIZED
+            }
+            Self::UnexpectedError(e) => {
+                tracing::error!(cause_chain = ?e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+        .into_response()
+    }
+}
+
+impl DataLinkParameters {
+    fn verify(&self, hmac_secret: &HmacSecret) -> Result<(), DataRequestError> {
+        signed_link::verify_expiring(
+            hmac_secret,
+            LinkPurpose::ManageData,
+            self.subscriber_id,
+            self.expires,
+            &self.token,
+        )
+        .map_err(DataRequestError::InvalidLink)
+    }
+
+    /// The same parameters, ready to put after a `?`.
+    fn query(&self) -> String {
+        format!(
+            "subscriber_id={}&expires={}&token={}",
+            self.subscriber_id, self.expires, self.token
+        )
+    }
+}
+
+fn manage_data_link(base_url: &str, hmac_secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    let expires = (Utc::now() + DATA_LINK_TTL).timestamp();
+    let token =
+        signed_link::sign_expiring(hmac_secret, LinkPurpose::ManageData, subscriber_id, expires);
+    format!(
+        "{}/subscriptions/data/manage?subscriber_id={}&expires={}&token={}",
+        base_url, subscriber_id, expires, token
+    )
+}
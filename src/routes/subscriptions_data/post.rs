@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse},
+    Form,
+};
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailError};
+use crate::erasure::erase_subscriber;
+use crate::startup::{AppState, HmacSecret};
+
+use super::{
+    manage_data_link, DataLinkParameters, DataRequestError, DataRequestTemplate, ManageDataTemplate,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Email a link to manage the data to the address, if we know it at all.
+///
+/// The page looks the same either way, it mustn't tell who is on the list.
+#[tracing::instrument(name = "Request a data link", skip(app_state, form))]
+pub async fn request_data_link(
+    State(app_state): State<Arc<AppState>>,
+    Form(form): Form<FormData>,
+) -> Result<impl IntoResponse, DataRequestError> {
+    // nobody with an invalid address can be on the list
+    if let Ok(email) = SubscriberEmail::parse(form.email) {
+        let address = email.as_ref();
+        let subscriber = sqlx::query!("SELECT uuid FROM subscriptions WHERE email = $1", address)
+            .fetch_optional(&app_state.pool)
+            .await
+            .context("Failed to look up the subscriber.")?;
+        if let Some(subscriber) = subscriber {
+            let subscriber_id =
+                Uuid::try_parse(&subscriber.uuid).context("Failed to parse the subscriber id.")?;
+            send_data_link_email(
+                &app_state.email_client,
+                &email,
+                &app_state.base_url.0,
+                &app_state.hmac_secret,
+                subscriber_id,
+            )
+            .await
+            .context("Failed to send the data link email.")?;
+        }
+    }
+    let html = DataRequestTemplate { link_sent: true }
+        .render()
+        .context("Failed to render the data request page.")?;
+    Ok(Html(html))
+}
+
+#[tracing::instrument(
+    name = "Send a data link email",
+    skip(email_client, email, base_url, hmac_secret)
+)]
+async fn send_data_link_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+) -> Result<(), EmailError> {
+    let link = manage_data_link(base_url, hmac_secret, subscriber_id);
+    let plain_body = format!(
+        "Someone, hopefully you, asked to see or erase what our newzletter stores about this address.\nVisit {} within 24 hours to download your data or have it erased. If it wasn't you, just ignore this email.",
+        link
+    );
+    let html_body = format!(
+        "Someone, hopefully you, asked to see or erase what our newzletter stores about this address.<br />Click <a href=\"{}\">here</a> within 24 hours to download your data or have it erased. If it wasn't you, just ignore this email.",
+        link
+    );
+    email_client
+        .send_email(email, "Your newzletter data", &html_body, &plain_body)
+        .await
+}
+
+#[tracing::instrument(name = "Erase subscriber data", skip(parameters, app_state))]
+pub async fn erase_data(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<DataLinkParameters>,
+) -> Result<impl IntoResponse, DataRequestError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let subscriber_id = parameters.subscriber_id.to_string();
+    let mut transaction = app_state
+        .pool
+        .begin()
+        .await
+        .context("Failed to acquire a connection from the pool")?;
+    let subscriber = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE uuid = $1",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up the subscriber.")?;
+    // a second click finds nothing left to erase, which is fine
+    if let Some(subscriber) = subscriber {
+        erase_subscriber(
+            &mut transaction,
+            &app_state.hmac_secret,
+            parameters.subscriber_id,
+            &subscriber.email,
+        )
+        .await
+        .context("Failed to erase the subscriber.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+    let html = ManageDataTemplate {
+        query: String::new(),
+        erased: true,
+    }
+    .render()
+    .context("Failed to render the manage data page.")?;
+    Ok(Html(html))
+}
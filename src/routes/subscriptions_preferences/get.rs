@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use axum_messages::Messages;
+use rinja_axum::Template;
+
+use crate::domain::{DeliveryPreference, Language};
+use crate::lists::{get_list_memberships, get_lists};
+use crate::preferences::get_preferences;
+use crate::signed_link::{self, LinkPurpose};
+use crate::startup::AppState;
+
+use super::{Choice, PreferencesError, PreferencesParameters, PreferencesTemplate};
+
+#[tracing::instrument(name = "Preferences form", skip(parameters, app_state, messages))]
+pub async fn preferences_form(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<PreferencesParameters>,
+    messages: Messages,
+) -> Result<axum::response::Response, PreferencesError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let subscriber_id = parameters.subscriber_id;
+    let Some(preferences) = get_preferences(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber's preferences.")?
+    else {
+        // the link outlives the data it points to
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let memberships = get_list_memberships(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's lists.")?;
+    let lists = get_lists(&app_state.pool)
+        .await
+        .context("Failed to fetch the lists.")?
+        .into_iter()
+        .map(|list| Choice {
+            selected: memberships
+                .iter()
+                .any(|m| m.list_slug == list.slug && m.status == "confirmed"),
+            value: list.slug,
+            label: list.name,
+        })
+        .collect();
+    let deliveries = [
+        // as the signup form words them
+        (
+            DeliveryPreference::Instant,
+            "Every blog post as it comes out",
+        ),
+        (
+            DeliveryPreference::WeeklyDigest,
+            "One email a week with the new posts",
+        ),
+    ]
+    .into_iter()
+    .map(|(delivery, label)| Choice {
+        value: delivery.as_str().to_owned(),
+        label: label.to_owned(),
+        selected: delivery == preferences.delivery,
+    })
+    .collect();
+    let languages = Language::ALL
+        .into_iter()
+        .map(|language| Choice {
+            value: language.as_str().to_owned(),
+            label: language.name().to_owned(),
+            selected: language == preferences.language,
+        })
+        .collect();
+    // off every list at once, as the links in issues sent before there were
+    // several lists do
+    let unsubscribe_token = signed_link::sign(
+        &app_state.hmac_secret,
+        LinkPurpose::Unsubscribe,
+        subscriber_id,
+    );
+    let html = PreferencesTemplate {
+        query: parameters.query(),
+        errors: messages.into_iter().map(|m| m.message).collect(),
+        subscribed: preferences.status == "confirmed",
+        name: preferences.name,
+        lists,
+        deliveries,
+        languages,
+        paused_until: preferences
+            .paused_until
+            .map(|paused_until| paused_until.format("%B %-d, %Y").to_string()),
+        unsubscribe_url: format!(
+            "/subscriptions/unsubscribe?subscriber_id={}&token={}",
+            subscriber_id, unsubscribe_token
+        ),
+    }
+    .render()
+    .context("Failed to render the preferences page.")?;
+    Ok(Html(html).into_response())
+}
@@ -0,0 +1,108 @@
+mod get;
+mod post;
+
+pub use get::preferences_form;
+pub use post::{pause_delivery, update_preferences};
+
+use axum::response::IntoResponse;
+use reqwest::StatusCode;
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::routes::error_chain_fmt;
+use crate::signed_link::{self, LinkPurpose};
+use crate::startup::HmacSecret;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+/// One of the options of a checkbox group or a select.
+struct Choice {
+    value: String,
+    label: String,
+    selected: bool,
+}
+
+#[derive(Template)]
+#[template(path = "preferences/index.html")]
+struct PreferencesTemplate {
+    query: String,
+    errors: Vec<String>,
+    // only confirmed subscribers get the form, the rest are pointed at signup
+    subscribed: bool,
+    name: String,
+    lists: Vec<Choice>,
+    deliveries: Vec<Choice>,
+    languages: Vec<Choice>,
+    // e.g. `July 4, 2025`, `None` unless delivery is paused
+    paused_until: Option<String>,
+    unsubscribe_url: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("The preferences link is invalid.")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PreferencesError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::InvalidToken(_) => {
+                tracing::warn!(cause_chain = ?self);
+                StatusCode::UNAUTHORIZED
+            }
+            Self::UnexpectedError(e) => {
+                tracing::error!(cause_chain = ?e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+        .into_response()
+    }
+}
+
+impl PreferencesParameters {
+    fn verify(&self, hmac_secret: &HmacSecret) -> Result<(), PreferencesError> {
+        signed_link::verify(
+            hmac_secret,
+            LinkPurpose::Preferences,
+            self.subscriber_id,
+            &self.token,
+        )
+        .map_err(PreferencesError::InvalidToken)
+    }
+
+    /// The same parameters, ready to put after a `?`.
+    fn query(&self) -> String {
+        format!("subscriber_id={}&token={}", self.subscriber_id, self.token)
+    }
+
+    /// The page the forms go back to once they are handled.
+    fn page(&self) -> String {
+        format!("/subscriptions/preferences?{}", self.query())
+    }
+}
+
+/// The per-recipient link to the preferences page, it goes at the bottom of
+/// every newsletter issue next to the unsubscribe link.
+///
+/// Like the unsubscribe link it doesn't expire, an old issue still leads to
+/// the page.
+pub fn preferences_link(base_url: &str, hmac_secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    let token = signed_link::sign(hmac_secret, LinkPurpose::Preferences, subscriber_id);
+    format!(
+        "{}/subscriptions/preferences?subscriber_id={}&token={}",
+        base_url, subscriber_id, token
+    )
+}
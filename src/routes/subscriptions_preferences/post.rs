@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+};
+// the plain one can't take a field more than once, as checkboxes send it
+use axum_extra::extract::Form;
+use axum_messages::Messages;
+use chrono::{TimeDelta, Utc};
+
+use crate::consent::{record_consent_event, ClientInfo, ConsentEventType};
+use crate::domain::{DeliveryPreference, Language, SubscriberName};
+use crate::lists::{add_to_list, get_list_by_slug, get_list_memberships, get_lists, leave_lists};
+use crate::preferences::{get_preferences, save_preferences, set_paused_until};
+use crate::startup::AppState;
+
+use super::{PreferencesError, PreferencesParameters};
+
+// a longer break is better taken by unsubscribing
+const MAX_PAUSE_WEEKS: u32 = 12;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    // slugs of the lists to be on
+    #[serde(default)]
+    lists: Vec<String>,
+    #[serde(default)]
+    delivery: DeliveryPreference,
+    #[serde(default)]
+    language: Language,
+}
+
+#[tracing::instrument(
+    name = "Update the preferences of a subscriber",
+    skip(parameters, app_state, client_info, messages, form)
+)]
+pub async fn update_preferences(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<PreferencesParameters>,
+    client_info: ClientInfo,
+    messages: Messages,
+    Form(form): Form<FormData>,
+) -> Result<axum::response::Response, PreferencesError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let subscriber_id = parameters.subscriber_id;
+    let Some(preferences) = get_preferences(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber's preferences.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if preferences.status != "confirmed" {
+        // the page asks them to sign up again instead
+        return Ok(Redirect::to(&parameters.page()).into_response());
+    }
+    let name = match SubscriberName::parse(form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            messages.error(e);
+            return Ok(Redirect::to(&parameters.page()).into_response());
+        }
+    };
+    let lists = get_lists(&app_state.pool)
+        .await
+        .context("Failed to fetch the lists.")?;
+    if !lists.iter().any(|list| form.lists.contains(&list.slug)) {
+        messages.error("Pick at least one list, or unsubscribe from all of them.");
+        return Ok(Redirect::to(&parameters.page()).into_response());
+    }
+    let memberships = get_list_memberships(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber's lists.")?;
+
+    let mut transaction = app_state
+        .pool
+        .begin()
+        .await
+        .context("Failed to acquire a connection from the pool")?;
+    save_preferences(
+        &mut transaction,
+        subscriber_id,
+        &name,
+        form.delivery,
+        form.language,
+    )
+    .await
+    .context("Failed to save the subscriber's preferences.")?;
+    for list in &lists {
+        let wanted = form.lists.contains(&list.slug);
+        let on_list = memberships
+            .iter()
+            .any(|m| m.list_slug == list.slug && m.status == "confirmed");
+        if wanted == on_list {
+            continue;
+        }
+        let list_id = get_list_by_slug(&mut *transaction, &list.slug)
+            .await
+            .context("Failed to look up a list.")?
+            .ok_or_else(|| anyhow::anyhow!("The list {} no longer exists.", list.slug))?
+            .id;
+        if wanted {
+            // the link came to their inbox, the address needs no confirming
+            add_to_list(&mut transaction, list_id, subscriber_id, "confirmed")
+                .await
+                .context("Failed to add the subscriber to a list.")?;
+            record_consent_event(
+                &mut transaction,
+                subscriber_id,
+                ConsentEventType::Preferences,
+                &client_info,
+                None,
+            )
+            .await
+            .context("Failed to record that the subscriber joined a list.")?;
+        } else {
+            leave_lists(&mut transaction, subscriber_id, Some(list_id))
+                .await
+                .context("Failed to take the subscriber off a list.")?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save the subscriber's preferences.")?;
+    messages.info("Your preferences have been saved.");
+    Ok(Redirect::to(&parameters.page()).into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct PauseFormData {
+    // 0 to resume right away
+    weeks: u32,
+}
+
+#[tracing::instrument(
+    name = "Pause delivery to a subscriber",
+    skip(parameters, app_state, messages, form)
+)]
+pub async fn pause_delivery(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<PreferencesParameters>,
+    messages: Messages,
+    Form(form): Form<PauseFormData>,
+) -> Result<axum::response::Response, PreferencesError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let subscriber_id = parameters.subscriber_id;
+    let Some(preferences) = get_preferences(&app_state.pool, subscriber_id)
+        .await
+        .context("Failed to look up the subscriber's preferences.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if preferences.status != "confirmed" {
+        return Ok(Redirect::to(&parameters.page()).into_response());
+    }
+    if form.weeks > MAX_PAUSE_WEEKS {
+        messages.error(format!(
+            "Delivery can be paused for up to {} weeks.",
+            MAX_PAUSE_WEEKS
+        ));
+        return Ok(Redirect::to(&parameters.page()).into_response());
+    }
+    let until = (form.weeks > 0).then(|| Utc::now() + TimeDelta::weeks(form.weeks.into()));
+    set_paused_until(&app_state.pool, subscriber_id, until)
+        .await
+        .context("Failed to pause delivery to the subscriber.")?;
+    messages.info(match until {
+        Some(until) => format!(
+            "You won't get any emails until {}.",
+            until.format("%B %-d, %Y")
+        ),
+        None => "You will get emails again.".to_owned(),
+    });
+    Ok(Redirect::to(&parameters.page()).into_response())
+}
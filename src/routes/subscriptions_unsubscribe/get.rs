@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse},
+};
+use rinja_axum::Template;
+
+use crate::lists::get_list_by_slug;
+use crate::startup::AppState;
+
+use super::{UnsubscribeError, UnsubscribeParameters, UnsubscribeTemplate};
+
+// Link checkers and mail scanners open every link they see, so following the
+// link only asks for confirmation. The actual unsubscribe is a POST.
+#[tracing::instrument(name = "Unsubscribe form", skip(parameters, app_state))]
+pub async fn unsubscribe_form(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<impl IntoResponse, UnsubscribeError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let list = match &parameters.list {
+        Some(slug) => get_list_by_slug(&app_state.pool, slug)
+            .await
+            .context("Failed to look up the list to unsubscribe from.")?,
+        None => None,
+    };
+    let html = UnsubscribeTemplate {
+        action: parameters.action(),
+        unsubscribed: false,
+        list_name: list.map(|list| list.name),
+    }
+    .render()
+    .context("Failed to render the unsubscribe page.")?;
+    Ok(Html(html))
+}
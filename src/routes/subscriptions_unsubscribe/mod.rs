@@ -0,0 +1,117 @@
+mod get;
+mod post;
+
+pub use get::unsubscribe_form;
+pub use post::unsubscribe;
+
+use axum::response::IntoResponse;
+use reqwest::StatusCode;
+use rinja_axum::Template;
+use uuid::Uuid;
+
+use crate::routes::error_chain_fmt;
+use crate::signed_link::{self, LinkPurpose};
+use crate::startup::HmacSecret;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    // links sent before there were several lists leave it out, those
+    // unsubscribe from every list
+    #[serde(default)]
+    list: Option<String>,
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe/index.html")]
+struct UnsubscribeTemplate {
+    action: String,
+    unsubscribed: bool,
+    list_name: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for UnsubscribeError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::InvalidToken(_) => {
+                tracing::warn!(cause_chain = ?self);
+                StatusCode::UNAUTHORIZED
+            }
+            Self::UnexpectedError(e) => {
+                tracing::error!(cause_chain = ?e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+        .into_response()
+    }
+}
+
+impl UnsubscribeParameters {
+    fn verify(&self, hmac_secret: &HmacSecret) -> Result<(), UnsubscribeError> {
+        match &self.list {
+            Some(list) => signed_link::verify_scoped(
+                hmac_secret,
+                LinkPurpose::Unsubscribe,
+                self.subscriber_id,
+                list,
+                &self.token,
+            ),
+            None => signed_link::verify(
+                hmac_secret,
+                LinkPurpose::Unsubscribe,
+                self.subscriber_id,
+                &self.token,
+            ),
+        }
+        .map_err(UnsubscribeError::InvalidToken)
+    }
+
+    /// Where the confirmation form posts to, the same link the subscriber followed.
+    fn action(&self) -> String {
+        match &self.list {
+            Some(list) => format!(
+                "/subscriptions/unsubscribe?subscriber_id={}&list={}&token={}",
+                self.subscriber_id, list, self.token
+            ),
+            None => format!(
+                "/subscriptions/unsubscribe?subscriber_id={}&token={}",
+                self.subscriber_id, self.token
+            ),
+        }
+    }
+}
+
+/// The per-recipient link that takes them off `list_slug`, it goes at the
+/// bottom of every newsletter issue sent through that list.
+pub fn unsubscribe_link(
+    base_url: &str,
+    hmac_secret: &HmacSecret,
+    subscriber_id: Uuid,
+    list_slug: &str,
+) -> String {
+    let token = signed_link::sign_scoped(
+        hmac_secret,
+        LinkPurpose::Unsubscribe,
+        subscriber_id,
+        list_slug,
+    );
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&list={}&token={}",
+        base_url, subscriber_id, list_slug, token
+    )
+}
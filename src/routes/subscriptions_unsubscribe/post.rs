@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse},
+};
+use rinja_axum::Template;
+
+use crate::lists::{get_list_by_slug, leave_lists};
+use crate::startup::AppState;
+
+use super::{UnsubscribeError, UnsubscribeParameters, UnsubscribeTemplate};
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, app_state))]
+pub async fn unsubscribe(
+    State(app_state): State<Arc<AppState>>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<impl IntoResponse, UnsubscribeError> {
+    parameters.verify(&app_state.hmac_secret)?;
+    let mut transaction = app_state
+        .pool
+        .begin()
+        .await
+        .context("Failed to acquire a connection from the pool")?;
+    let list = match &parameters.list {
+        Some(slug) => Some(
+            get_list_by_slug(&mut *transaction, slug)
+                .await
+                .context("Failed to look up the list to unsubscribe from.")?
+                .ok_or_else(|| anyhow::anyhow!("The list {} no longer exists.", slug))?,
+        ),
+        None => None,
+    };
+    leave_lists(
+        &mut transaction,
+        parameters.subscriber_id,
+        list.as_ref().map(|list| list.id),
+    )
+    .await
+    .context("Failed to update the subscriber status to `unsubscribed`.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    let html = UnsubscribeTemplate {
+        action: String::new(),
+        unsubscribed: true,
+        list_name: list.map(|list| list.name),
+    }
+    .render()
+    .context("Failed to render the unsubscribe page.")?;
+    Ok(Html(html))
+}
@@ -0,0 +1,127 @@
+//! Publishes scheduled drafts once their time has come.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{TimeDelta, Utc};
+use sqlx::SqlitePool;
+use tokio::sync::Notify;
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::{
+    drafts::{
+        claim_due_draft, get_due_drafts, get_schedule_failures, postpone_failed_draft,
+        record_schedule_error, time_until_next_draft,
+    },
+    routes::publish_scheduled_draft,
+};
+
+// the longest the scheduler sleeps without having another look
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+// a draft that failed to publish is tried again this much later, doubling
+// every time, until it is left for the admin to look at
+const FIRST_RETRY_DELAY: TimeDelta = TimeDelta::minutes(1);
+const MAX_PUBLISH_ATTEMPTS: u32 = 5;
+
+/// Publish scheduled drafts as they fall due, forever.
+///
+/// The schedule lives in the database, so a restarted process, or a machine
+/// Fly stopped and started again, carries on where the last one left off:
+/// drafts that fell due while nothing was running go out right away.
+/// `schedule_draft` pokes `schedule_trigger` so that a new, earlier time is
+/// not slept through, and we poke `delivery_trigger` once issues are queued.
+pub async fn run_scheduler_until_stopped(
+    pool: SqlitePool,
+    delivery_trigger: Arc<Notify>,
+    schedule_trigger: Arc<Notify>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match publish_due_drafts(&pool).await {
+            Ok(n_published) => {
+                if n_published > 0 {
+                    delivery_trigger.notify_one();
+                }
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        }
+        // the clock tokio sleeps on stands still while the machine is
+        // suspended, waking up every so often catches up with the wall clock
+        let wait = time_until_next_draft(&pool)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(IDLE_POLL_INTERVAL)
+            .min(IDLE_POLL_INTERVAL);
+        let _ = tokio::time::timeout(wait, schedule_trigger.notified()).await;
+    }
+}
+
+/// Publish every draft that is due, returns how many went out.
+///
+/// A draft that fails does not hold back the ones after it, it is put off
+/// for a while instead.
+#[tracing::instrument(skip_all, fields(n_published = tracing::field::Empty), err)]
+pub async fn publish_due_drafts(pool: &SqlitePool) -> Result<usize, anyhow::Error> {
+    let mut n_published = 0;
+    let mut n_still_due = 0;
+    for draft_id in get_due_drafts(pool, Utc::now()).await? {
+        match publish_scheduled_draft(pool, draft_id).await {
+            Ok(true) => n_published += 1,
+            Ok(false) => {}
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    %draft_id,
+                    "Failed to publish a scheduled draft",
+                );
+                if let Err(e) = put_off_failed_draft(pool, draft_id, &e).await {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        %draft_id,
+                        "Failed to put off a scheduled draft",
+                    );
+                    n_still_due += 1;
+                }
+            }
+        }
+    }
+    Span::current().record("n_published", n_published);
+    // those are still due, have the scheduler back off before another look
+    if n_still_due > 0 {
+        anyhow::bail!("Failed to put off {} scheduled drafts", n_still_due);
+    }
+    Ok(n_published)
+}
+
+/// Try a draft that failed to publish again later, or take it off the
+/// schedule with a note for the admin once it has failed too often.
+#[tracing::instrument(skip(pool, error))]
+async fn put_off_failed_draft(
+    pool: &SqlitePool,
+    draft_id: Uuid,
+    error: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let now = Utc::now();
+    let n_failures = get_schedule_failures(&mut transaction, draft_id).await?;
+    if n_failures + 1 >= MAX_PUBLISH_ATTEMPTS {
+        tracing::error!("Giving up on a scheduled draft");
+        claim_due_draft(&mut transaction, draft_id, now).await?;
+        let note = format!(
+            "Publishing it failed {} times, the last time with: {}",
+            MAX_PUBLISH_ATTEMPTS, error
+        );
+        record_schedule_error(&mut transaction, draft_id, &note).await?;
+    } else {
+        let retry_at = now + FIRST_RETRY_DELAY * 2i32.pow(n_failures);
+        postpone_failed_draft(&mut transaction, draft_id, retry_at).await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
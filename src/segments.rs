@@ -0,0 +1,218 @@
+//! Tags on subscribers, and the saved segments that pick subscribers by them.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{NaiveDate, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+use uuid::Uuid;
+
+use crate::domain::{DeliveryPreference, SegmentExpression, SegmentSubject, SubscriberTag};
+
+pub struct SegmentRow {
+    pub uuid: String,
+    pub name: String,
+    pub expression: String,
+}
+
+#[tracing::instrument(name = "Get all segments", skip(pool))]
+pub async fn get_segments(pool: &SqlitePool) -> Result<Vec<SegmentRow>, sqlx::Error> {
+    sqlx::query_as!(
+        SegmentRow,
+        r#"SELECT uuid, name, expression FROM segments ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get a segment", skip(pool))]
+pub async fn get_segment(
+    pool: &SqlitePool,
+    segment_id: Uuid,
+) -> Result<Option<SegmentExpression>, anyhow::Error> {
+    let segment_id = segment_id.to_string();
+    let r = sqlx::query!(
+        r#"SELECT expression FROM segments WHERE uuid = $1"#,
+        segment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    match r {
+        // it was checked on the way in, failing now means the grammar changed
+        Some(r) => Ok(Some(
+            SegmentExpression::parse(&r.expression).map_err(anyhow::Error::msg)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Returns `false` if another segment already goes by `name`.
+#[tracing::instrument(name = "Create a segment", skip(pool))]
+pub async fn insert_segment(
+    pool: &SqlitePool,
+    name: &str,
+    expression: &str,
+) -> Result<bool, sqlx::Error> {
+    let uuid = Uuid::new_v4().to_string();
+    let created_at = Utc::now().to_string();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO segments (uuid, name, expression, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        uuid,
+        name,
+        expression,
+        created_at,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows > 0)
+}
+
+#[tracing::instrument(name = "Get the tags of a subscriber", skip(pool))]
+pub async fn get_tags(pool: &SqlitePool, subscriber_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let tags = sqlx::query!(
+        r#"SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| r.tag)
+    .collect();
+    Ok(tags)
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(pool))]
+pub async fn add_tag(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+    tag: &SubscriberTag,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    let tag = tag.as_ref();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        VALUES ($1, $2)
+        ON CONFLICT (subscriber_id, tag) DO NOTHING
+        "#,
+        subscriber_id,
+        tag,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Untag a subscriber", skip(pool))]
+pub async fn remove_tag(
+    pool: &SqlitePool,
+    subscriber_id: Uuid,
+    tag: &str,
+) -> Result<(), sqlx::Error> {
+    let subscriber_id = subscriber_id.to_string();
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2"#,
+        subscriber_id,
+        tag,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The addresses confirmed on at least one of `list_ids`, once each, narrowed
+/// down to `segment` if there is one, and to those who want issues the
+/// `delivery` way if given. Subscribers who paused delivery are left out.
+///
+/// Segments are evaluated here rather than in SQL, they are too free-form for
+/// a checked query and the list is read in full either way.
+#[tracing::instrument(name = "Get the recipients of an issue", skip(connection, segment))]
+pub async fn get_recipients(
+    connection: &mut SqliteConnection,
+    list_ids: &[Uuid],
+    segment: Option<&SegmentExpression>,
+    delivery: Option<DeliveryPreference>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let list_ids = serde_json::to_string(list_ids)?;
+    let delivery = delivery.map(|delivery| delivery.as_str());
+    let now = Utc::now().to_string();
+    let candidates = sqlx::query!(
+        r#"
+        SELECT DISTINCT
+            s.uuid,
+            s.email,
+            s.status,
+            s.subscribed_at,
+            (
+                SELECT GROUP_CONCAT(t.tag, ' ')
+                FROM subscriber_tags t
+                WHERE t.subscriber_id = s.uuid
+            ) AS "tags?: String"
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.uuid
+        WHERE ls.list_id IN (SELECT value FROM json_each($1))
+            AND s.status = 'confirmed'
+            AND ls.status = 'confirmed'
+            AND ($2 IS NULL OR s.delivery_preference = $2)
+            AND (s.paused_until IS NULL OR s.paused_until <= $3)
+        "#,
+        list_ids,
+        delivery,
+        now,
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    let Some(segment) = segment else {
+        return Ok(candidates.into_iter().map(|r| r.email).collect());
+    };
+    let mut answers: HashMap<String, HashMap<String, String>> = HashMap::new();
+    if !segment.field_keys().is_empty() {
+        let rows = sqlx::query!(
+            r#"
+            SELECT v.subscriber_id, f.key, v.value
+            FROM subscriber_field_values v
+            JOIN custom_fields f ON f.uuid = v.field_id
+            "#
+        )
+        .fetch_all(&mut *connection)
+        .await?;
+        for r in rows {
+            answers
+                .entry(r.subscriber_id)
+                .or_default()
+                .insert(r.key, r.value);
+        }
+    }
+    let no_answers = HashMap::new();
+    let mut recipients = Vec::new();
+    for candidate in candidates {
+        // tags can't contain spaces, so they survive the round trip
+        let tags = candidate
+            .tags
+            .as_deref()
+            .unwrap_or_default()
+            .split(' ')
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+        let subscribed_on = NaiveDate::parse_from_str(
+            candidate.subscribed_at.get(..10).unwrap_or_default(),
+            "%Y-%m-%d",
+        )?;
+        let subject = SegmentSubject {
+            status: &candidate.status,
+            subscribed_on,
+            tags: &tags,
+            fields: answers.get(&candidate.uuid).unwrap_or(&no_answers),
+        };
+        if segment.matches(&subject) {
+            recipients.push(candidate.email);
+        }
+    }
+    Ok(recipients)
+}
@@ -1,9 +1,14 @@
 use crate::routes::subscribe_form;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
-    extract::{FromRef, Request},
-    middleware,
+    extract::{
+        connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo, DefaultBodyLimit, FromRef,
+        Request,
+    },
+    middleware::{self, AddExtension},
     response::Response,
     routing::{get, post},
     serve::Serve,
@@ -14,6 +19,8 @@ use secrecy::{ExposeSecret, SecretString};
 use sqlx::SqlitePool;
 use time::Duration;
 use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use tower::ServiceBuilder;
 use tower_http::{services::ServeDir, trace::TraceLayer};
 use tower_sessions::{Expiry, SessionManagerLayer};
@@ -23,14 +30,27 @@ use tower_sessions_redis_store::{
 };
 
 use crate::routes::{
-    admin_dashboard, blog_index, blog_post, change_password, change_password_form, confirm,
-    health_check, home, log_out, login, login_form, publish_newsletter, publish_newsletter_form,
-    subscribe,
+    add_subscriber_tag, admin_dashboard, archived_issue, atom_feed, blog_index, blog_post,
+    change_email, change_password, change_password_form, confirm, count_recipients,
+    create_custom_field, create_draft, create_list, create_segment, custom_fields_page,
+    data_request_form, draft_form, drafts_page, erase_data, export_data, export_subscriber_consent,
+    export_subscribers, health_check, home, import_subscribers, import_subscribers_form,
+    issues_page, list_subscribers, lists_page, log_out, login, login_form, manage_data,
+    newsletter_archive, pause_delivery, preferences_form, preview_draft, publish_draft,
+    publish_newsletter, publish_newsletter_form, remove_subscriber_tag, request_data_link,
+    requeue_delivery, rss_feed, schedule_draft, segments_page, send_test_issue, set_issue_archived,
+    subscribe, subscriber_detail, sync_blog_posts, unschedule_draft, unsubscribe, unsubscribe_form,
+    update_draft, update_preferences, MAX_IMPORT_SIZE,
 };
 use crate::{
     authentication::reject_anonymous_users,
-    configuration::{configure_database, Settings},
+    blog_posts::announce_new_posts,
+    configuration::{configure_database, BlogSettings, Settings},
+    consent::ClientIpSource,
+    digest::{run_digest_until_stopped, take_digest_baseline},
     email_client::EmailClient,
+    issue_delivery_worker::run_worker_until_stopped,
+    scheduler::run_scheduler_until_stopped,
 };
 use tracing::{info, info_span, Span};
 use uuid::Uuid;
@@ -39,26 +59,47 @@ pub struct AppState {
     pub pool: SqlitePool,
     pub email_client: EmailClient,
     pub base_url: ApplicationBaseUrl,
-    _hmac_secret: HmacSecret,
+    pub delivery_trigger: Arc<Notify>,
+    pub schedule_trigger: Arc<Notify>,
+    pub hmac_secret: HmacSecret,
+    pub blog: BlogSettings,
+    pub client_ip_source: ClientIpSource,
 }
 
 // substate
 impl FromRef<Arc<AppState>> for HmacSecret {
     fn from_ref(input: &Arc<AppState>) -> Self {
-        input._hmac_secret.clone()
+        input.hmac_secret.clone()
+    }
+}
+
+impl FromRef<Arc<AppState>> for ClientIpSource {
+    fn from_ref(input: &Arc<AppState>) -> Self {
+        input.client_ip_source
     }
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+type Server = Serve<
+    TcpListener,
+    IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+    AddExtension<Router, ConnectInfo<SocketAddr>>,
+>;
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     pool: SqlitePool,
     email_client: EmailClient,
     base_url: String,
-    _hmac_secret: SecretString,
+    hmac_secret: SecretString,
     redis_uri: SecretString,
-) -> anyhow::Result<Serve<TcpListener, Router, Router>> {
+    delivery_trigger: Arc<Notify>,
+    schedule_trigger: Arc<Notify>,
+    blog: BlogSettings,
+    client_ip_source: ClientIpSource,
+) -> anyhow::Result<Server> {
     // redis sessions
     let redis_url = redis_uri.expose_secret();
     let redis_config = Config::from_url(redis_url)
@@ -78,10 +119,62 @@ pub async fn run(
         .route("/dashboard", get(admin_dashboard))
         .route("/password", get(change_password_form).post(change_password))
         .route("/logout", post(log_out))
+        .route("/email", post(change_email))
+        .route("/deliveries/requeue", post(requeue_delivery))
+        .route("/lists", get(lists_page).post(create_list))
+        .route("/segments", get(segments_page).post(create_segment))
+        .route("/fields", get(custom_fields_page).post(create_custom_field))
+        .route("/subscribers", get(list_subscribers))
+        .route(
+            "/subscribers/import",
+            get(import_subscribers_form)
+                .post(import_subscribers)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+        )
+        .route("/subscribers/export", get(export_subscribers))
+        .route("/subscribers/{subscriber_id}", get(subscriber_detail))
+        .route(
+            "/subscribers/{subscriber_id}/consent",
+            get(export_subscriber_consent),
+        )
+        .route(
+            "/subscribers/{subscriber_id}/tags",
+            post(add_subscriber_tag),
+        )
+        .route(
+            "/subscribers/{subscriber_id}/tags/remove",
+            post(remove_subscriber_tag),
+        )
         .route(
             "/newsletters",
             get(publish_newsletter_form).post(publish_newsletter),
         )
+        .route("/newsletters/recipients", get(count_recipients))
+        .route("/newsletters/issues", get(issues_page))
+        .route(
+            "/newsletters/issues/{issue_id}/archive",
+            post(set_issue_archived),
+        )
+        .route("/newsletters/drafts", get(drafts_page).post(create_draft))
+        .route("/blog/sync", post(sync_blog_posts))
+        .route(
+            "/newsletters/drafts/{draft_id}",
+            get(draft_form).post(update_draft),
+        )
+        .route("/newsletters/drafts/{draft_id}/preview", get(preview_draft))
+        .route("/newsletters/drafts/{draft_id}/test", post(send_test_issue))
+        .route(
+            "/newsletters/drafts/{draft_id}/publish",
+            post(publish_draft),
+        )
+        .route(
+            "/newsletters/drafts/{draft_id}/schedule",
+            post(schedule_draft),
+        )
+        .route(
+            "/newsletters/drafts/{draft_id}/unschedule",
+            post(unschedule_draft),
+        )
         .layer(middleware::from_fn(reject_anonymous_users));
 
     // Wrapped in an Arc pointer to allow cheap cloning of AppState across handlers.
@@ -91,7 +184,11 @@ pub async fn run(
         pool,
         email_client,
         base_url: ApplicationBaseUrl(base_url),
-        _hmac_secret: HmacSecret(SecretString::from(_hmac_secret)),
+        delivery_trigger,
+        schedule_trigger,
+        hmac_secret: HmacSecret(hmac_secret),
+        blog,
+        client_ip_source,
     });
 
     let app = Router::new()
@@ -102,8 +199,28 @@ pub async fn run(
         .route("/subscriptions", post(subscribe))
         .route("/subscriptions", get(subscribe_form))
         .route("/subscriptions/confirm", get(confirm))
+        .route(
+            "/subscriptions/data",
+            get(data_request_form).post(request_data_link),
+        )
+        .route("/subscriptions/data/manage", get(manage_data))
+        .route("/subscriptions/data/export", get(export_data))
+        .route("/subscriptions/data/erase", post(erase_data))
+        .route(
+            "/subscriptions/preferences",
+            get(preferences_form).post(update_preferences),
+        )
+        .route("/subscriptions/preferences/pause", post(pause_delivery))
+        .route(
+            "/subscriptions/unsubscribe",
+            get(unsubscribe_form).post(unsubscribe),
+        )
         .route("/blog", get(blog_index))
         .route("/blog/{slug}", get(blog_post))
+        .route("/newsletter/archive", get(newsletter_archive))
+        .route("/newsletter/archive/{slug}", get(archived_issue))
+        .route("/newsletter/feed.xml", get(atom_feed))
+        .route("/newsletter/rss.xml", get(rss_feed))
         .nest("/admin", admin_routes)
         .fallback_service(ServeDir::new("frontend/dist"))
         .layer(
@@ -124,7 +241,7 @@ pub async fn run(
                             |response: &Response, latency: std::time::Duration, span: &Span| {
                                 let status = response.status();
                                 let headers = response.headers();
-                                span.record("status", &status.as_u16());
+                                span.record("status", status.as_u16());
                                 info!(parent: span, ?status, ?headers, ?latency, "Response sent");
                             },
                         )
@@ -137,7 +254,11 @@ pub async fn run(
         )
         .with_state(app_state);
 
-    Ok(axum::serve(listener, app))
+    // the peer address ends up in the consent records
+    Ok(axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    ))
 }
 
 #[derive(Clone)]
@@ -145,7 +266,10 @@ pub struct HmacSecret(pub SecretString);
 
 pub struct Application {
     port: u16,
-    server: Serve<TcpListener, Router, Router>,
+    server: Server,
+    delivery_worker: JoinHandle<anyhow::Result<()>>,
+    scheduler: JoinHandle<anyhow::Result<()>>,
+    digest: JoinHandle<anyhow::Result<()>>,
 }
 
 impl Application {
@@ -161,34 +285,85 @@ impl Application {
 
         let pool = configure_database(&configuration.database).await?;
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            sender_email,
-            configuration.email_client.base_url.clone(),
-            configuration.email_client.authorization_token,
-            timeout,
-        );
+        let email_client = configuration.email_client.clone().client();
+        let delivery_trigger = Arc::new(Notify::new());
+        let schedule_trigger = Arc::new(Notify::new());
 
         let server = run(
             listener,
-            pool,
+            pool.clone(),
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret.clone(),
             configuration.redis_uri,
+            delivery_trigger.clone(),
+            schedule_trigger.clone(),
+            configuration.blog.clone(),
+            configuration.application.client_ip_source,
         )
         .await?;
 
-        Ok(Self { server, port })
+        // drafts for posts published while the app was down, a failure here
+        // is no reason not to serve
+        if configuration.blog.sync_on_startup {
+            if let Err(e) = announce_new_posts(
+                &pool,
+                &configuration.blog,
+                &configuration.application.base_url,
+            )
+            .await
+            {
+                tracing::error!(error.cause_chain = ?e, "Failed to announce new blog posts");
+            }
+        }
+
+        // the posts already out go in no digest, noted before anything else
+        // writes
+        if let Err(e) = take_digest_baseline(&pool, &configuration.blog).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to note the blog posts already out");
+        }
+
+        // scheduled drafts are published from the same process too
+        let scheduler = tokio::spawn(run_scheduler_until_stopped(
+            pool.clone(),
+            delivery_trigger.clone(),
+            schedule_trigger,
+        ));
+
+        // and so are the weekly digests of blog posts
+        let digest = tokio::spawn(run_digest_until_stopped(
+            pool.clone(),
+            configuration.blog,
+            configuration.application.base_url.clone(),
+            delivery_trigger.clone(),
+        ));
+
+        // newsletter issues are delivered in the background, next to the server
+        let delivery_worker = tokio::spawn(run_worker_until_stopped(
+            pool,
+            configuration.email_client.client(),
+            configuration.delivery.retry_policy(),
+            delivery_trigger,
+            configuration.application.base_url,
+            HmacSecret(configuration.application.hmac_secret),
+        ));
+
+        Ok(Self {
+            server,
+            port,
+            delivery_worker,
+            scheduler,
+            digest,
+        })
     }
 
     pub async fn run_until_stopped(self) -> anyhow::Result<()> {
-        Ok(self.server.await?)
+        tokio::select! {
+            outcome = self.server.into_future() => Ok(outcome?),
+            outcome = self.delivery_worker => outcome?,
+            outcome = self.scheduler => outcome?,
+            outcome = self.digest => outcome?,
+        }
     }
 
     pub fn port(&self) -> u16 {
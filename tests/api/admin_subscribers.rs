@@ -0,0 +1,308 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, create_unconfirmed_subscriber,
+    spawn_app, PostmarkBatchResponder, TestApp,
+};
+
+/// Insert a subscriber straight into the database, much faster than going
+/// through `POST /subscriptions` when a test needs a lot of them.
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str, subscribed_at: &str) {
+    let uuid = Uuid::new_v4().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (uuid, name, email, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        uuid,
+        "Ursula",
+        email,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_subscribers("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "confirmed",
+        "2025-06-01 10:00:00 UTC",
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "le_guin@example.com",
+        "confirmed",
+        "2025-06-02 10:00:00 UTC",
+    )
+    .await;
+
+    // Act
+    let html_page = app.get_subscribers_html("q=ursula%40").await;
+
+    // Assert
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("le_guin@example.com"));
+
+    // `_` is a LIKE wildcard, it must only match itself
+    let html_page = app.get_subscribers_html("q=e_g").await;
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("le_guin@example.com"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_subscription_date() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(
+        &app,
+        "may@example.com",
+        "confirmed",
+        "2025-05-15 10:00:00 UTC",
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "june@example.com",
+        "confirmed",
+        "2025-06-03 23:00:00 UTC",
+    )
+    .await;
+    insert_subscriber(
+        &app,
+        "left@example.com",
+        "unsubscribed",
+        "2025-06-02 10:00:00 UTC",
+    )
+    .await;
+
+    // Act
+    let html_page = app
+        .get_subscribers_html(
+            "status=confirmed&subscribed_from=2025-06-01&subscribed_to=2025-06-03",
+        )
+        .await;
+
+    // Assert
+    assert!(html_page.contains("june@example.com"));
+    assert!(!html_page.contains("may@example.com"));
+    assert!(!html_page.contains("left@example.com"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_invalid_date_filter_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers("subscribed_from=yesterday").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..51 {
+        insert_subscriber(
+            &app,
+            &format!("subscriber{:02}@example.com", i),
+            "confirmed",
+            "2025-06-01 10:00:00 UTC",
+        )
+        .await;
+    }
+
+    // Act - Part 1 - Newest first
+    let html_page = app.get_subscribers_html("").await;
+
+    // Assert - Part 1
+    assert!(html_page.contains("subscriber50@example.com"));
+    assert!(html_page.contains("subscriber01@example.com"));
+    assert!(!html_page.contains("subscriber00@example.com"));
+    assert!(html_page.contains("Next page"));
+
+    // Act - Part 2 - Follow the next page link
+    let oldest_on_first_page = sqlx::query!(
+        "SELECT id AS \"id!\" FROM subscriptions WHERE email = 'subscriber01@example.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .id;
+    let html_page = app
+        .get_subscribers_html(&format!("after={}", oldest_on_first_page))
+        .await;
+
+    // Assert - Part 2
+    assert!(html_page.contains("subscriber00@example.com"));
+    assert!(!html_page.contains("subscriber01@example.com"));
+    assert!(!html_page.contains("Next page"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_the_confirmation_token() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT s.uuid, s.email, t.subscription_token
+        FROM subscriptions s
+        JOIN subscription_tokens t ON t.subscriber_id = s.uuid
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.get_subscriber(&subscriber.uuid).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&subscriber.email));
+    assert!(html_page.contains(&subscriber.subscription_token));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_detail_page_of_an_unknown_subscriber_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscriber(&Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_the_delivery_history() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula@example.com".to_string();
+    create_confirmed_subscriber_with_email(&app, email.clone()).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "The Dispossessed",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+    let subscriber_id = sqlx::query!("SELECT uuid FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .uuid;
+
+    // Act
+    let html_page = app
+        .get_subscriber(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("The Dispossessed"));
+    assert!(html_page.contains("delivered"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_consent_record_can_be_downloaded_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.test_user.login(&app).await;
+    let subscriber_id = sqlx::query!("SELECT uuid FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .uuid;
+
+    // Act - Part 1 - The detail page
+    let html_page = app
+        .get_subscriber(&subscriber_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("confirmation"));
+    assert!(html_page.contains(&format!("/admin/subscribers/{}/consent", subscriber_id)));
+
+    // Act - Part 2 - The JSON export
+    let response = app.get_subscriber_consent(&subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let record: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(record["subscriber_id"], subscriber_id);
+    assert_eq!(record["status"], "confirmed");
+    let events = record["consent_events"].as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event_type"], "signup");
+    assert_eq!(events[1]["event_type"], "confirmation");
+    assert_eq!(events[1]["ip_address"], "127.0.0.1");
+
+    app.cleanup_test_db().await.unwrap()
+}
@@ -0,0 +1,266 @@
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email,
+    create_unconfirmed_subscriber_with_email, spawn_app, PostmarkBatchResponder, TestApp,
+};
+
+struct StoredSubscriber {
+    email: String,
+    status: String,
+    consent_source: Option<String>,
+}
+
+async fn stored_subscribers(app: &TestApp) -> Vec<StoredSubscriber> {
+    sqlx::query_as!(
+        StoredSubscriber,
+        "SELECT email, status, consent_source FROM subscriptions ORDER BY email"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_import_subscribers(
+            "pending_confirmation",
+            "",
+            "name,email\nUrsula,ursula@example.com\n",
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(stored_subscribers(&app).await.is_empty());
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_pending_import_sends_a_confirmation_email_to_every_row() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Import
+    let response = app
+        .post_import_subscribers(
+            "pending_confirmation",
+            "",
+            "email,name\r\nursula@example.com,Ursula\r\njo@example.com,Jo\r\n",
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Assert
+    let subscribers = stored_subscribers(&app).await;
+    assert_eq!(subscribers.len(), 2);
+    assert!(subscribers
+        .iter()
+        .all(|s| s.status == "pending_confirmation" && s.consent_source.is_none()));
+    let emails = app.received_newsletter_emails().await;
+    assert_eq!(emails.len(), 2);
+    assert!(emails[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/confirm?subscription_token="));
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("Imported 2 subscribers."));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_confirmed_import_records_the_consent_source_and_sends_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_import_subscribers(
+        "confirmed",
+        "Exported from Mailchimp",
+        "name,email\nUrsula,ursula@example.com\n",
+    )
+    .await;
+
+    // Assert
+    let subscribers = stored_subscribers(&app).await;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].status, "confirmed");
+    assert_eq!(
+        subscribers[0].consent_source.as_deref(),
+        Some("Exported from Mailchimp")
+    );
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_confirmed_import_needs_a_consent_source() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_import_subscribers("confirmed", "  ", "name,email\nUrsula,ursula@example.com\n")
+        .await;
+
+    // Assert
+    assert!(stored_subscribers(&app).await.is_empty());
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("Say where pre-confirmed subscribers gave their consent."));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn invalid_rows_are_reported_with_their_line_number() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csv = "name,email\n\
+               Ursula,ursula@example.com\n\
+               Jo,not-an-email\n\
+               ,nameless@example.com\n\
+               Ursula,ursula@example.com\n\
+               Le Guin\n";
+
+    // Act
+    app.post_import_subscribers("confirmed", "Paper sign-up sheet", csv)
+        .await;
+
+    // Assert
+    let subscribers = stored_subscribers(&app).await;
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].email, "ursula@example.com");
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("Imported 1 subscribers."));
+    assert!(html_page.contains("Line 3: not-an-email is not a valid subscriber email."));
+    assert!(html_page.contains("Line 4:"));
+    assert!(html_page.contains("Line 5: ursula@example.com already appears earlier in the file."));
+    assert!(html_page.contains("Line 6: expected at least 2 columns."));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_file_without_name_and_email_columns_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_import_subscribers(
+        "confirmed",
+        "Paper sign-up sheet",
+        "Ursula,ursula@example.com\n",
+    )
+    .await;
+
+    // Assert
+    assert!(stored_subscribers(&app).await.is_empty());
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("The first line must name the columns"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_import_leaves_existing_subscribers_alone() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    create_unconfirmed_subscriber_with_email(&app, "jo@example.com".into()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed' WHERE email = 'jo@example.com'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_import_subscribers(
+        "confirmed",
+        "Exported from Mailchimp",
+        "name,email\nUrsula,ursula@example.com\nJo,jo@example.com\n",
+    )
+    .await;
+
+    // Assert
+    let subscribers = stored_subscribers(&app).await;
+    assert_eq!(subscribers[0].email, "jo@example.com");
+    assert_eq!(subscribers[0].status, "unsubscribed");
+    assert_eq!(subscribers[1].consent_source, None);
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("Imported 0 subscribers."));
+    assert!(html_page.contains("Skipped 2 rows whose email is already on the list."));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_export_is_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_import_subscribers(
+        "confirmed",
+        "Exported from Mailchimp",
+        "name,email\n\"Le Guin, Ursula\",ursula@example.com\n",
+    )
+    .await;
+    create_unconfirmed_subscriber_with_email(&app, "jo@example.com".into()).await;
+
+    // Act
+    let response = app.get_subscribers_export("status=confirmed").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "name,email,status,subscribed_at,consent_source");
+    assert!(lines[1].starts_with("\"Le Guin, Ursula\",ursula@example.com,confirmed,"));
+    assert!(lines[1].ends_with(",Exported from Mailchimp"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_status_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers_export("status=bogus").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup_test_db().await.unwrap()
+}
@@ -0,0 +1,196 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, create_draft, spawn_app, TestApp,
+};
+use uuid::Uuid;
+
+async fn publish(app: &TestApp, body: serde_json::Value) {
+    let mut body = body;
+    body["idempotency_key"] = Uuid::new_v4().to_string().into();
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+fn issue(title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn issue_id(app: &TestApp, slug: &str) -> String {
+    sqlx::query!("SELECT uuid FROM newsletter_issues WHERE slug = $1", slug)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .uuid
+}
+
+#[tokio::test]
+async fn published_issues_are_in_the_public_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, issue("Hello, World!")).await;
+    publish(&app, issue("Hello, World!")).await;
+
+    // Act
+    let archive = reqwest::get(format!("{}/newsletter/archive", app.address))
+        .await
+        .unwrap();
+    let first = app.get_archived_issue("hello-world").await;
+    let second = app.get_archived_issue("hello-world-2").await;
+
+    // Assert
+    assert_eq!(archive.status().as_u16(), 200);
+    let archive = archive.text().await.unwrap();
+    assert!(archive.contains(r#"href="/newsletter/archive/hello-world""#));
+    assert!(archive.contains(r#"href="/newsletter/archive/hello-world-2""#));
+    assert_eq!(first.status().as_u16(), 200);
+    let first = first.text().await.unwrap();
+    assert!(first.contains("Hello, World!"));
+    assert!(first.contains("Newsletter body as HTML"));
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(app.get_archived_issue("nope").await.status().as_u16(), 404);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_public_copy_has_no_recipient_details() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    publish(
+        &app,
+        serde_json::json!({
+            "title": "Personal",
+            "text_content": "Hi {{ name }}, {{ unsubscribe_url }}",
+            "html_content": "<p>Hi {{ name }}, this is for {{ email }} on {{ list_name }}.</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        }),
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let html_page = app
+        .get_archived_issue("personal")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("Hi reader, this is for your email address on Newzletter."));
+    assert!(html_page.contains("/subscriptions/data"));
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("/subscriptions/unsubscribe"));
+    assert!(!html_page.contains("{{"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_issue_can_be_left_out_of_the_archive_when_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = issue("Members only");
+    body["hidden_from_archive"] = "true".into();
+
+    // Act
+    publish(&app, body).await;
+
+    // Assert
+    let archive = app.get_newsletter_archive().await.text().await.unwrap();
+    assert!(!archive.contains("Members only"));
+    assert_eq!(
+        app.get_archived_issue("members-only")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    let admin_page = app.get_published_issues().await.text().await.unwrap();
+    assert!(admin_page.contains("Members only"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_issue_can_be_hidden_and_shown_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, issue("Oops")).await;
+    let issue_id = issue_id(&app, "oops").await;
+
+    // Act - Part 1 - Hide it
+    let response = app.post_set_issue_archived(&issue_id, true).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/issues");
+    let admin_page = app.get_published_issues().await.text().await.unwrap();
+    assert!(admin_page.contains("The issue is hidden from the public archive."));
+    assert_eq!(app.get_archived_issue("oops").await.status().as_u16(), 404);
+
+    // Act - Part 2 - Show it again
+    app.post_set_issue_archived(&issue_id, false).await;
+
+    // Assert
+    assert_eq!(app.get_archived_issue("oops").await.status().as_u16(), 200);
+    let unknown = app
+        .post_set_issue_archived(&Uuid::new_v4().to_string(), true)
+        .await;
+    assert_eq!(unknown.status().as_u16(), 404);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_keeps_its_archive_choice_when_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut body = issue("Drafted in private");
+    body["hidden_from_archive"] = "true".into();
+    let draft_id = create_draft(&app, &body).await;
+    let draft_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(draft_page.contains(
+        r#"name="hidden_from_archive" value="true" class="checkbox checkbox-primary" checked"#
+    ));
+
+    // Act
+    let response = app
+        .post_publish_draft(&draft_id, &Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(
+        app.get_archived_issue("drafted-in-private")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let page = app.get_published_issues().await;
+    let hide = app
+        .post_set_issue_archived(&Uuid::new_v4().to_string(), true)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&page, "/login");
+    assert_is_redirect_to(&hide, "/login");
+
+    app.cleanup_test_db().await.unwrap()
+}
@@ -0,0 +1,172 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, spawn_app, TestApp,
+};
+use newzletter::blog_posts::announce_new_posts;
+use newzletter::configuration::BlogSettings;
+use newzletter::scheduler::publish_due_drafts;
+
+const FRONTMATTER: &str = "title: 'Rust & SQLite'\n\
+    description: 'Why one *file* is enough'\n\
+    pubDate: 'Jun 15 2025'\n\
+    heroImage: '../../assets/hero.jpg'";
+
+async fn drafts(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT title, markdown_content FROM newsletter_drafts")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.title, r.markdown_content))
+        .collect()
+}
+
+#[tokio::test]
+async fn a_new_blog_post_is_drafted_as_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, true);
+
+    // Act
+    let response = app.post_sync_blog().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("Rust &#38; SQLite is drafted."));
+    let drafts = drafts(&app).await;
+    assert_eq!(drafts.len(), 1);
+    let (title, markdown) = &drafts[0];
+    assert_eq!(title, "Rust & SQLite");
+    assert!(markdown.contains("](http://127.0.0.1/_astro/hero.Bx0Zcyzv.webp)"));
+    assert!(markdown.contains(r"Why one \*file\* is enough"));
+    assert!(markdown.contains("(http://127.0.0.1/blog/rust-and-sqlite)"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_blog_post_is_announced_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, true);
+    app.post_sync_blog().await;
+    app.get_drafts_html().await;
+
+    // Act
+    app.post_sync_blog().await;
+
+    // Assert
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("There are no new blog posts."));
+    assert_eq!(drafts(&app).await.len(), 1);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn posts_out_before_the_first_sync_are_not_drafted() {
+    // Arrange
+    let app = spawn_app().await;
+    // as if the server had never looked at the blog
+    sqlx::query!("DELETE FROM blog_announcements_baseline")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, true);
+
+    // Act - Part 1 - First sync
+    let announcements = announce_new_posts(&app.db_pool, &app.blog, "https://example.com")
+        .await
+        .unwrap();
+    assert!(announcements.is_empty());
+    assert_eq!(drafts(&app).await.len(), 0);
+
+    // Act - Part 2 - A post after it
+    app.write_blog_post("Queues.md", "queues", "title: 'Queues'", true);
+    let announcements = announce_new_posts(&app.db_pool, &app.blog, "https://example.com")
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(announcements.len(), 1);
+    assert_eq!(announcements[0].title, "Queues");
+    assert_eq!(drafts(&app).await.len(), 1);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_post_is_only_announced_once_the_site_is_built_with_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, false);
+
+    // Act - Part 1 - Written but not built
+    app.post_sync_blog().await;
+    assert_eq!(drafts(&app).await.len(), 0);
+
+    // Act - Part 2 - Built
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, true);
+    app.post_sync_blog().await;
+
+    // Assert
+    assert_eq!(drafts(&app).await.len(), 1);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn with_auto_send_a_new_post_goes_out_right_away() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, true);
+    let settings = BlogSettings {
+        auto_send: true,
+        ..app.blog.clone()
+    };
+
+    // Act
+    let announcements = announce_new_posts(&app.db_pool, &settings, "https://example.com")
+        .await
+        .unwrap();
+    let n_published = publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(announcements.len(), 1);
+    assert_eq!(n_published, 1);
+    let issue = sqlx::query!("SELECT title, html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Rust & SQLite");
+    assert!(issue
+        .html_content
+        .contains("https://example.com/blog/rust-and-sqlite"));
+    assert_eq!(drafts(&app).await.len(), 0);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_sync_the_blog() {
+    // Arrange
+    let app = spawn_app().await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FRONTMATTER, true);
+
+    // Act
+    let response = app.post_sync_blog().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(drafts(&app).await.len(), 0);
+
+    app.cleanup_test_db().await.unwrap()
+}
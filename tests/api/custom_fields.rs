@@ -0,0 +1,289 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, PostmarkBatchResponder, TestApp};
+
+/// A required `role` select and an optional `company` text field.
+async fn create_fields(app: &TestApp) {
+    app.post_create_custom_field(&serde_json::json!({
+        "key": "role",
+        "label": "Role",
+        "field_type": "select",
+        "rule": "developer, designer",
+        "required": "on",
+    }))
+    .await;
+    app.post_create_custom_field(&serde_json::json!({
+        "key": "company",
+        "label": "Company",
+        "field_type": "text",
+        "rule": "",
+    }))
+    .await;
+}
+
+async fn subscribe(app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn stored_answers(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT f.key, v.value
+        FROM subscriber_field_values v
+        JOIN custom_fields f ON f.uuid = v.field_id
+        JOIN subscriptions s ON s.uuid = v.subscriber_id
+        WHERE s.email = $1
+        ORDER BY f.key
+        "#,
+        email,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.key, r.value))
+    .collect()
+}
+
+#[tokio::test]
+async fn an_admin_can_define_a_custom_field() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create the field
+    let response = app
+        .post_create_custom_field(&serde_json::json!({
+            "key": "role",
+            "label": "Role",
+            "field_type": "select",
+            "rule": "developer, designer",
+            "required": "on",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/fields");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_custom_fields_html().await;
+    assert!(html_page.contains("The field role has been created."));
+    assert!(html_page.contains("developer, designer"));
+
+    // Act - Part 3 - It shows up on the signup form
+    let html_page = app
+        .api_client
+        .get(format!("{}/subscriptions", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"name="role""#));
+    assert!(html_page.contains("<option>designer</option>"));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_field_with_an_invalid_rule_or_a_taken_key_is_not_created() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+
+    // Act - Part 1 - A taken key
+    app.post_create_custom_field(&serde_json::json!({
+        "key": "role",
+        "label": "Role again",
+        "field_type": "text",
+        "rule": "",
+    }))
+    .await;
+    let html_page = app.get_custom_fields_html().await;
+    assert!(html_page.contains("There already is a field called role."));
+
+    // Act - Part 2 - A rule that doesn't fit the type
+    app.post_create_custom_field(&serde_json::json!({
+        "key": "birthday",
+        "label": "Birthday",
+        "field_type": "date",
+        "rule": "last week",
+    }))
+    .await;
+    let html_page = app.get_custom_fields_html().await;
+    assert!(html_page.contains("Bound a date field with YYYY-MM-DD..YYYY-MM-DD"));
+
+    // Assert
+    let n_fields = sqlx::query!("SELECT COUNT(*) AS count FROM custom_fields")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_fields, 2);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn valid_answers_are_stored_with_the_new_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = subscribe(
+        &app,
+        &[
+            ("name", "Ursula"),
+            ("email", "ursula@example.com"),
+            ("role", "designer"),
+            ("company", "  "),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        stored_answers(&app, "ursula@example.com").await,
+        [("role".to_string(), "designer".to_string())]
+    );
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_a_custom_field_is_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    let test_cases = [
+        (vec![], "missing a required field"),
+        (vec![("role", "manager")], "not one of the options"),
+    ];
+
+    for (fields, description) in test_cases {
+        let mut form = vec![("name", "Ursula"), ("email", "ursula@example.com")];
+        form.extend(fields);
+
+        // Act
+        let response = subscribe(&app, &form).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not return a 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) AS count FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn custom_fields_are_merge_tags_in_newsletter_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    subscribe(
+        &app,
+        &[
+            ("name", "Ursula"),
+            ("email", "ursula@example.com"),
+            ("role", "developer"),
+            ("company", "Fish & Chips"),
+        ],
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Hiring",
+        "text_content": "Does {{ company }} need a {{role}}?",
+        "html_content": "<p>Does {{ company }} need a {{role}}?</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    assert!(message["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Does Fish & Chips need a developer?"));
+    assert!(message["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Does Fish &amp; Chips need a developer?</p>"));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_segment_on_an_unknown_field_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_fields(&app).await;
+
+    // Act - Part 1 - A field that exists
+    app.post_create_segment("Designers", "field.role:designer")
+        .await;
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("The segment Designers has been created."));
+
+    // Act - Part 2 - One that doesn't
+    app.post_create_segment("Cities", r#"field.city:"New York""#)
+        .await;
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("There is no field called city."));
+
+    app.cleanup_test_db().await.unwrap();
+}
@@ -0,0 +1,258 @@
+use crate::helpers::{create_confirmed_subscriber_with_email, spawn_app, TestApp};
+use chrono::{TimeDelta, Utc};
+use newzletter::blog_posts::announce_new_posts;
+use newzletter::configuration::BlogSettings;
+use newzletter::digest::send_due_digest;
+use newzletter::scheduler::publish_due_drafts;
+use reqwest::StatusCode;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const FIRST_POST: &str = "title: 'Rust & SQLite'\n\
+    description: 'Why one file is enough'\n\
+    pubDate: 'Jun 15 2025'";
+
+const SECOND_POST: &str = "title: 'Queues in SQLite'\n\
+    description: 'No broker needed'\n\
+    pubDate: 'Jun 22 2025'";
+
+async fn create_digest_subscriber(app: &TestApp, email: &str) {
+    create_confirmed_subscriber_with_email(app, email.into()).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET delivery_preference = 'weekly_digest' WHERE email = $1",
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Who each published issue was queued for, as `(title, email)`.
+async fn queued_deliveries(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT i.title, q.subscriber_email
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.uuid = q.newsletter_issue_id
+        ORDER BY i.id, q.subscriber_email
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.title, r.subscriber_email))
+    .collect()
+}
+
+#[tokio::test]
+async fn the_digest_goes_to_digest_subscribers_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "instant@example.com".into()).await;
+    create_digest_subscriber(&app, "digest@example.com").await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FIRST_POST, true);
+
+    // Act
+    let sent = send_due_digest(&app.db_pool, &app.blog, "https://example.com", Utc::now())
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(sent);
+    assert_eq!(
+        queued_deliveries(&app).await,
+        vec![(
+            "This week on the blog".to_owned(),
+            "digest@example.com".to_owned()
+        )]
+    );
+    let issue = sqlx::query!(
+        r#"SELECT html_content, text_content, hidden_from_archive AS "hidden: bool" FROM newsletter_issues"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(issue.hidden);
+    assert!(issue
+        .html_content
+        .contains(r#"href="https://example.com/blog/rust-and-sqlite""#));
+    assert!(issue.text_content.contains("Why one file is enough"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_post_is_in_one_digest_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_digest_subscriber(&app, "digest@example.com").await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FIRST_POST, true);
+    let now = Utc::now();
+    send_due_digest(&app.db_pool, &app.blog, "https://example.com", now)
+        .await
+        .unwrap();
+    app.write_blog_post("Queues in SQLite.md", "queues-in-sqlite", SECOND_POST, true);
+
+    // Act
+    let next_week = now + TimeDelta::days(7);
+    let sent = send_due_digest(&app.db_pool, &app.blog, "https://example.com", next_week)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(sent);
+    let issues = sqlx::query!("SELECT text_content FROM newsletter_issues ORDER BY id")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.len(), 2);
+    assert!(!issues[1].text_content.contains("Rust & SQLite"));
+    assert!(issues[1].text_content.contains("Queues in SQLite"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn no_digest_goes_out_within_a_week_of_the_last_one() {
+    // Arrange
+    let app = spawn_app().await;
+    create_digest_subscriber(&app, "digest@example.com").await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FIRST_POST, true);
+    let now = Utc::now();
+    send_due_digest(&app.db_pool, &app.blog, "https://example.com", now)
+        .await
+        .unwrap();
+    app.write_blog_post("Queues in SQLite.md", "queues-in-sqlite", SECOND_POST, true);
+
+    // Act
+    let sent = send_due_digest(
+        &app.db_pool,
+        &app.blog,
+        "https://example.com",
+        now + TimeDelta::days(6),
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert!(!sent);
+    assert_eq!(queued_deliveries(&app).await.len(), 1);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn posts_out_before_the_first_run_are_in_no_digest() {
+    // Arrange
+    let app = spawn_app().await;
+    create_digest_subscriber(&app, "digest@example.com").await;
+    // as if the digest job had never run
+    sqlx::query!("DELETE FROM blog_digests")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FIRST_POST, true);
+
+    // Act - Part 1 - First run
+    let sent = send_due_digest(&app.db_pool, &app.blog, "https://example.com", Utc::now())
+        .await
+        .unwrap();
+    assert!(!sent);
+
+    // Act - Part 2 - A post after it, no need to wait a week
+    app.write_blog_post("Queues in SQLite.md", "queues-in-sqlite", SECOND_POST, true);
+    let sent = send_due_digest(&app.db_pool, &app.blog, "https://example.com", Utc::now())
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(sent);
+    let issue = sqlx::query!("SELECT text_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!issue.text_content.contains("Rust & SQLite"));
+    assert!(issue.text_content.contains("Queues in SQLite"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn no_digest_goes_out_without_new_posts() {
+    // Arrange
+    let app = spawn_app().await;
+    create_digest_subscriber(&app, "digest@example.com").await;
+
+    // Act
+    let sent = send_due_digest(&app.db_pool, &app.blog, "https://example.com", Utc::now())
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(!sent);
+    assert!(queued_deliveries(&app).await.is_empty());
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn blog_post_announcements_skip_digest_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "instant@example.com".into()).await;
+    create_digest_subscriber(&app, "digest@example.com").await;
+    app.write_blog_post("Rust and SQLite.md", "rust-and-sqlite", FIRST_POST, true);
+    let settings = BlogSettings {
+        auto_send: true,
+        ..app.blog.clone()
+    };
+
+    // Act
+    announce_new_posts(&app.db_pool, &settings, "https://example.com")
+        .await
+        .unwrap();
+    publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(
+        queued_deliveries(&app).await,
+        vec![("Rust & SQLite".to_owned(), "instant@example.com".to_owned())]
+    );
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_can_ask_for_the_weekly_digest_when_signing_up() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("delivery", "weekly_digest"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let saved = sqlx::query!("SELECT delivery_preference FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.delivery_preference, "weekly_digest");
+
+    app.cleanup_test_db().await.unwrap()
+}
@@ -0,0 +1,392 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, create_draft, spawn_app,
+    PostmarkBatchResponder, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Hi {{ name }}, this is for {{ email }}",
+        "html_content": "<p>Hi {{ name }}, this is for {{ email }}</p>",
+    })
+}
+
+async fn pending_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn saving_a_draft_queues_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let draft_id = create_draft(&app, &draft_body()).await;
+
+    // Assert
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(app.get_drafts_html().await.contains("Draft title"));
+    assert_eq!(pending_deliveries(&app).await, 0);
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_unfinished_draft_can_be_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let draft_id = create_draft(&app, &serde_json::json!({ "title": "Just an idea" })).await;
+
+    // Assert
+    let response = app.get_draft(&draft_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Just an idea"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_can_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+
+    // Act
+    let response = app
+        .post_update_draft(
+            &draft_id,
+            &serde_json::json!({
+                "title": "A better title",
+                "format": "markdown",
+                "markdown_content": "Hi **there**",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("A better title"));
+    assert!(html_page.contains("Hi **there**"));
+    let drafts_page = app.get_drafts_html().await;
+    assert!(drafts_page.contains("A better title"));
+    assert!(!drafts_page.contains("Draft title"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_unknown_draft_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = Uuid::new_v4().to_string();
+
+    // Act
+    let page = app.get_draft(&draft_id).await;
+    let update = app.post_update_draft(&draft_id, &draft_body()).await;
+    let publish = app
+        .post_publish_draft(&draft_id, &Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 404);
+    assert_eq!(update.status().as_u16(), 404);
+    assert_eq!(publish.status().as_u16(), 404);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_preview_fills_in_merge_tags_for_the_chosen_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    create_confirmed_subscriber_with_email(&app, "octavia@example.com".into()).await;
+    sqlx::query!("UPDATE subscriptions SET name = 'Octavia' WHERE email = 'octavia@example.com'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+
+    // Act
+    let first = app.get_draft_preview(&draft_id, "").await;
+    let chosen = app.get_draft_preview(&draft_id, "ursula@example.com").await;
+    let stranger = app
+        .get_draft_preview(&draft_id, "stranger@example.com")
+        .await;
+
+    // Assert
+    let first = first.text().await.unwrap();
+    assert!(first.contains("Hi Octavia, this is for octavia@example.com"));
+    assert!(first.contains("#unsubscribe"));
+    let chosen = chosen.text().await.unwrap();
+    assert!(chosen.contains("this is for ursula@example.com"));
+    assert!(!chosen.contains("this is for octavia@example.com"));
+    assert_eq!(stranger.status().as_u16(), 400);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_nobody_would_get_previews_with_empty_merge_tags() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+
+    // Act
+    let response = app.get_draft_preview(&draft_id, "").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Nobody would get this draft yet"));
+    assert!(html_page.contains("Hi , this is for </pre>"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_test_send_goes_to_the_admin_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    app.post_change_email("admin@example.com").await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_send_test_issue(&draft_id, "ursula@example.com")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!(
+            "/admin/newsletters/drafts/{}/preview?subscriber=ursula%40example.com",
+            draft_id
+        ),
+    );
+    let html_page = app
+        .get_draft_preview(&draft_id, "ursula@example.com")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("A test of the draft has been sent to admin@example.com."));
+    let test_send = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .rfind(|r| r.url.path() == "/email")
+        .unwrap();
+    let message: serde_json::Value = serde_json::from_slice(&test_send.body).unwrap();
+    assert_eq!(message["To"], "admin@example.com");
+    assert_eq!(message["Subject"], "[Test] Draft title");
+    assert!(message["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(", this is for ursula@example.com"));
+    assert_eq!(pending_deliveries(&app).await, 0);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_test_send_carries_no_signed_links_for_the_sample_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    app.post_change_email("admin@example.com").await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_send_test_issue(&draft_id, "ursula@example.com")
+        .await;
+
+    // Assert
+    let test_send = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .rfind(|r| r.url.path() == "/email")
+        .unwrap();
+    let message: serde_json::Value = serde_json::from_slice(&test_send.body).unwrap();
+    for body in [&message["HtmlBody"], &message["TextBody"]] {
+        let body = body.as_str().unwrap();
+        assert!(body.contains("#unsubscribe"));
+        assert!(body.contains("#preferences"));
+        assert!(!body.contains("token="));
+    }
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_test_send_needs_the_admin_email_address() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_send_test_issue(&draft_id, "").await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}/preview?subscriber=", draft_id),
+    );
+    let html_page = app
+        .get_draft_preview(&draft_id, "")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Add your email address on the dashboard first"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn publishing_a_draft_queues_it_once_and_removes_the_draft() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Publish the draft
+    let response = app.post_publish_draft(&draft_id, &idempotency_key).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+
+    // Act - Part 2 - Publish it again
+    let response = app.post_publish_draft(&draft_id, &idempotency_key).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    assert!(message["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(", this is for ursula@example.com"));
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+    assert!(!app.get_drafts_html().await.contains("Draft title"));
+    // Mock verifies on Drop that we have sent the newsletter email once
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_with_an_unknown_merge_tag_is_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(
+        &app,
+        &serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Hi {{ first_name }}",
+            "html_content": "<p>Hi</p>",
+        }),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_publish_draft(&draft_id, &Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("first_name"));
+    assert_eq!(pending_deliveries(&app).await, 0);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_work_on_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let draft_id = Uuid::new_v4().to_string();
+    let page = app.get_draft(&draft_id).await;
+    let create = app.post_create_draft(&draft_body()).await;
+    let test_send = app.post_send_test_issue(&draft_id, "").await;
+
+    // Assert
+    assert_is_redirect_to(&page, "/login");
+    assert_is_redirect_to(&create, "/login");
+    assert_is_redirect_to(&test_send, "/login");
+
+    app.cleanup_test_db().await.unwrap()
+}
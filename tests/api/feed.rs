@@ -0,0 +1,161 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, spawn_app, TestApp,
+};
+use uuid::Uuid;
+
+async fn publish(app: &TestApp, title: &str) {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Hi {{ name }}",
+            "html_content": "<p>Hi {{ name }}, this is for {{ email }}.</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+fn header<'a>(response: &'a reqwest::Response, name: &str) -> &'a str {
+    response
+        .headers()
+        .get(name)
+        .unwrap_or_else(|| panic!("No {} header", name))
+        .to_str()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_are_in_both_feeds() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    publish(&app, "Tips & tricks").await;
+
+    // Act
+    let atom = app.get_newsletter_feed("feed.xml", &[]).await;
+    let rss = app.get_newsletter_feed("rss.xml", &[]).await;
+
+    // Assert
+    assert_eq!(atom.status().as_u16(), 200);
+    assert_eq!(
+        header(&atom, "Content-Type"),
+        "application/atom+xml; charset=utf-8"
+    );
+    let atom = atom.text().await.unwrap();
+    assert!(atom.contains("<title>Tips &amp; tricks</title>"));
+    assert!(atom.contains("/newsletter/archive/tips-tricks"));
+    assert!(atom.contains("Hi reader, this is for your email address."));
+    assert!(!atom.contains("ursula@example.com"));
+    assert_eq!(rss.status().as_u16(), 200);
+    assert_eq!(
+        header(&rss, "Content-Type"),
+        "application/rss+xml; charset=utf-8"
+    );
+    let rss = rss.text().await.unwrap();
+    assert!(rss.contains("<title>Tips &amp; tricks</title>"));
+    assert!(rss.contains("Hi reader, this is for your email address."));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_empty_feed_has_no_last_modified_date() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_newsletter_feed("feed.xml", &[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("ETag").is_some());
+    assert!(response.headers().get("Last-Modified").is_none());
+    assert!(!response.text().await.unwrap().contains("<entry>"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_unchanged_feed_is_not_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, "First").await;
+    let response = app.get_newsletter_feed("feed.xml", &[]).await;
+    let etag = header(&response, "ETag").to_owned();
+    let last_modified = header(&response, "Last-Modified").to_owned();
+
+    for conditional in [
+        ("If-None-Match", etag.as_str()),
+        ("If-Modified-Since", last_modified.as_str()),
+    ] {
+        // Act
+        let response = app.get_newsletter_feed("feed.xml", &[conditional]).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 304, "{:?}", conditional);
+        assert_eq!(header(&response, "ETag"), etag);
+        assert!(response.text().await.unwrap().is_empty());
+    }
+    let stale = app
+        .get_newsletter_feed(
+            "feed.xml",
+            &[("If-Modified-Since", "Sat, 01 Jan 2000 00:00:00 GMT")],
+        )
+        .await;
+    assert_eq!(stale.status().as_u16(), 200);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_new_issue_changes_the_feed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, "First").await;
+    let response = app.get_newsletter_feed("rss.xml", &[]).await;
+    let etag = header(&response, "ETag").to_owned();
+
+    // Act
+    publish(&app, "Second").await;
+    let response = app
+        .get_newsletter_feed("rss.xml", &[("If-None-Match", &etag)])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(header(&response, "ETag"), etag);
+    let rss = response.text().await.unwrap();
+    assert!(rss.find("<title>Second</title>") < rss.find("<title>First</title>"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn hidden_issues_are_left_out_of_the_feed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, "Oops").await;
+    let response = app.get_newsletter_feed("feed.xml", &[]).await;
+    let etag = header(&response, "ETag").to_owned();
+    let issue_id = sqlx::query!("SELECT uuid FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .uuid;
+
+    // Act
+    app.post_set_issue_archived(&issue_id, true).await;
+    let response = app
+        .get_newsletter_feed("feed.xml", &[("If-None-Match", &etag)])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!response.text().await.unwrap().contains("Oops"));
+
+    app.cleanup_test_db().await.unwrap()
+}
@@ -4,8 +4,11 @@ use argon2::{
     password_hash::{rand_core, PasswordHasher, SaltString},
     Algorithm, Argon2, Params, Version,
 };
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use newzletter::{
-    configuration::{configure_database, get_configuration},
+    configuration::{configure_database, get_configuration, BlogSettings, EmailTransportKind},
     startup::Application,
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -13,7 +16,8 @@ use serde::Serialize;
 use sqlx::sqlite::SqlitePool;
 use tokio::fs::remove_file;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Respond, ResponseTemplate};
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 static TRACING: LazyLock<()> = LazyLock::new(|| {
     let default_filter_level = "info".to_string();
@@ -36,6 +40,8 @@ pub struct TestApp {
     pub db_path: String,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    // a blog of its own, empty to begin with
+    pub blog: BlogSettings,
 }
 
 #[derive(Serialize)]
@@ -49,22 +55,36 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+pub struct UnsubscribeLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
 impl TestApp {
     pub async fn post_subscriptions(&self, form_data: &FormData) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .form(form_data)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_data_request(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/data", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -73,7 +93,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -88,7 +108,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -100,7 +120,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -111,7 +131,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -120,7 +140,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -132,7 +152,179 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_list(&self, slug: &str, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(&[("slug", slug), ("name", name)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_custom_fields_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/fields", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_custom_field<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/fields", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/segments", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_segment(&self, name: &str, expression: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/segments", &self.address))
+            .form(&[("name", name), ("expression", expression)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_add_subscriber_tag(
+        &self,
+        subscriber_id: &str,
+        tag: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .form(&[("tag", tag)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_consent(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}/consent",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_import_subscribers_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers/import", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Upload `csv` the way the import form does, options first.
+    pub async fn post_import_subscribers(
+        &self,
+        status: &str,
+        consent_source: &str,
+        csv: &str,
+    ) -> reqwest::Response {
+        let form = reqwest::multipart::Form::new()
+            .text("status", status.to_owned())
+            .text("consent_source", consent_source.to_owned())
+            .part(
+                "file",
+                reqwest::multipart::Part::text(csv.to_owned())
+                    .file_name("subscribers.csv")
+                    .mime_str("text/csv")
+                    .unwrap(),
+            );
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/export?{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_requeue_delivery<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/deliveries/requeue", &self.address))
+            .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -143,39 +335,303 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_update_draft<Body>(&self, draft_id: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_draft(&self, draft_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_preview(&self, draft_id: &str, subscriber: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/drafts/{}/preview",
+                &self.address, draft_id
+            ))
+            .query(&[("subscriber", subscriber)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_send_test_issue(
+        &self,
+        draft_id: &str,
+        subscriber: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/test",
+                &self.address, draft_id
+            ))
+            .form(&[("subscriber", subscriber)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_publish_draft(
+        &self,
+        draft_id: &str,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/publish",
+                &self.address, draft_id
+            ))
+            .form(&[("idempotency_key", idempotency_key)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_schedule_draft(
+        &self,
+        draft_id: &str,
+        scheduled_for: &str,
+        timezone: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/schedule",
+                &self.address, draft_id
+            ))
+            .form(&[("scheduled_for", scheduled_for), ("timezone", timezone)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unschedule_draft(&self, draft_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/unschedule",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_archive(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletter/archive", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archived_issue(&self, slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletter/archive/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_feed(
+        &self,
+        feed: &str,
+        headers: &[(&str, &str)],
+    ) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("{}/newsletter/{}", &self.address, feed));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_published_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_set_issue_archived(&self, issue_id: &str, hidden: bool) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/issues/{}/archive",
+                &self.address, issue_id
+            ))
+            .form(&[("hidden", hidden.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/email", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Extract the confirmation links embedded in the request to the email API.
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
-        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let message: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let (html, plain_text) = self.get_links(&message, "/subscriptions/confirm");
+        ConfirmationLinks { html, plain_text }
+    }
 
+    /// Extract the unsubscribe links embedded in a newsletter issue.
+    pub fn get_unsubscribe_links(&self, message: &serde_json::Value) -> UnsubscribeLinks {
+        let (html, plain_text) = self.get_links(message, "/subscriptions/unsubscribe");
+        UnsubscribeLinks { html, plain_text }
+    }
+
+    /// Extract the link to the preferences page from a newsletter issue.
+    pub fn get_preferences_link(&self, message: &serde_json::Value) -> reqwest::Url {
+        let (_, plain_text) = self.get_links(message, "/subscriptions/preferences");
+        plain_text
+    }
+
+    /// Extract the link to download or erase a subscriber's data.
+    pub fn get_data_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let message: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let (_, plain_text) = self.get_links(&message, "/subscriptions/data/manage");
+        plain_text
+    }
+
+    /// Extract the one link to `path` in each body of a message sent to the email API.
+    fn get_links(&self, message: &serde_json::Value, path: &str) -> (reqwest::Url, reqwest::Url) {
         // Extract the link from one of the request fields.
         let get_link = |s: &str| {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| reqwest::Url::parse(l.as_str()).is_ok_and(|url| url.path() == path))
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
-            let mut confirmation_link = reqwest::Url::parse(&raw_link).unwrap();
+            let mut link = reqwest::Url::parse(&raw_link).unwrap();
             // Let's make sure we don't call random APIs on the web
-            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
-            confirmation_link.set_port(Some(self.port)).unwrap();
-            confirmation_link
+            assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+            link.set_port(Some(self.port)).unwrap();
+            link
         };
 
-        let html = get_link(body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(body["TextBody"].as_str().unwrap());
-        ConfirmationLinks { html, plain_text }
+        let html = get_link(message["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(message["TextBody"].as_str().unwrap());
+        (html, plain_text)
+    }
+
+    /// Every message the delivery worker handed to Postmark's batch endpoint, oldest first.
+    pub async fn received_newsletter_emails(&self) -> Vec<serde_json::Value> {
+        self.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.url.path() == "/email/batch")
+            .flat_map(|r| r.body_json::<Vec<serde_json::Value>>().unwrap())
+            .collect()
+    }
+
+    /// Wait for the background worker to drain the delivery queue.
+    ///
+    /// Dead-lettered deliveries are left alone.
+    pub async fn dispatch_all_pending_emails(&self) {
+        for _ in 0..100 {
+            let pending = sqlx::query!(
+                "SELECT COUNT(*) AS count FROM issue_delivery_queue WHERE status = 'pending'"
+            )
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap();
+            if pending.count == 0 {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("The delivery worker did not drain the queue in time.");
+    }
+
+    pub async fn post_sync_blog(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/blog/sync", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Write a post with `frontmatter` to the blog sources, and with `built`
+    /// to the built site at `slug` as well.
+    pub fn write_blog_post(&self, file_name: &str, slug: &str, frontmatter: &str, built: bool) {
+        fs::write(
+            format!("{}/{}", self.blog.content_directory, file_name),
+            format!("---\n{}\n---\n# The post\n", frontmatter),
+        )
+        .unwrap();
+        if built {
+            let page_directory = format!("{}/{}", self.blog.dist_directory, slug);
+            fs::create_dir_all(&page_directory).unwrap();
+            fs::write(
+                format!("{}/index.html", page_directory),
+                r#"<main><article><figure class="card-image"><img src="/_astro/hero.Bx0Zcyzv.webp" alt width="1020" height="510"></figure></article></main>"#,
+            )
+            .unwrap();
+        }
     }
 
     pub async fn cleanup_test_db(&self) -> Result<(), sqlx::Error> {
         remove_file(&format!("{}.db", self.db_path)).await?;
+        let _ = fs::remove_dir_all(format!("{}-blog", self.db_path));
         Ok(())
     }
 }
@@ -192,7 +648,7 @@ pub async fn spawn_app() -> TestApp {
     let configuration = {
         let mut configuration = get_configuration().expect("Failed to read configuration");
         configuration.application.port = 0;
-        configuration.database.database_path = format!("scripts/a_place_for_test_dbs_to_spawn_in_it,supposed_to_be_empty_cuz_tests_terminate_after_success_execution/{}", Uuid::new_v4().to_string());
+        configuration.database.database_path = format!("scripts/a_place_for_test_dbs_to_spawn_in_it,supposed_to_be_empty_cuz_tests_terminate_after_success_execution/{}", Uuid::new_v4());
         configuration.database.create_if_missing = true;
         configuration.database.journal_mode = "MEMORY".to_string();
         configuration.database.synchronous = "OFF".to_string();
@@ -203,7 +659,16 @@ pub async fn spawn_app() -> TestApp {
         configuration.database.cache_size = "-10000".to_string();
         configuration.database.mmap_size = "0".to_string();
         configuration.database.temp_store = "MEMORY".to_string();
+        configuration.email_client.kind = EmailTransportKind::Postmark;
         configuration.email_client.base_url = email_server.uri();
+        // retry failed deliveries right away
+        configuration.delivery.backoff_base_milliseconds = 10;
+        configuration.delivery.backoff_cap_milliseconds = 50;
+        let blog_directory = format!("{}-blog", configuration.database.database_path);
+        configuration.blog.content_directory = format!("{}/content", blog_directory);
+        configuration.blog.dist_directory = format!("{}/dist", blog_directory);
+        fs::create_dir_all(&configuration.blog.content_directory)
+            .expect("Failed to create the blog directory");
         configuration
     };
 
@@ -241,6 +706,7 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
+        blog: configuration.blog,
     };
 
     test_app.test_user.store(&db_pool).await;
@@ -300,6 +766,121 @@ impl TestUser {
     }
 }
 
+/// Answers Postmark batch calls the way Postmark does: one result per message,
+/// failing the ones sent to `rejected` as inactive recipients.
+pub struct PostmarkBatchResponder {
+    rejected: Vec<String>,
+    delay: std::time::Duration,
+}
+
+impl PostmarkBatchResponder {
+    pub fn accepting_all() -> Self {
+        Self {
+            rejected: vec![],
+            delay: std::time::Duration::ZERO,
+        }
+    }
+
+    pub fn rejecting(email: &str) -> Self {
+        Self {
+            rejected: vec![email.to_owned()],
+            ..Self::accepting_all()
+        }
+    }
+
+    pub fn with_delay(self, delay: std::time::Duration) -> Self {
+        Self { delay, ..self }
+    }
+}
+
+impl Respond for PostmarkBatchResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> = request.body_json().unwrap();
+        let results = messages
+            .iter()
+            .map(|message| {
+                if self.rejected.iter().any(|r| message["To"] == r.as_str()) {
+                    serde_json::json!({
+                        "ErrorCode": 406,
+                        "Message": "You tried to send to a recipient that has been marked as inactive."
+                    })
+                } else {
+                    serde_json::json!({ "ErrorCode": 0, "Message": "OK" })
+                }
+            })
+            .collect::<Vec<_>>();
+        ResponseTemplate::new(200)
+            .set_body_json(results)
+            .set_delay(self.delay)
+    }
+}
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with_email(app, SafeEmail().fake()).await
+}
+
+pub async fn create_unconfirmed_subscriber_with_email(
+    app: &TestApp,
+    email: String,
+) -> ConfirmationLinks {
+    let body = FormData {
+        name: Some(Name().fake()),
+        email: Some(email),
+    };
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(&body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    create_confirmed_subscriber_with_email(app, SafeEmail().fake()).await
+}
+
+pub async fn create_confirmed_subscriber_with_email(app: &TestApp, email: String) {
+    let confirmation_link = create_unconfirmed_subscriber_with_email(app, email)
+        .await
+        .html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Save a draft and return its id, taken from the redirect to its page.
+pub async fn create_draft(app: &TestApp, body: &serde_json::Value) -> String {
+    let response = app.post_create_draft(body).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .to_owned()
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
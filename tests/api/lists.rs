@@ -0,0 +1,322 @@
+use fake::faker::name::en::Name;
+use fake::Fake;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, spawn_app,
+    PostmarkBatchResponder, TestApp,
+};
+
+/// Sign `email` up for the list with `slug` and follow the confirmation link.
+async fn join_list(app: &TestApp, email: &str, slug: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let name: String = Name().fake();
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[("name", name.as_str()), ("email", email), ("list", slug)])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Publish an issue to `lists`, the form sends one `lists` field per ticked box.
+async fn publish_to_lists(app: &TestApp, lists: &[&str]) -> reqwest::Response {
+    let mut body = format!(
+        "title=Issue&text_content=Plain&html_content=%3Cp%3EHTML%3C%2Fp%3E&idempotency_key={}",
+        Uuid::new_v4()
+    );
+    for list in lists {
+        body.push_str(&format!("&lists={}", list));
+    }
+    app.api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn list_status(app: &TestApp, email: &str, slug: &str) -> String {
+    sqlx::query!(
+        r#"
+        SELECT ls.status
+        FROM list_subscriptions ls
+        JOIN subscriptions s ON s.uuid = ls.subscriber_id
+        JOIN lists l ON l.uuid = ls.list_id
+        WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        slug,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+#[tokio::test]
+async fn an_admin_can_create_a_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create the list
+    let response = app
+        .post_create_list("announcements", "Product announcements")
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("The list announcements has been created."));
+    assert!(html_page.contains("Product announcements"));
+
+    // Act - Part 3 - The slug is taken now
+    app.post_create_list("announcements", "Again").await;
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("There already is a list called announcements."));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_list_with_an_invalid_slug_is_not_created() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_create_list("Product News", "Product announcements")
+        .await;
+
+    // Assert
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("Product News is not a valid list slug."));
+    let n_lists = sqlx::query!("SELECT COUNT(*) AS count FROM lists")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    // only the default one
+    assert_eq!(n_lists, 1);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_returns_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[
+            ("name", "Ursula"),
+            ("email", "ursula@example.com"),
+            ("list", "no-such-list"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_confirmed_subscriber_can_join_another_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("announcements", "Product announcements")
+        .await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+
+    // Act
+    join_list(&app, "ursula@example.com", "announcements").await;
+
+    // Assert
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "newsletter").await,
+        "confirmed"
+    );
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "announcements").await,
+        "confirmed"
+    );
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_issue_for_several_lists_reaches_everyone_on_them_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("announcements", "Product announcements")
+        .await;
+    app.post_create_list("jobs", "Job offers").await;
+    // on both lists
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    join_list(&app, "ursula@example.com", "announcements").await;
+    // on one of them
+    join_list(&app, "jo@example.com", "announcements").await;
+    // on neither
+    join_list(&app, "le-guin@example.com", "jobs").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = publish_to_lists(&app, &["newsletter", "announcements"]).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let mut recipients = app
+        .received_newsletter_emails()
+        .await
+        .iter()
+        .map(|message| message["To"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    recipients.sort();
+    assert_eq!(recipients, ["jo@example.com", "ursula@example.com"]);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_returns_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = publish_to_lists(&app, &["no-such-list"]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_of_an_issue_only_leaves_its_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("announcements", "Product announcements")
+        .await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    join_list(&app, "ursula@example.com", "announcements").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+    publish_to_lists(&app, &["announcements"]).await;
+    app.dispatch_all_pending_emails().await;
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    let links = app.get_unsubscribe_links(&message);
+
+    // Act - Part 1 - Follow the link
+    let html_page = reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("You will stop receiving Product announcements."));
+
+    // Act - Part 2 - Confirm
+    reqwest::Client::new()
+        .post(links.html)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "announcements").await,
+        "unsubscribed"
+    );
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "newsletter").await,
+        "confirmed"
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_tampered_list_in_an_unsubscribe_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("announcements", "Product announcements")
+        .await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    join_list(&app, "ursula@example.com", "announcements").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+    publish_to_lists(&app, &["announcements"]).await;
+    app.dispatch_all_pending_emails().await;
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    let mut link = app.get_unsubscribe_links(&message).html;
+    let query = link
+        .query()
+        .unwrap()
+        .replace("list=announcements", "list=newsletter");
+    link.set_query(Some(&query));
+
+    // Act
+    let response = reqwest::Client::new().post(link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        list_status(&app, "ursula@example.com", "newsletter").await,
+        "confirmed"
+    );
+
+    app.cleanup_test_db().await.unwrap();
+}
@@ -1,8 +1,22 @@
 mod admin_dashboard;
+mod admin_subscribers;
+mod admin_subscribers_csv;
+mod archive;
+mod blog_posts;
 mod change_password;
+mod custom_fields;
+mod digest;
+mod drafts;
+mod feed;
 mod health_check;
 mod helpers;
+mod lists;
 mod login;
 mod newsletter;
+mod preferences;
+mod scheduling;
+mod segments;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data;
+mod unsubscribe;
@@ -1,84 +1,466 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, FormData, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_confirmed_subscriber_with_email,
+    create_unconfirmed_subscriber, spawn_app, PostmarkBatchResponder,
+};
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+use std::time::Duration;
+use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = FormData {
-        name: Some("abood".to_string()),
-        email: Some("3la_el_7doood@yahoo.com".to_string()),
-    };
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
 
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
+    Mock::given(any())
         .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
-    app.post_subscriptions(&body)
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn merge_tags_are_filled_in_for_each_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    sqlx::query!("UPDATE subscriptions SET name = 'Ursula <Le Guin>'")
+        .execute(&app.db_pool)
         .await
-        .error_for_status()
         .unwrap();
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name }}, this is {{ list_name }} for {{email}}. Leave: {{ unsubscribe_url }}",
+        "html_content": "<p>Hi {{ name }}, this is {{ list_name }} for {{email}}.</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
 
-    let email_request = &app
-        .email_server
-        .received_requests()
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    let text_body = message["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with(
+        "Hi Ursula <Le Guin>, this is Newzletter for ursula@example.com. Leave: http"
+    ));
+    assert_eq!(text_body.matches("/subscriptions/unsubscribe?").count(), 2);
+    assert!(message["HtmlBody"].as_str().unwrap().starts_with(
+        "<p>Hi Ursula &lt;Le Guin&gt;, this is Newzletter for ursula@example.com.</p>"
+    ));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn an_issue_with_an_unknown_merge_tag_is_rejected_before_anything_is_queued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name }}",
+        "html_content": "<p>Hi {{ first_name }}</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(
+        html_page.contains("In the HTML content: There is no merge tag called `{{ first_name }}`")
+    );
+
+    // Assert
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
         .await
         .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(email_request)
+        .count;
+    assert_eq!(n_issues, 0);
+    app.dispatch_all_pending_emails().await;
+
+    app.cleanup_test_db().await.unwrap()
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
-    reqwest::get(confirmation_link)
+#[tokio::test]
+async fn an_issue_written_in_markdown_goes_out_as_styled_html_and_footnoted_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    sqlx::query!("UPDATE subscriptions SET name = 'Ursula'")
+        .execute(&app.db_pool)
         .await
-        .unwrap()
-        .error_for_status()
         .unwrap();
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "format": "markdown",
+        "markdown_content": "Hi **{{ name }}**, read [the post](https://example.com/post).\n\n<script>alert(1)</script>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    let html_body = message["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(
+        r#"<p style="margin: 0 0 16px;">Hi <strong>Ursula</strong>, read <a href="https://example.com/post" style="color: #2563eb; text-decoration: underline;">the post</a>.</p>"#
+    ));
+    assert!(!html_body.contains("<script>"));
+    let text_body = message["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with(
+        "Hi Ursula, read the post [1].\n\n<script>alert(1)</script>\n\n[1] https://example.com/post"
+    ));
+
+    app.cleanup_test_db().await.unwrap()
 }
 
 #[tokio::test]
-async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+async fn an_issue_in_markdown_mode_needs_a_markdown_body() {
     // Arrange
     let app = spawn_app().await;
-    create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
-
     Mock::given(any())
         .respond_with(ResponseTemplate::new(200))
         .expect(0)
         .mount(&app.email_server)
         .await;
 
-    // Act - Part 1 - Submit newsletter form
+    // Act - Part 1 - Submit newsletter form, the hand-written parts don't count
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
+        "format": "markdown",
+        "markdown_content": " ",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
     });
     let response = app.post_publish_newsletter(&newsletter_request_body).await;
     assert_is_redirect_to(&response, "/admin/newsletters");
 
     // Act - Part 2 - Follow the redirect
     let html_page = app.get_publish_newsletter_html().await;
-    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
-    // Mock verifies on Drop that we haven't sent the newsletter email
+    assert!(html_page.contains("<p><i>Write the issue in the markdown content.</i></p>"));
+
+    // Assert
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+    app.dispatch_all_pending_emails().await;
 
     app.cleanup_test_db().await.unwrap()
 }
 
 #[tokio::test]
-async fn newsletters_are_delivered_to_confirmed_subscribers() {
+async fn a_failed_delivery_does_not_block_the_other_subscribers() {
     // Arrange
     let app = spawn_app().await;
+    let unlucky_email: String = SafeEmail().fake();
+    create_confirmed_subscriber_with_email(&app, unlucky_email.clone()).await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    // both go out in the same batch, only one of them bounces
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponder::rejecting(&unlucky_email))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let outcomes =
+        sqlx::query!("SELECT subscriber_email, outcome FROM issue_delivery_attempts ORDER BY id")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(outcomes.len(), 2);
+    for attempt in outcomes {
+        let expected = if attempt.subscriber_email == unlucky_email {
+            "permanent_failure"
+        } else {
+            "delivered"
+        };
+        assert_eq!(attempt.outcome, expected);
+    }
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_delivery_that_cannot_be_recorded_does_not_resend_the_rest_of_the_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    let unlucky_email: String = SafeEmail().fake();
+    create_confirmed_subscriber_with_email(&app, unlucky_email.clone()).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    // the first message of the batch can't be recorded as delivered
+    sqlx::query(&format!(
+        r#"
+        CREATE TRIGGER fail_to_record BEFORE INSERT ON issue_delivery_attempts
+        WHEN NEW.subscriber_email = '{}'
+        BEGIN SELECT RAISE(ABORT, 'disk I/O error'); END
+        "#,
+        unlucky_email
+    ))
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    let mut queued = Vec::new();
+    for _ in 0..100 {
+        queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+        if queued.len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Assert
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, unlucky_email);
+    let recipients = app
+        .received_newsletter_emails()
+        .await
+        .into_iter()
+        .map(|message| message["To"].as_str().unwrap().to_owned())
+        .filter(|recipient| *recipient != unlucky_email)
+        .collect::<Vec<_>>();
+    assert_eq!(recipients.len(), 2);
+    assert_ne!(recipients[0], recipients[1]);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn transient_delivery_failures_are_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let outcomes: Vec<String> =
+        sqlx::query_scalar!("SELECT outcome FROM issue_delivery_attempts ORDER BY id")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        outcomes,
+        ["transient_failure", "transient_failure", "delivered"]
+    );
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn permanent_delivery_failures_are_dead_lettered_and_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+    create_confirmed_subscriber_with_email(&app, email.clone()).await;
+    app.test_user.login(&app).await;
+
+    let rejected = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::rejecting(&email))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish an issue the provider refuses to deliver
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+    drop(rejected);
+
+    // Assert - Part 1 - The delivery shows up on the dashboard
+    let delivery = sqlx::query!(
+        "SELECT id, status FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "dead_lettered");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&email));
+
+    // Act - Part 2 - Requeue it once the recipient is fixed
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_requeue_delivery(&serde_json::json!({ "delivery_id": delivery.id }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("<p><i>The delivery has been requeued.</i></p>"));
+    assert!(!html_page.contains(&email));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -88,6 +470,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
+        // We expect the idempotency key as part of the
+        // form data, not as an header
+        "idempotency_key": Uuid::new_v4().to_string(),
     });
     let response = app.post_publish_newsletter(&newsletter_request_body).await;
     assert_is_redirect_to(&response, "/admin/newsletters");
@@ -95,7 +480,83 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Act - Part 2 - Follow the redirect
     let html_page = app.get_publish_newsletter_html().await;
     assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
-    // Mock verifies on Drop that we have sent the newsletter email
+
+    // Act - Part 3 - Submit newsletter form **again**
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 4 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn concurrent_form_submission_is_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        // Setting a long delay to keep the worker busy while the second
+        // request comes in
+        .respond_with(
+            PostmarkBatchResponder::accepting_all().with_delay(Duration::from_millis(500)),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Submit two newsletter forms concurrently
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response1 = app.post_publish_newsletter(&newsletter_request_body);
+    let response2 = app.post_publish_newsletter(&newsletter_request_body);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.headers().get("Location"),
+        response2.headers().get("Location")
+    );
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn publishing_requires_a_valid_idempotency_key() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": "",
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
 
     app.cleanup_test_db().await.unwrap()
 }
@@ -124,6 +585,7 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
     });
     let response = app.post_publish_newsletter(&newsletter_request_body).await;
 
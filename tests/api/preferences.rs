@@ -0,0 +1,489 @@
+use reqwest::StatusCode;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, spawn_app,
+    PostmarkBatchResponder, TestApp,
+};
+
+const EMAIL: &str = "ursula@example.com";
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+/// Publish an issue to a single confirmed subscriber and grab the link to
+/// their preferences page it came with.
+async fn receive_a_newsletter_issue(app: &TestApp) -> reqwest::Url {
+    create_confirmed_subscriber_with_email(app, EMAIL.to_owned()).await;
+    app.test_user.login(app).await;
+
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(app).await;
+
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    app.get_preferences_link(&message)
+}
+
+async fn post_preferences(
+    app: &TestApp,
+    link: &reqwest::Url,
+    form: &[(&str, &str)],
+) -> reqwest::Response {
+    app.api_client
+        .post(link.clone())
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_pause(app: &TestApp, link: &reqwest::Url, weeks: &str) -> reqwest::Response {
+    let mut pause_link = link.clone();
+    pause_link.set_path("/subscriptions/preferences/pause");
+    app.api_client
+        .post(pause_link)
+        .form(&[("weeks", weeks)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_preferences_html(app: &TestApp, link: &reqwest::Url) -> String {
+    app.api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+/// The page the handlers redirect back to, relative as they send it.
+fn page(link: &reqwest::Url) -> String {
+    format!("{}?{}", link.path(), link.query().unwrap())
+}
+
+#[tokio::test]
+async fn newsletter_issues_link_to_the_preferences_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Your preferences"));
+    assert!(html.contains("/subscriptions/unsubscribe?subscriber_id="));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn the_preferences_url_merge_tag_is_filled_in_with_the_preferences_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, EMAIL.to_owned()).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Pick your topics: {{ preferences_url }}",
+        "html_content": "<p><a href=\"{{ preferences_url }}\">Pick your topics</a></p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    let text = message["TextBody"].as_str().unwrap();
+    let link = text.lines().last().unwrap().rsplit(' ').next().unwrap();
+    assert!(link.contains("/subscriptions/preferences?subscriber_id="));
+    assert!(text.starts_with(&format!("Pick your topics: {}\n", link)));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_preferences_link_with_a_tampered_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut link = receive_a_newsletter_issue(&app).await;
+    let subscriber_id = link
+        .query_pairs()
+        .find(|(key, _)| key == "subscriber_id")
+        .unwrap()
+        .1
+        .into_owned();
+    link.set_query(Some(&format!(
+        "subscriber_id={}&token={}",
+        subscriber_id,
+        "0".repeat(64)
+    )));
+
+    // Act
+    let get_response = reqwest::get(link.clone()).await.unwrap();
+    let post_response = post_preferences(&app, &link, &[("name", "Mallory")]).await;
+
+    // Assert
+    assert_eq!(get_response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(post_response.status(), StatusCode::UNAUTHORIZED);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_delivery_and_language() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &link,
+        &[
+            ("name", "Ursula K. Le Guin"),
+            ("lists", "newsletter"),
+            ("delivery", "weekly_digest"),
+            ("language", "de"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &page(&link));
+    assert!(get_preferences_html(&app, &link)
+        .await
+        .contains("Your preferences have been saved."));
+    let saved = sqlx::query!(
+        "SELECT name, delivery_preference, language FROM subscriptions WHERE email = $1",
+        EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.delivery_preference, "weekly_digest");
+    assert_eq!(saved.language, "de");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn the_language_merge_tag_is_filled_in_with_the_chosen_language() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+    post_preferences(
+        &app,
+        &link,
+        &[
+            ("name", "Ursula"),
+            ("lists", "newsletter"),
+            ("delivery", "instant"),
+            ("language", "fr"),
+        ],
+    )
+    .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Read it in {{ language }}",
+        "html_content": "<p lang=\"{{ language }}\">Read it</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    assert!(message["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Read it in fr\n"));
+    assert!(message["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with(r#"<p lang="fr">Read it</p>"#));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+    let name_before = sqlx::query!("SELECT name FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .name;
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &link,
+        &[
+            ("name", "<script>"),
+            ("lists", "newsletter"),
+            ("language", "fr"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &page(&link));
+    assert!(get_preferences_html(&app, &link)
+        .await
+        .contains("is not a valid subscriber name."));
+    let saved = sqlx::query!(
+        "SELECT name, language FROM subscriptions WHERE email = $1",
+        EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, name_before);
+    assert_eq!(saved.language, "en");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribers_can_switch_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+    app.post_create_list("announcements", "Announcements").await;
+
+    // Act
+    post_preferences(
+        &app,
+        &link,
+        &[("name", "Ursula"), ("lists", "announcements")],
+    )
+    .await;
+
+    // Assert
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.uuid = ls.list_id
+        JOIN subscriptions s ON s.uuid = ls.subscriber_id
+        WHERE s.email = $1
+        ORDER BY l.id
+        "#,
+        EMAIL
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        lists,
+        vec![
+            ("newsletter".to_owned(), "unsubscribed".to_owned()),
+            ("announcements".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+    let consent_event = sqlx::query!(
+        r#"
+        SELECT e.event_type
+        FROM subscription_consent_events e
+        JOIN subscriptions s ON s.uuid = e.subscriber_id
+        WHERE s.email = $1
+        ORDER BY e.id DESC
+        LIMIT 1
+        "#,
+        EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(consent_event.event_type, "preferences");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn leaving_every_list_takes_unsubscribing() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+
+    // Act
+    let response = post_preferences(&app, &link, &[("name", "Ursula")]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &page(&link));
+    assert!(get_preferences_html(&app, &link)
+        .await
+        .contains("Pick at least one list, or unsubscribe from all of them."));
+    let status = sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_paused_subscriber_gets_no_issues_until_they_resume() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+
+    // Act - Part 1 - Pause
+    let response = post_pause(&app, &link, "2").await;
+    assert_is_redirect_to(&response, &page(&link));
+    assert!(get_preferences_html(&app, &link)
+        .await
+        .contains("Resume now"));
+    publish_newsletter(&app).await;
+
+    // Assert - Part 1
+    assert_eq!(app.received_newsletter_emails().await.len(), 1);
+
+    // Act - Part 2 - Resume
+    post_pause(&app, &link, "0").await;
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+
+    // Assert - Part 2
+    assert_eq!(app.received_newsletter_emails().await.len(), 2);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn deliveries_queued_before_a_pause_are_not_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+    // a delivery of the same issue still waiting on a retry, held back until
+    // the subscriber has paused
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, execute_after)
+        SELECT uuid, $1, '9999-01-01 00:00:00 UTC' FROM newsletter_issues
+        "#,
+        EMAIL,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    post_pause(&app, &link, "2").await;
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = '1970-01-01 00:00:00 UTC'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    // wakes the worker up, the paused subscriber isn't queued for this one
+    publish_newsletter(&app).await;
+
+    // Assert
+    assert_eq!(app.received_newsletter_emails().await.len(), 1);
+    let queued = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn delivery_cannot_be_paused_for_longer_than_twelve_weeks() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+
+    // Act
+    let response = post_pause(&app, &link, "13").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &page(&link));
+    assert!(get_preferences_html(&app, &link)
+        .await
+        .contains("Delivery can be paused for up to 12 weeks."));
+    let paused_until = sqlx::query!(
+        "SELECT paused_until FROM subscriptions WHERE email = $1",
+        EMAIL
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .paused_until;
+    assert_eq!(paused_until, None);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_are_asked_to_sign_up_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = receive_a_newsletter_issue(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE email = $1",
+        EMAIL
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let html = get_preferences_html(&app, &link).await;
+
+    // Assert
+    assert!(html.contains("You are not subscribed"));
+    assert!(!html.contains("Save preferences"));
+
+    app.cleanup_test_db().await.unwrap();
+}
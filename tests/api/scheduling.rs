@@ -0,0 +1,409 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, create_draft, spawn_app,
+    PostmarkBatchResponder, TestApp,
+};
+use chrono::{Duration, Utc};
+use newzletter::drafts::get_due_drafts;
+use newzletter::scheduler::publish_due_drafts;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Monday issue",
+        "text_content": "Good morning {{ email }}",
+        "html_content": "<p>Good morning {{ email }}</p>",
+    })
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+/// Move the draft's send time into the past behind the scheduler's back, as
+/// if the process had been down when it fell due.
+async fn make_due(app: &TestApp, draft_id: &str) {
+    let scheduled_at = (Utc::now() - Duration::minutes(5)).to_string();
+    sqlx::query!(
+        "UPDATE newsletter_drafts SET scheduled_at = $1, schedule_timezone = 'UTC' WHERE uuid = $2",
+        scheduled_at,
+        draft_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn a_scheduled_draft_goes_out_when_it_is_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let scheduled_for = (Utc::now() + Duration::seconds(2))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+
+    // Act - Part 1 - Schedule it
+    let response = app
+        .post_schedule_draft(&draft_id, &scheduled_for, "UTC")
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("The draft goes out on"));
+    assert_eq!(n_issues(&app).await, 0);
+
+    // Act - Part 2 - Wait for it
+    for _ in 0..100 {
+        if n_issues(&app).await > 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(n_issues(&app).await, 1);
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    assert_eq!(message["Subject"], "Monday issue");
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+    // Mock verifies on Drop that we have sent the newsletter email once
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_send_time_is_picked_in_the_given_time_zone() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+
+    // Act
+    app.post_schedule_draft(&draft_id, "2999-06-16T09:00", "Asia/Tokyo")
+        .await;
+
+    // Assert
+    let draft = sqlx::query!(
+        "SELECT scheduled_at, schedule_timezone FROM newsletter_drafts WHERE uuid = $1",
+        draft_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        draft.scheduled_at.as_deref(),
+        Some("2999-06-16 00:00:00 UTC")
+    );
+    assert_eq!(draft.schedule_timezone.as_deref(), Some("Asia/Tokyo"));
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("Goes out on Sunday 2999-06-16 at 09:00 in"));
+    assert!(html_page.contains(r#"value="2999-06-16T09:00""#));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_send_time_in_the_past_or_an_unknown_time_zone_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    let test_cases = vec![
+        ("2000-01-01T09:00", "UTC", "Pick a time in the future."),
+        (
+            "2999-01-01T09:00",
+            "Mars/Olympus_Mons",
+            "is not a time zone",
+        ),
+        ("tomorrow", "UTC", "is not a date and time."),
+    ];
+
+    for (scheduled_for, timezone, error) in test_cases {
+        // Act
+        let response = app
+            .post_schedule_draft(&draft_id, scheduled_for, timezone)
+            .await;
+
+        // Assert
+        assert_is_redirect_to(
+            &response,
+            &format!("/admin/newsletters/drafts/{}", draft_id),
+        );
+        let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+        assert!(html_page.contains(error), "No `{}` on the page", error);
+        assert!(!html_page.contains("Goes out on"));
+    }
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_with_an_unknown_merge_tag_cannot_be_scheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(
+        &app,
+        &serde_json::json!({
+            "title": "Monday issue",
+            "text_content": "Good morning {{ first_name }}",
+            "html_content": "<p>Good morning</p>",
+        }),
+    )
+    .await;
+
+    // Act
+    app.post_schedule_draft(&draft_id, "2999-06-16T09:00", "UTC")
+        .await;
+
+    // Assert
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("first_name"));
+    assert!(!html_page.contains("Goes out on"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_schedule_can_be_moved_or_cancelled() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    app.post_schedule_draft(&draft_id, "2999-06-16T09:00", "UTC")
+        .await;
+
+    // Act - Part 1 - Reschedule
+    app.post_schedule_draft(&draft_id, "2999-06-23T10:30", "UTC")
+        .await;
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("Goes out on Sunday 2999-06-23 at 10:30 in"));
+
+    // Act - Part 2 - Cancel
+    let response = app.post_unschedule_draft(&draft_id).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/drafts/{}", draft_id),
+    );
+
+    // Assert
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("The draft is no longer scheduled."));
+    assert!(!html_page.contains("Goes out on"));
+    let draft = sqlx::query!(
+        "SELECT scheduled_at FROM newsletter_drafts WHERE uuid = $1",
+        draft_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(draft.scheduled_at, None);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_that_fell_due_is_published_exactly_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    make_due(&app, &draft_id).await;
+
+    // Act - several processes catching up at once, as after a restart
+    let (first, second, third) = tokio::join!(
+        publish_due_drafts(&app.db_pool),
+        publish_due_drafts(&app.db_pool),
+        publish_due_drafts(&app.db_pool),
+    );
+    let again = publish_due_drafts(&app.db_pool).await;
+
+    // Assert
+    let n_published = first.unwrap() + second.unwrap() + third.unwrap() + again.unwrap();
+    assert_eq!(n_published, 1);
+    assert_eq!(n_issues(&app).await, 1);
+    let deliveries = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.count, 1);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_cancelled_draft_is_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    app.post_schedule_draft(&draft_id, "2999-06-16T09:00", "UTC")
+        .await;
+    app.post_unschedule_draft(&draft_id).await;
+
+    // Act
+    let n_published = publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(n_published, 0);
+    assert_eq!(n_issues(&app).await, 0);
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 200);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn cancelling_a_draft_that_went_out_already_says_so() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    make_due(&app, &draft_id).await;
+    publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Act
+    let response = app.post_unschedule_draft(&draft_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    assert!(app
+        .get_drafts_html()
+        .await
+        .contains("The draft is gone, it may have been published already."));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_due_draft_that_no_longer_checks_out_stays_a_draft() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    make_due(&app, &draft_id).await;
+    // edited after it was scheduled
+    sqlx::query!(
+        "UPDATE newsletter_drafts SET text_content = 'Hi {{ first_name }}' WHERE uuid = $1",
+        draft_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let n_published = publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(n_published, 0);
+    assert_eq!(n_issues(&app).await, 0);
+    let html_page = app.get_draft(&draft_id).await.text().await.unwrap();
+    assert!(html_page.contains("It was not sent when scheduled"));
+    assert!(html_page.contains("first_name"));
+    assert!(!html_page.contains("Goes out on"));
+    // and it is not retried in a loop
+    assert_eq!(publish_due_drafts(&app.db_pool).await.unwrap(), 0);
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_that_fails_to_publish_does_not_hold_back_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    app.test_user.login(&app).await;
+    let broken_draft_id = create_draft(&app, &draft_body()).await;
+    make_due(&app, &broken_draft_id).await;
+    sqlx::query!(
+        "UPDATE newsletter_drafts SET format = 'unknown' WHERE uuid = $1",
+        broken_draft_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let draft_id = create_draft(&app, &draft_body()).await;
+    make_due(&app, &draft_id).await;
+
+    // Act
+    let n_published = publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(n_published, 1);
+    assert_eq!(n_issues(&app).await, 1);
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+    // and it is tried again later rather than straight away
+    let due = get_due_drafts(&app.db_pool, Utc::now()).await.unwrap();
+    assert!(due.is_empty());
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn a_draft_that_keeps_failing_to_publish_is_taken_off_the_schedule() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, &draft_body()).await;
+    make_due(&app, &draft_id).await;
+    sqlx::query!(
+        "UPDATE newsletter_drafts SET format = 'unknown', schedule_failures = 4 WHERE uuid = $1",
+        draft_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    publish_due_drafts(&app.db_pool).await.unwrap();
+
+    // Assert
+    let draft = sqlx::query!(
+        "SELECT scheduled_at, schedule_error FROM newsletter_drafts WHERE uuid = $1",
+        draft_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(draft.scheduled_at, None);
+    assert!(draft
+        .schedule_error
+        .unwrap()
+        .starts_with("Publishing it failed 5 times"));
+
+    app.cleanup_test_db().await.unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_schedule_a_draft() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let schedule = app
+        .post_schedule_draft("some-draft", "2999-06-16T09:00", "UTC")
+        .await;
+    let unschedule = app.post_unschedule_draft("some-draft").await;
+
+    // Assert
+    assert_is_redirect_to(&schedule, "/login");
+    assert_is_redirect_to(&unschedule, "/login");
+
+    app.cleanup_test_db().await.unwrap()
+}
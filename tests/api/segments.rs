@@ -0,0 +1,210 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_with_email, spawn_app,
+    PostmarkBatchResponder, TestApp,
+};
+
+async fn subscriber_id(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT uuid FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .uuid
+}
+
+async fn segment_id(app: &TestApp, name: &str) -> String {
+    sqlx::query!("SELECT uuid FROM segments WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .uuid
+}
+
+async fn recipient_count(app: &TestApp, query: &str) -> u64 {
+    let body: serde_json::Value = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/recipients?{}",
+            &app.address, query
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+    body["recipients"].as_u64().unwrap()
+}
+
+/// Three confirmed subscribers: ursula tagged rust, jo tagged rust and
+/// beta-tester, le-guin untagged.
+async fn tagged_subscribers(app: &TestApp) {
+    for email in [
+        "ursula@example.com",
+        "jo@example.com",
+        "le-guin@example.com",
+    ] {
+        create_confirmed_subscriber_with_email(app, email.into()).await;
+    }
+    let ursula = subscriber_id(app, "ursula@example.com").await;
+    let jo = subscriber_id(app, "jo@example.com").await;
+    app.post_add_subscriber_tag(&ursula, "rust").await;
+    app.post_add_subscriber_tag(&jo, "rust").await;
+    app.post_add_subscriber_tag(&jo, "beta-tester").await;
+}
+
+#[tokio::test]
+async fn an_admin_can_save_a_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create the segment
+    let response = app
+        .post_create_segment("Rustaceans", "tag:rust AND NOT tag:beta-tester")
+        .await;
+    assert_is_redirect_to(&response, "/admin/segments");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("The segment Rustaceans has been created."));
+    assert!(html_page.contains("tag:rust AND NOT tag:beta-tester"));
+
+    // Act - Part 3 - The name is taken now
+    app.post_create_segment("Rustaceans", "tag:rust").await;
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("There already is a segment called Rustaceans."));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_malformed_segment_is_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_create_segment("Broken", "(tag:rust OR").await;
+
+    // Assert
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("The segment ends where a condition was expected."));
+    let n_segments = sqlx::query!("SELECT COUNT(*) AS count FROM segments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_segments, 0);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn tags_show_up_on_the_subscriber_page() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    let ursula = subscriber_id(&app, "ursula@example.com").await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - A valid tag
+    let response = app.post_add_subscriber_tag(&ursula, "beta-tester").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", ursula));
+
+    // Act - Part 2 - An invalid one
+    app.post_add_subscriber_tag(&ursula, "Beta Tester").await;
+
+    // Assert
+    let html_page = app.get_subscriber(&ursula).await.text().await.unwrap();
+    assert!(html_page.contains("beta-tester"));
+    assert!(html_page.contains("Beta Tester is not a valid tag."));
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_issue_for_a_segment_only_reaches_matching_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    tagged_subscribers(&app).await;
+    app.post_create_segment("Rustaceans", "tag:rust AND NOT tag:beta-tester")
+        .await;
+    let segment = segment_id(&app, "Rustaceans").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Rust news",
+            "text_content": "Plain",
+            "html_content": "<p>HTML</p>",
+            "segment": segment,
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let recipients = app
+        .received_newsletter_emails()
+        .await
+        .iter()
+        .map(|message| message["To"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(recipients, ["ursula@example.com"]);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn the_recipient_count_follows_the_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    tagged_subscribers(&app).await;
+    app.post_create_segment("Rustaceans", "tag:rust").await;
+    let segment = segment_id(&app, "Rustaceans").await;
+
+    // Act
+    let everyone = recipient_count(&app, "lists=newsletter").await;
+    let rustaceans = recipient_count(&app, &format!("lists=newsletter&segment={}", segment)).await;
+
+    // Assert
+    assert_eq!(everyone, 3);
+    assert_eq!(rustaceans, 2);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_returns_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Rust news",
+            "text_content": "Plain",
+            "html_content": "<p>HTML</p>",
+            "segment": Uuid::new_v4().to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup_test_db().await.unwrap();
+}
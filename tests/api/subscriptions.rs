@@ -4,7 +4,10 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::{spawn_app, FormData};
+use crate::helpers::{
+    create_confirmed_subscriber_with_email, create_unconfirmed_subscriber_with_email, spawn_app,
+    FormData,
+};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -221,3 +224,212 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     app.cleanup_test_db().await.unwrap();
 }
+
+#[tokio::test]
+async fn subscribing_again_while_pending_resends_a_fresh_confirmation_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = FormData {
+        name: Some("abood".to_string()),
+        email: Some("3la_el_7doood@yahoo.com".to_string()),
+    };
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(&body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = app.post_subscriptions(&body).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+
+    // only the latest link works
+    let response = reqwest::get(first_link).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    reqwest::get(second_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_an_already_confirmed_email_sends_an_already_subscribed_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "3la_el_7doood@yahoo.com".to_string();
+    create_confirmed_subscriber_with_email(&app, email.clone()).await;
+    let body = FormData {
+        name: Some("abood".to_string()),
+        email: Some(email),
+    };
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(&body).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email_body: serde_json::Value = email_request.body_json().unwrap();
+    assert!(email_body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("you're already subscribed"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribe_responds_the_same_whatever_the_status_of_the_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmed_email = "confirmed@example.com".to_string();
+    create_confirmed_subscriber_with_email(&app, confirmed_email.clone()).await;
+    let pending_email = "pending@example.com".to_string();
+    create_unconfirmed_subscriber_with_email(&app, pending_email.clone()).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut responses = vec![];
+    for email in [
+        confirmed_email,
+        pending_email,
+        "new@example.com".to_string(),
+    ] {
+        let body = FormData {
+            name: Some("abood".to_string()),
+            email: Some(email),
+        };
+        let response = app.post_subscriptions(&body).await;
+        responses.push((response.status(), response.text().await.unwrap()));
+    }
+
+    // Assert
+    assert!(responses.iter().all(|r| r == &responses[0]));
+    assert_eq!(responses[0].0, StatusCode::OK);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "3la_el_7doood@yahoo.com".to_string();
+    create_confirmed_subscriber_with_email(&app, email.clone()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - Subscribe again
+    let confirmation_links = create_unconfirmed_subscriber_with_email(&app, email).await;
+
+    // Assert - Part 1
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+
+    // Act - Part 2 - Confirm
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert - Part 2
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscribe_records_how_and_where_the_signup_happened() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "Mozilla/5.0 (Test)")
+        .header("X-Forwarded-For", "203.0.113.7, 10.0.0.1")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", "homepage"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let event = sqlx::query!(
+        "SELECT event_type, ip_address, user_agent, source FROM subscription_consent_events"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(event.event_type, "signup");
+    // nothing in front of the test app, a forwarding header is just a claim
+    assert_eq!(event.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(event.user_agent.as_deref(), Some("Mozilla/5.0 (Test)"));
+    assert_eq!(event.source.as_deref(), Some("homepage"));
+
+    app.cleanup_test_db().await.unwrap();
+}
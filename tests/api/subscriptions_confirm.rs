@@ -1,10 +1,11 @@
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::{spawn_app, FormData};
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app, FormData};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -86,3 +87,81 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
 
     app.cleanup_test_db().await.unwrap();
 }
+
+#[tokio::test]
+async fn an_expired_confirmation_link_is_rejected_with_a_friendly_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = '2000-01-01 00:00:00 UTC'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert!(response.text().await.unwrap().contains("Link Expired"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn subscription_tokens_expire_after_a_while() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    create_unconfirmed_subscriber(&app).await;
+
+    // Assert
+    let token = sqlx::query!("SELECT created_at, expires_at FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let created_at: DateTime<Utc> = token.created_at.parse().unwrap();
+    let expires_at: DateTime<Utc> = token.expires_at.parse().unwrap();
+    assert!(expires_at > created_at);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_records_when_and_from_where() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    reqwest::Client::new()
+        .get(confirmation_links.html)
+        .header("User-Agent", "Mozilla/5.0 (Test)")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let events = sqlx::query!(
+        "SELECT event_type, ip_address, user_agent FROM subscription_consent_events ORDER BY id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event_type, "signup");
+    assert_eq!(events[1].event_type, "confirmation");
+    // no proxy in front of the test server, so we see the peer itself
+    assert_eq!(events[1].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(events[1].user_agent.as_deref(), Some("Mozilla/5.0 (Test)"));
+
+    app.cleanup_test_db().await.unwrap();
+}
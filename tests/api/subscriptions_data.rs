@@ -0,0 +1,201 @@
+use reqwest::{StatusCode, Url};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber_with_email, spawn_app, TestApp};
+
+/// Ask for a data link for `email` and return it.
+async fn request_data_link(app: &TestApp, email: &str) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_data_request(email).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_data_link(&email_request)
+}
+
+/// The same link pointing at another path, e.g. the export instead of the page.
+fn with_path(link: &Url, path: &str) -> Url {
+    let mut link = link.clone();
+    link.set_path(path);
+    link
+}
+
+#[tokio::test]
+async fn requesting_a_data_link_looks_the_same_whether_or_not_we_know_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let known = app.post_data_request("ursula@example.com").await;
+    let known_status = known.status();
+    let known_body = known.text().await.unwrap();
+    let unknown = app.post_data_request("nobody@example.com").await;
+
+    // Assert
+    assert_eq!(known_status, StatusCode::OK);
+    assert_eq!(unknown.status(), known_status);
+    assert_eq!(unknown.text().await.unwrap(), known_body);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn the_data_link_lets_the_subscriber_download_their_data() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    let link = request_data_link(&app, "ursula@example.com").await;
+
+    // Act - Part 1 - Follow the link
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("/subscriptions/data/export?"));
+
+    // Act - Part 2 - Download
+    let response = reqwest::get(with_path(&link, "/subscriptions/data/export"))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscriber"]["email"], "ursula@example.com");
+    assert_eq!(data["subscriber"]["status"], "confirmed");
+    let consent_events = data["consent_events"].as_array().unwrap();
+    assert_eq!(consent_events[0]["event_type"], "signup");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_tampered_data_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    let link = request_data_link(&app, "ursula@example.com").await;
+    let mut extended_link = link.clone();
+    let query = link
+        .query_pairs()
+        .map(|(key, value)| {
+            let value = if key == "expires" {
+                (value.parse::<i64>().unwrap() + 3600).to_string()
+            } else {
+                value.into_owned()
+            };
+            (key.into_owned(), value)
+        })
+        .collect::<Vec<_>>();
+    extended_link.query_pairs_mut().clear().extend_pairs(query);
+
+    // Act
+    let response = reqwest::get(with_path(&extended_link, "/subscriptions/data/export"))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn erasing_removes_everything_tied_to_the_address_but_a_tombstone() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    let link = request_data_link(&app, "ursula@example.com").await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(with_path(&link, "/subscriptions/data/erase"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    for table_count in [
+        sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap(),
+        sqlx::query_scalar!("SELECT COUNT(*) FROM subscription_tokens")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap(),
+        sqlx::query_scalar!("SELECT COUNT(*) FROM subscription_consent_events")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap(),
+    ] {
+        assert_eq!(table_count, 0);
+    }
+    let tombstone = sqlx::query!("SELECT email_hash FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!tombstone.email_hash.contains("ursula"));
+    // the link still works, there's just nothing left behind it
+    let response = reqwest::get(with_path(&link, "/subscriptions/data/export"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_erased_address_is_not_imported_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "ursula@example.com".into()).await;
+    let link = request_data_link(&app, "ursula@example.com").await;
+    reqwest::Client::new()
+        .post(with_path(&link, "/subscriptions/data/erase"))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_import_subscribers(
+        "confirmed",
+        "Exported from Mailchimp",
+        "name,email\nUrsula,Ursula@Example.com\n",
+    )
+    .await;
+
+    // Assert
+    let n_subscribers = sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 0);
+    let html_page = app.get_import_subscribers_html().await;
+    assert!(html_page.contains("Skipped 1 rows whose owner had their data erased."));
+
+    app.cleanup_test_db().await.unwrap();
+}
@@ -0,0 +1,223 @@
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+use reqwest::StatusCode;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+use crate::helpers::{
+    create_confirmed_subscriber_with_email, spawn_app, PostmarkBatchResponder, TestApp,
+    UnsubscribeLinks,
+};
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+/// Publish an issue to a single confirmed subscriber and grab the links it came with.
+async fn receive_a_newsletter_issue(app: &TestApp, email: &str) -> UnsubscribeLinks {
+    create_confirmed_subscriber_with_email(app, email.to_string()).await;
+    app.test_user.login(app).await;
+
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(app).await;
+
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    app.get_unsubscribe_links(&message)
+}
+
+async fn subscriber_status(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn newsletter_issues_carry_an_unsubscribe_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+
+    // Act
+    let links = receive_a_newsletter_issue(&app, &email).await;
+
+    // Assert
+    assert_eq!(links.html, links.plain_text);
+    assert_eq!(links.html.path(), "/subscriptions/unsubscribe");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn newsletter_issues_carry_one_click_unsubscribe_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+
+    // Act
+    let links = receive_a_newsletter_issue(&app, &email).await;
+
+    // Assert
+    let message = app.received_newsletter_emails().await.pop().unwrap();
+    let headers = message["Headers"].as_array().unwrap();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|h| h["Name"] == name)
+            .and_then(|h| h["Value"].as_str())
+            .unwrap()
+            .to_owned()
+    };
+    let list_unsubscribe = header("List-Unsubscribe");
+    assert!(list_unsubscribe.starts_with("<mailto:"));
+    // the helper points links at the test server's port, so compare what follows it
+    let query = links.html.query().unwrap();
+    assert!(list_unsubscribe.contains(&format!("/subscriptions/unsubscribe?{}>", query)));
+    assert_eq!(
+        header("List-Unsubscribe-Post"),
+        "List-Unsubscribe=One-Click"
+    );
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_one_click_unsubscribe_post_works_without_logging_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+    let links = receive_a_newsletter_issue(&app, &email).await;
+
+    // Act
+    // a fresh client, so no admin session cookie comes along
+    let response = reqwest::Client::new()
+        .post(links.html)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(subscriber_status(&app, &email).await, "unsubscribed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn following_the_unsubscribe_link_asks_for_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+    let links = receive_a_newsletter_issue(&app, &email).await;
+
+    // Act
+    let response = reqwest::get(links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains("Unsubscribe?"));
+    // A GET alone must not unsubscribe anyone, link scanners follow those
+    assert_eq!(subscriber_status(&app, &email).await, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn confirming_the_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+    let links = receive_a_newsletter_issue(&app, &email).await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(links.html)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("You have been unsubscribed"));
+    assert_eq!(subscriber_status(&app, &email).await, "unsubscribed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_unsubscribe_link_with_a_tampered_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+    let mut link = receive_a_newsletter_issue(&app, &email).await.html;
+    let subscriber_id = link
+        .query_pairs()
+        .find(|(k, _)| k == "subscriber_id")
+        .unwrap()
+        .1
+        .into_owned();
+    link.set_query(Some(&format!(
+        "subscriber_id={}&token={}",
+        subscriber_id,
+        "ab".repeat(32)
+    )));
+
+    // Act
+    let response = reqwest::Client::new().post(link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(subscriber_status(&app, &email).await, "confirmed");
+
+    app.cleanup_test_db().await.unwrap();
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_later_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let email: String = SafeEmail().fake();
+    let links = receive_a_newsletter_issue(&app, &email).await;
+    reqwest::Client::new()
+        .post(links.html)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder::accepting_all())
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+
+    // Assert
+    // Mock verifies on Drop that we haven't sent the newsletter email
+
+    app.cleanup_test_db().await.unwrap();
+}